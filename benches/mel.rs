@@ -0,0 +1,126 @@
+/*
+Benchmarks the log-mel frontend on a batch of utterances
+
+Compares the previous per-call STFT (new FFT planner and filterbank on every call,
+a fresh buffer per frame, dense filterbank product) against one cached MelFrontend
+reused across files. Prints the best of several runs.
+
+Usage:
+cargo bench --bench mel
+cargo bench --bench mel --features rayon   # frames computed in parallel
+
+The bench does not touch ONNX Runtime; to build it without downloading the ort binaries:
+cargo bench --bench mel --no-default-features --features cpu,load-dynamic
+*/
+
+use ndarray::Array2;
+use parakeet_rs::{MelConfig, MelFrontend, PreprocessorConfig};
+use rustfft::{num_complex::Complex, FftPlanner};
+use std::f32::consts::PI;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const FILES: usize = 20;
+const SECONDS: usize = 10;
+const RUNS: usize = 5;
+
+fn utterance(seed: usize) -> Vec<f32> {
+    (0..16000 * SECONDS)
+        .map(|i| {
+            let t = i as f32 / 16000.0;
+            let f0 = 120.0 + 10.0 * seed as f32;
+            0.3 * (2.0 * PI * f0 * t).sin() * (2.0 * PI * 4.0 * t).sin().abs()
+                + 0.02 * (2.0 * PI * 2750.0 * t).cos()
+        })
+        .collect()
+}
+
+// The STFT + mel path as it was before the frontend was cached
+fn per_call_stft(audio: &[f32], config: &MelConfig) -> Array2<f32> {
+    let filterbank = MelFrontend::new(config.clone()).filterbank().clone();
+    let (n_fft, win, hop) = (config.n_fft, config.win_length, config.hop_length);
+
+    let mut signal = Vec::with_capacity(audio.len());
+    signal.push(audio[0]);
+    for i in 1..audio.len() {
+        signal.push(audio[i] - config.preemphasis * audio[i - 1]);
+    }
+
+    let window: Vec<f32> = (0..win)
+        .map(|i| 0.5 - 0.5 * ((2.0 * PI * i as f32) / (win as f32 - 1.0)).cos())
+        .collect();
+    let num_frames = (signal.len() - win) / hop + 1;
+    let mut spectrogram = Array2::<f32>::zeros((n_fft / 2 + 1, num_frames));
+
+    let mut planner = FftPlanner::<f32>::new();
+    let fft = planner.plan_fft_forward(n_fft);
+    for t in 0..num_frames {
+        let start = t * hop;
+        let mut frame = vec![Complex::new(0.0f32, 0.0); n_fft];
+        for i in 0..win {
+            frame[i] = Complex::new(signal[start + i] * window[i], 0.0);
+        }
+        fft.process(&mut frame);
+        for k in 0..n_fft / 2 + 1 {
+            spectrogram[[k, t]] = frame[k].norm_sqr();
+        }
+    }
+
+    filterbank
+        .dot(&spectrogram)
+        .mapv(|x| x.max(1e-10).ln())
+        .t()
+        .to_owned()
+}
+
+fn best_of(label: &str, files: &[Vec<f32>], mut f: impl FnMut(&[f32])) -> Duration {
+    f(&files[0]); // warm up
+
+    let best = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            for audio in files {
+                f(audio);
+            }
+            start.elapsed()
+        })
+        .min()
+        .unwrap();
+    println!(
+        "{label:<24} {:>8.2} ms/file ({} x {SECONDS}s)",
+        best.as_secs_f64() * 1000.0 / files.len() as f64,
+        files.len()
+    );
+    best
+}
+
+fn main() {
+    let files: Vec<Vec<f32>> = (0..FILES).map(utterance).collect();
+
+    for n_mels in [80, 128] {
+        let config = MelConfig::parakeet(&PreprocessorConfig {
+            feature_size: n_mels,
+            ..Default::default()
+        });
+        println!("{n_mels} mels");
+
+        let before = best_of("  per-call STFT", &files, |audio| {
+            black_box(per_call_stft(audio, &config));
+        });
+
+        let frontend = MelFrontend::new(config.clone());
+        let after = best_of("  cached MelFrontend", &files, |audio| {
+            black_box(frontend.compute(audio));
+        });
+
+        println!(
+            "  speedup: {:.2}x{}",
+            before.as_secs_f64() / after.as_secs_f64(),
+            if cfg!(feature = "rayon") {
+                " (rayon)"
+            } else {
+                ""
+            }
+        );
+    }
+}
@@ -25,8 +25,6 @@ run Sortformer on the full audio for diarization, then chunk the audio into
 ~5-minute segments for TDT transcription, and map the results back together.
 */
 
-#[cfg(feature = "sortformer")]
-use hound;
 #[cfg(feature = "sortformer")]
 use parakeet_rs::sortformer::{DiarizationConfig, Sortformer};
 #[cfg(feature = "sortformer")]
@@ -0,0 +1,300 @@
+//! CTC forced alignment of a known transcript.
+//!
+//! The transcript's tokens are interleaved with blanks and the most probable path
+//! through the CTC log-probabilities is found with Viterbi, the same topology
+//! `torchaudio.functional.forced_align` uses. Long input is aligned segment by
+//! segment: each step aligns the next stretch of frames against the remaining
+//! transcript with an open end, keeps the tokens that end well before the segment
+//! boundary and continues from there, so memory stays bounded for hours of audio.
+
+use crate::decoder::TimedToken;
+use crate::error::{Error, Result};
+use ndarray::{s, Array2, ArrayView2};
+
+/// Result of [`crate::Parakeet::align`]
+#[derive(Debug, Clone, Default)]
+pub struct Alignment {
+    /// Token pieces of the normalized transcript
+    pub tokens: Vec<TimedToken>,
+    /// Words as written in the transcript (case and punctuation kept)
+    pub words: Vec<TimedToken>,
+    /// Words grouped at `.`, `?` and `!`
+    pub sentences: Vec<TimedToken>,
+    /// Score of each entry of `words`: the mean log-probability of its tokens over the
+    /// frames they were aligned to. Close to 0 is a clean match; strongly negative
+    /// values point at words that were not spoken or are transcribed wrongly.
+    pub word_scores: Vec<f32>,
+}
+
+/// Frames of one aligned token, end exclusive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct TokenSpan {
+    pub start: usize,
+    pub end: usize,
+    /// Sum of the token's log-probabilities over its frames
+    pub log_prob: f32,
+}
+
+impl TokenSpan {
+    fn offset(self, frames: usize) -> Self {
+        Self {
+            start: self.start + frames,
+            end: self.end + frames,
+            ..self
+        }
+    }
+}
+
+/// Most probable alignment of `tokens` to `log_probs` (`(frames, vocab)`).
+///
+/// With `open_end` the path may stop at any token, and only the tokens it reached
+/// are returned. `None` if the tokens cannot fit into the frames.
+pub(crate) fn viterbi(
+    log_probs: ArrayView2<f32>,
+    tokens: &[u32],
+    blank: usize,
+    open_end: bool,
+) -> Option<Vec<TokenSpan>> {
+    let frames = log_probs.nrows();
+    let states = 2 * tokens.len() + 1;
+    if frames == 0 {
+        return None;
+    }
+    // Even states are blanks, odd state 2k + 1 is token k
+    let label = |state: usize| match state % 2 {
+        0 => blank,
+        _ => tokens[state / 2] as usize,
+    };
+    // Skipping the blank between two tokens is only allowed if they differ
+    let can_skip =
+        |state: usize| state % 2 == 1 && state >= 3 && tokens[state / 2] != tokens[state / 2 - 1];
+
+    let mut score = vec![f32::NEG_INFINITY; states];
+    score[0] = log_probs[[0, blank]];
+    if states > 1 {
+        score[1] = log_probs[[0, label(1)]];
+    }
+    // Step taken into each state: 0 stay, 1 from the previous state, 2 skip a blank
+    let mut back = Array2::<u8>::zeros((frames, states));
+    let mut next = vec![f32::NEG_INFINITY; states];
+
+    for t in 1..frames {
+        for state in 0..states {
+            let mut best = (score[state], 0);
+            if state >= 1 && score[state - 1] > best.0 {
+                best = (score[state - 1], 1);
+            }
+            if can_skip(state) && score[state - 2] > best.0 {
+                best = (score[state - 2], 2);
+            }
+            next[state] = best.0 + log_probs[[t, label(state)]];
+            back[[t, state]] = best.1;
+        }
+        std::mem::swap(&mut score, &mut next);
+    }
+
+    let finals = if open_end {
+        0..states
+    } else {
+        states.saturating_sub(2)..states
+    };
+    let (mut state, best) = finals
+        .map(|state| (state, score[state]))
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+    if best == f32::NEG_INFINITY {
+        return None;
+    }
+
+    let mut path = vec![0; frames];
+    for t in (0..frames).rev() {
+        path[t] = state;
+        state -= back[[t, state]] as usize;
+    }
+
+    let mut spans: Vec<TokenSpan> = Vec::new();
+    let mut current = None;
+    for (t, &state) in path.iter().enumerate() {
+        if state % 2 == 0 {
+            continue;
+        }
+        let lp = log_probs[[t, label(state)]];
+        if current == Some(state) {
+            let span = spans.last_mut().unwrap();
+            span.end = t + 1;
+            span.log_prob += lp;
+        } else {
+            spans.push(TokenSpan {
+                start: t,
+                end: t + 1,
+                log_prob: lp,
+            });
+            current = Some(state);
+        }
+    }
+    Some(spans)
+}
+
+/// Align `tokens` to all of `log_probs`, `segment` frames at a time.
+pub(crate) fn segmental_align(
+    log_probs: &Array2<f32>,
+    tokens: &[u32],
+    blank: usize,
+    segment: usize,
+) -> Result<Vec<TokenSpan>> {
+    let total = log_probs.nrows();
+    // Tokens ending in the last quarter of a segment may still move; align them again
+    let keep = (segment * 3 / 4).max(1);
+    let mut spans = Vec::with_capacity(tokens.len());
+    let (mut frame, mut token) = (0, 0);
+
+    while token < tokens.len() {
+        let end = (frame + segment).min(total);
+        let last = end == total;
+        let remaining = &tokens[token..];
+        // A segment cannot hold more tokens than frames
+        let window = if last {
+            remaining
+        } else {
+            &remaining[..remaining.len().min(end - frame)]
+        };
+
+        let found = viterbi(log_probs.slice(s![frame..end, ..]), window, blank, !last).ok_or_else(
+            || {
+                Error::Alignment(format!(
+                    "{} tokens do not fit into the remaining {} frames",
+                    remaining.len(),
+                    end - frame
+                ))
+            },
+        )?;
+
+        if last {
+            spans.extend(found.into_iter().map(|span| span.offset(frame)));
+            break;
+        }
+
+        let kept: Vec<TokenSpan> = found
+            .into_iter()
+            .take_while(|span| span.end <= keep)
+            .collect();
+        match kept.last() {
+            Some(span) => {
+                let next = frame + span.end;
+                token += kept.len();
+                spans.extend(kept.into_iter().map(|span| span.offset(frame)));
+                frame = next;
+            }
+            // Nothing spoken early in the segment: move past it
+            None => frame += keep,
+        }
+    }
+    Ok(spans)
+}
+
+/// Normalize a transcript word for a lowercase, punctuation-free CTC vocabulary
+pub(crate) fn normalize_word(word: &str) -> String {
+    word.chars()
+        .filter(|c| c.is_alphanumeric() || *c == '\'')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Group aligned words into sentences ending in `.`, `?` or `!`
+pub(crate) fn group_sentences(words: &[TimedToken]) -> Vec<TimedToken> {
+    let mut sentences = Vec::new();
+    let mut current: Vec<&TimedToken> = Vec::new();
+    for (i, word) in words.iter().enumerate() {
+        current.push(word);
+        let ends_sentence = word
+            .text
+            .trim_end_matches(['"', '\'', ')', ']'])
+            .ends_with(['.', '?', '!']);
+        if ends_sentence || i + 1 == words.len() {
+            sentences.push(TimedToken {
+                text: current
+                    .iter()
+                    .map(|w| w.text.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                start: current[0].start,
+                end: word.end,
+                ..Default::default()
+            });
+            current.clear();
+        }
+    }
+    sentences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array;
+
+    // Log-probabilities with `best[t]` at 0.9 and the rest sharing 0.1
+    fn peaked(best: &[usize], vocab: usize) -> Array2<f32> {
+        Array::from_shape_fn((best.len(), vocab), |(t, v)| {
+            if v == best[t] {
+                0.9f32.ln()
+            } else {
+                (0.1 / (vocab - 1) as f32).ln()
+            }
+        })
+    }
+
+    #[test]
+    fn test_viterbi_spans() {
+        // blank = 3; "a a b" spoken as _ a a _ a b b _
+        let log_probs = peaked(&[3, 0, 0, 3, 0, 1, 1, 3], 4);
+        let spans = viterbi(log_probs.view(), &[0, 0, 1], 3, false).unwrap();
+        let frames: Vec<_> = spans.iter().map(|s| (s.start, s.end)).collect();
+        assert_eq!(frames, vec![(1, 3), (4, 5), (5, 7)]);
+        assert!((spans[0].log_prob - 2.0 * 0.9f32.ln()).abs() < 1e-5);
+
+        // Repeated tokens need a blank between them
+        assert!(viterbi(log_probs.slice(s![..2, ..]), &[0, 0], 3, false).is_none());
+        assert!(viterbi(log_probs.slice(s![..3, ..]), &[0, 0], 3, false).is_some());
+    }
+
+    #[test]
+    fn test_segmental_matches_full_alignment() {
+        // Tokens 0..4 repeating every 5 frames with silence around each
+        let mut best = Vec::new();
+        let mut tokens = Vec::new();
+        for i in 0..40 {
+            let token = i % 4;
+            best.extend([5, token, token, 5, 5]);
+            tokens.push(token as u32);
+        }
+        let log_probs = peaked(&best, 6);
+
+        let full = viterbi(log_probs.view(), &tokens, 5, false).unwrap();
+        let segmental = segmental_align(&log_probs, &tokens, 5, 16).unwrap();
+        assert_eq!(segmental.len(), tokens.len());
+        assert_eq!(full, segmental);
+
+        let too_many = vec![0; 150];
+        assert!(matches!(
+            segmental_align(&log_probs, &too_many, 5, 16),
+            Err(Error::Alignment(_))
+        ));
+    }
+
+    #[test]
+    fn test_normalize_and_sentences() {
+        assert_eq!(normalize_word("Don't,"), "don't");
+        assert_eq!(normalize_word("--"), "");
+
+        let word = |text: &str, start| TimedToken {
+            text: text.to_string(),
+            start,
+            end: start + 0.5,
+            ..Default::default()
+        };
+        let words = [word("Hi", 0.0), word("there.", 0.5), word("Bye", 1.0)];
+        let sentences = group_sentences(&words);
+        assert_eq!(sentences.len(), 2);
+        assert_eq!(sentences[0].text, "Hi there.");
+        assert_eq!((sentences[1].start, sentences[1].end), (1.0, 1.5));
+    }
+}
@@ -1,118 +1,734 @@
-use crate::config::PreprocessorConfig;
 use crate::error::{Error, Result};
-use hound::{WavReader, WavSpec};
+use crate::mel::MelFrontend;
+use crate::wav;
 use ndarray::Array2;
-use std::f32::consts::PI;
 use std::path::Path;
 
-pub fn load_audio<P: AsRef<Path>>(path: P) -> Result<(Vec<f32>, WavSpec)> {
-    let mut reader = WavReader::open(path)?;
-    let spec = reader.spec();
-
-    let samples: Vec<f32> = match spec.sample_format {
-        hound::SampleFormat::Float => reader
-            .samples::<f32>()
-            .collect::<std::result::Result<Vec<_>, _>>()
-            .map_err(|e| Error::Audio(format!("Failed to read float samples: {e}")))?,
-        hound::SampleFormat::Int => reader
-            .samples::<i16>()
-            .map(|s| s.map(|s| s as f32 / 32768.0))
-            .collect::<std::result::Result<Vec<_>, _>>()
-            .map_err(|e| Error::Audio(format!("Failed to read int samples: {e}")))?,
+/// Sample rate and channel layout of decoded audio, independent of the container format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioSpec {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+/// Load an audio file as interleaved f32 samples in [-1.0, 1.0].
+///
+/// WAV files are always supported. With the `symphonia` feature enabled, FLAC, MP3,
+/// Ogg Vorbis, AAC/ALAC in MP4/M4A and other formats are decoded too; the container
+/// is detected by probing the file contents, the extension is only used as a hint.
+/// Opus has no decoder and is rejected with [`Error::Audio`].
+pub fn load_audio<P: AsRef<Path>>(path: P) -> Result<(Vec<f32>, AudioSpec)> {
+    let path = path.as_ref();
+
+    if is_wav(path)? {
+        return load_wav(path);
+    }
+
+    #[cfg(feature = "symphonia")]
+    {
+        decode_with_symphonia(path)
+    }
+
+    #[cfg(not(feature = "symphonia"))]
+    {
+        Err(Error::Audio(format!(
+            "{} is not a WAV file. Enable the `symphonia` feature to decode compressed formats.",
+            path.display()
+        )))
+    }
+}
+
+// Check the RIFF/WAVE (or RF64) magic instead of trusting the extension
+fn is_wav(path: &Path) -> Result<bool> {
+    use std::io::Read;
+
+    let mut header = [0u8; 12];
+    let mut file = std::fs::File::open(path)?;
+    let mut read = 0;
+    while read < header.len() {
+        match file.read(&mut header[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+
+    Ok(read == header.len()
+        && (&header[0..4] == b"RIFF" || &header[0..4] == b"RF64")
+        && &header[8..12] == b"WAVE")
+}
+
+// WAV goes through our own reader: bit-depth aware PCM, float, G.711 and extensible headers
+fn load_wav(path: &Path) -> Result<(Vec<f32>, AudioSpec)> {
+    let bytes = std::fs::read(path)?;
+    wav::decode_wav(&bytes)
+}
+
+#[cfg(feature = "symphonia")]
+fn decode_with_symphonia(path: &Path) -> Result<(Vec<f32>, AudioSpec)> {
+    use symphonia::core::audio::SampleBuffer;
+    use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL, CODEC_TYPE_OPUS};
+    use symphonia::core::errors::Error as SymphoniaError;
+    use symphonia::core::formats::FormatOptions;
+    use symphonia::core::io::MediaSourceStream;
+    use symphonia::core::meta::MetadataOptions;
+    use symphonia::core::probe::Hint;
+
+    let file = std::fs::File::open(path)?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        stream,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    let mut format = probed.format;
+
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| Error::Audio(format!("No audio track found in {}", path.display())))?;
+    // The Ogg reader recognizes Opus streams, but symphonia has no Opus decoder
+    if track.codec_params.codec == CODEC_TYPE_OPUS {
+        return Err(Error::Audio(format!(
+            "{}: Opus audio is not supported, convert it to WAV or FLAC first",
+            path.display()
+        )));
+    }
+    let track_id = track.id;
+    let mut sample_rate = track.codec_params.sample_rate;
+    let mut channels = track.codec_params.channels.map(|c| c.count() as u16);
+
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    let mut samples = Vec::new();
+    let mut buffer: Option<SampleBuffer<f32>> = None;
+
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            // End of stream is reported as an unexpected EOF
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A corrupt packet only loses a few milliseconds, keep going
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        let spec = *decoded.spec();
+        sample_rate.get_or_insert(spec.rate);
+        channels.get_or_insert(spec.channels.count() as u16);
+
+        let buf = match buffer.as_mut() {
+            Some(buf) if buf.capacity() >= decoded.capacity() * spec.channels.count() => buf,
+            _ => buffer.insert(SampleBuffer::new(decoded.capacity() as u64, spec)),
+        };
+        buf.copy_interleaved_ref(decoded);
+        samples.extend_from_slice(buf.samples());
+    }
+
+    let spec = match (sample_rate, channels) {
+        (Some(sample_rate), Some(channels)) => AudioSpec {
+            sample_rate,
+            channels,
+        },
+        _ => {
+            return Err(Error::Audio(format!(
+                "Could not determine sample rate or channel count of {}",
+                path.display()
+            )))
+        }
     };
 
     Ok((samples, spec))
 }
 
-pub fn apply_preemphasis(audio: &[f32], coef: f32) -> Vec<f32> {
-    let mut result = Vec::with_capacity(audio.len());
-    result.push(audio[0]);
+/// Encoding of raw PCM samples in a byte buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// Unsigned 8-bit, 128 = silence
+    U8,
+    /// Signed 16-bit
+    S16,
+    /// Signed 24-bit packed in 3 bytes
+    S24,
+    /// Signed 32-bit
+    S32,
+    /// 32-bit IEEE float
+    F32,
+    /// 64-bit IEEE float
+    F64,
+    /// G.711 µ-law, 8 bits per sample
+    MuLaw,
+    /// G.711 A-law, 8 bits per sample
+    ALaw,
+}
 
-    for i in 1..audio.len() {
-        result.push(audio[i] - coef * audio[i - 1]);
+impl SampleFormat {
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            SampleFormat::U8 | SampleFormat::MuLaw | SampleFormat::ALaw => 1,
+            SampleFormat::S16 => 2,
+            SampleFormat::S24 => 3,
+            SampleFormat::S32 | SampleFormat::F32 => 4,
+            SampleFormat::F64 => 8,
+        }
     }
+}
 
-    result
+/// Byte order of multi-byte samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Endianness {
+    #[default]
+    Little,
+    Big,
 }
 
-fn hann_window(window_length: usize) -> Vec<f32> {
-    (0..window_length)
-        .map(|i| 0.5 - 0.5 * ((2.0 * PI * i as f32) / (window_length as f32 - 1.0)).cos())
-        .collect()
+/// How channels are arranged in the byte buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChannelLayout {
+    /// Frames of one sample per channel (L R L R ...)
+    #[default]
+    Interleaved,
+    /// All samples of channel 0, then all of channel 1, ...
+    Planar,
 }
 
-// We use proper FFT here instead of naive DFT because the model was trained
-// on correctly computed spectrograms. Naive DFT produces wrong frequency bins
-// and the model outputs all blank tokens. RustFFT gives us O(n log n) performance
-// and numerically correct results that match what the model expects.
-pub fn stft(audio: &[f32], n_fft: usize, hop_length: usize, win_length: usize) -> Array2<f32> {
-    use rustfft::{num_complex::Complex, FftPlanner};
+/// Out-of-band description of a raw PCM payload.
+///
+/// # Example
+/// ```
+/// use parakeet_rs::{Endianness, RawAudioFormat, SampleFormat};
+///
+/// // 8kHz stereo s16be
+/// let format = RawAudioFormat::new(SampleFormat::S16, 8000, 2)
+///     .with_endianness(Endianness::Big);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawAudioFormat {
+    pub sample_format: SampleFormat,
+    pub endianness: Endianness,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub layout: ChannelLayout,
+}
 
-    let window = hann_window(win_length);
-    let num_frames = (audio.len() - win_length) / hop_length + 1;
-    let freq_bins = n_fft / 2 + 1;
-    let mut spectrogram = Array2::<f32>::zeros((freq_bins, num_frames));
+impl RawAudioFormat {
+    /// Little-endian, interleaved format
+    pub fn new(sample_format: SampleFormat, sample_rate: u32, channels: u16) -> Self {
+        Self {
+            sample_format,
+            endianness: Endianness::default(),
+            sample_rate,
+            channels,
+            layout: ChannelLayout::default(),
+        }
+    }
 
-    let mut planner = FftPlanner::<f32>::new();
-    let fft = planner.plan_fft_forward(n_fft);
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
 
-    for frame_idx in 0..num_frames {
-        let start = frame_idx * hop_length;
+    pub fn with_layout(mut self, layout: ChannelLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+}
 
-        let mut frame: Vec<Complex<f32>> = vec![Complex::new(0.0, 0.0); n_fft];
-        for i in 0..win_length.min(audio.len() - start) {
-            frame[i] = Complex::new(audio[start + i] * window[i], 0.0);
+/// Convert a raw PCM byte buffer into interleaved f32 samples in [-1.0, 1.0].
+///
+/// The buffer must hold a whole number of frames (one sample for every channel).
+pub fn decode_raw_pcm(bytes: &[u8], format: &RawAudioFormat) -> Result<Vec<f32>> {
+    if format.channels == 0 || format.sample_rate == 0 {
+        return Err(Error::Audio(format!(
+            "Invalid raw audio format: {} channels at {} Hz",
+            format.channels, format.sample_rate
+        )));
+    }
+
+    let width = format.sample_format.bytes_per_sample();
+    let frame_bytes = width * format.channels as usize;
+    if !bytes.len().is_multiple_of(frame_bytes) {
+        return Err(Error::Audio(format!(
+            "Raw audio buffer of {} bytes is not a whole number of {}-byte frames ({:?} x {} channels)",
+            bytes.len(),
+            frame_bytes,
+            format.sample_format,
+            format.channels
+        )));
+    }
+
+    let big = format.endianness == Endianness::Big;
+    let decode = |b: &[u8]| -> f32 {
+        match format.sample_format {
+            SampleFormat::U8 => (b[0] as f32 - 128.0) / 128.0,
+            SampleFormat::MuLaw => wav::mulaw_to_f32(b[0]),
+            SampleFormat::ALaw => wav::alaw_to_f32(b[0]),
+            SampleFormat::S16 => {
+                let raw = [b[0], b[1]];
+                let v = if big {
+                    i16::from_be_bytes(raw)
+                } else {
+                    i16::from_le_bytes(raw)
+                };
+                v as f32 / 32768.0
+            }
+            SampleFormat::S24 => {
+                // Place the 3 bytes in the top of an i32 so the sign extends
+                let v = if big {
+                    i32::from_be_bytes([b[0], b[1], b[2], 0])
+                } else {
+                    i32::from_le_bytes([0, b[0], b[1], b[2]])
+                };
+                v as f32 / 2_147_483_648.0
+            }
+            SampleFormat::S32 => {
+                let raw = [b[0], b[1], b[2], b[3]];
+                let v = if big {
+                    i32::from_be_bytes(raw)
+                } else {
+                    i32::from_le_bytes(raw)
+                };
+                v as f32 / 2_147_483_648.0
+            }
+            SampleFormat::F32 => {
+                let raw = [b[0], b[1], b[2], b[3]];
+                if big {
+                    f32::from_be_bytes(raw)
+                } else {
+                    f32::from_le_bytes(raw)
+                }
+            }
+            SampleFormat::F64 => {
+                let raw: [u8; 8] = b.try_into().unwrap();
+                (if big {
+                    f64::from_be_bytes(raw)
+                } else {
+                    f64::from_le_bytes(raw)
+                }) as f32
+            }
         }
+    };
 
-        fft.process(&mut frame);
+    let samples: Vec<f32> = bytes.chunks_exact(width).map(decode).collect();
 
-        for k in 0..freq_bins {
-            let magnitude = frame[k].norm();
-            spectrogram[[k, frame_idx]] = magnitude * magnitude;
+    match format.layout {
+        ChannelLayout::Interleaved => Ok(samples),
+        ChannelLayout::Planar => {
+            let channels = format.channels as usize;
+            let frames = samples.len() / channels;
+            let mut interleaved = Vec::with_capacity(samples.len());
+            for frame in 0..frames {
+                for channel in 0..channels {
+                    interleaved.push(samples[channel * frames + frame]);
+                }
+            }
+            Ok(interleaved)
         }
     }
+}
 
-    spectrogram
+/// Average interleaved multichannel audio into mono.
+pub fn downmix(audio: &[f32], channels: u16) -> Vec<f32> {
+    if channels <= 1 {
+        return audio.to_vec();
+    }
+    audio
+        .chunks(channels as usize)
+        .map(|chunk| chunk.iter().sum::<f32>() / channels as f32)
+        .collect()
 }
 
-fn hz_to_mel(freq: f32) -> f32 {
-    2595.0 * (1.0 + freq / 700.0).log10()
+/// Pull a single channel (0-based) out of interleaved audio.
+pub fn extract_channel(audio: &[f32], channels: u16, channel: usize) -> Result<Vec<f32>> {
+    if channel >= channels.max(1) as usize {
+        return Err(Error::Audio(format!(
+            "Channel {channel} requested but audio has {channels} channel(s)"
+        )));
+    }
+    Ok(audio
+        .iter()
+        .skip(channel)
+        .step_by(channels.max(1) as usize)
+        .copied()
+        .collect())
+}
+
+/// Trade-off between resampling quality and speed.
+///
+/// All presets use a Kaiser-windowed sinc (band-limited) interpolation filter;
+/// they differ in filter length, stopband attenuation and passband width.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResampleQuality {
+    /// 8 zero crossings per side, ~60 dB stopband. Good enough for speech, cheapest.
+    Fast,
+    /// 16 zero crossings per side, ~80 dB stopband (default).
+    #[default]
+    Balanced,
+    /// 32 zero crossings per side, ~100 dB stopband with a wider passband.
+    High,
+}
+
+impl ResampleQuality {
+    // (zero crossings on each side of the sinc, Kaiser beta, passband rolloff)
+    fn params(self) -> (usize, f64, f64) {
+        match self {
+            ResampleQuality::Fast => (8, 5.7, 0.85),
+            ResampleQuality::Balanced => (16, 8.0, 0.92),
+            ResampleQuality::High => (32, 10.0, 0.96),
+        }
+    }
 }
 
-fn mel_to_hz(mel: f32) -> f32 {
-    700.0 * (10.0_f32.powf(mel / 2595.0) - 1.0)
+// Above this many polyphase branches the coefficient table would get too big,
+// so taps are evaluated on the fly instead (only happens for odd rate pairs).
+const MAX_TABLE_PHASES: usize = 4096;
+
+/// Band-limited polyphase sample-rate converter.
+///
+/// The conversion ratio is reduced to `up / down` and every output sample is
+/// computed from `2 * half_taps` input samples weighted by a windowed sinc
+/// evaluated at the output sample's fractional position. The resampler keeps
+/// the input history it still needs, so it can be fed arbitrarily sized chunks
+/// of a stream and produces the same output as converting the whole signal at once.
+#[derive(Debug, Clone)]
+pub struct Resampler {
+    from_rate: u32,
+    to_rate: u32,
+    up: u64,
+    down: u64,
+    half_taps: usize,
+    beta: f64,
+    cutoff: f64,
+    // up * 2 * half_taps coefficients, one row per phase (empty when evaluated on the fly)
+    table: Vec<f32>,
+    // Taps of the current output sample when there is no table, reused across samples
+    scratch: Vec<f32>,
+    // Input samples starting at absolute index `offset`
+    history: Vec<f32>,
+    offset: u64,
+    received: u64,
+    produced: u64,
 }
 
-fn create_mel_filterbank(n_fft: usize, n_mels: usize, sample_rate: usize) -> Array2<f32> {
-    let freq_bins = n_fft / 2 + 1;
-    let mut filterbank = Array2::<f32>::zeros((n_mels, freq_bins));
+impl Resampler {
+    /// Create a resampler converting mono audio from `from_rate` to `to_rate` Hz.
+    pub fn new(from_rate: u32, to_rate: u32, quality: ResampleQuality) -> Result<Self> {
+        if from_rate == 0 || to_rate == 0 {
+            return Err(Error::Audio(format!(
+                "Invalid sample rate conversion {from_rate} Hz -> {to_rate} Hz"
+            )));
+        }
+
+        let g = gcd(from_rate as u64, to_rate as u64);
+        let up = to_rate as u64 / g;
+        let down = from_rate as u64 / g;
+
+        let (zero_crossings, beta, rolloff) = quality.params();
+        // Low-pass at the lower of the two Nyquist frequencies, relative to the input rate.
+        // When downsampling the sinc is stretched, so the filter spans more input samples.
+        let cutoff = rolloff * (to_rate as f64 / from_rate as f64).min(1.0);
+        let half_taps = (zero_crossings as f64 / cutoff).ceil() as usize;
+
+        let mut resampler = Self {
+            from_rate,
+            to_rate,
+            up,
+            down,
+            half_taps,
+            beta,
+            cutoff,
+            table: Vec::new(),
+            scratch: Vec::new(),
+            history: Vec::new(),
+            offset: 0,
+            received: 0,
+            produced: 0,
+        };
+
+        if up as usize <= MAX_TABLE_PHASES {
+            let taps = 2 * half_taps;
+            let mut table = vec![0.0f32; up as usize * taps];
+            for phase in 0..up {
+                let row = &mut table[phase as usize * taps..(phase as usize + 1) * taps];
+                resampler.fill_taps(phase, row);
+            }
+            resampler.table = table;
+        }
+
+        Ok(resampler)
+    }
+
+    pub fn from_rate(&self) -> u32 {
+        self.from_rate
+    }
+
+    pub fn to_rate(&self) -> u32 {
+        self.to_rate
+    }
+
+    /// Clear the stream history so the next chunk starts a new signal.
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.offset = 0;
+        self.received = 0;
+        self.produced = 0;
+    }
+
+    /// Feed a chunk of input and return every output sample that can be computed so far.
+    ///
+    /// Output lags the input by `half_taps` input samples; call [`Resampler::flush`]
+    /// at the end of the stream to get the remainder.
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        if self.up == self.down {
+            self.received += input.len() as u64;
+            self.produced += input.len() as u64;
+            return input.to_vec();
+        }
 
-    let min_mel = hz_to_mel(0.0);
-    let max_mel = hz_to_mel(sample_rate as f32 / 2.0);
+        self.history.extend_from_slice(input);
+        self.received += input.len() as u64;
 
-    let mel_points: Vec<f32> = (0..=n_mels + 1)
-        .map(|i| mel_to_hz(min_mel + (max_mel - min_mel) * i as f32 / (n_mels + 1) as f32))
-        .collect();
+        let mut output = Vec::new();
+        loop {
+            let pos = self.produced * self.down;
+            let center = pos / self.up;
+            // Rightmost input sample used by this output sample
+            if center + self.half_taps as u64 >= self.received {
+                break;
+            }
+            output.push(self.compute(pos));
+            self.produced += 1;
+        }
+
+        self.trim_history();
+        output
+    }
+
+    /// Emit the remaining output samples, treating input past the end as silence.
+    pub fn flush(&mut self) -> Vec<f32> {
+        let total = (self.received * self.up).div_ceil(self.down);
+        if self.up == self.down {
+            self.produced = total;
+            return Vec::new();
+        }
 
-    let freq_bin_width = sample_rate as f32 / n_fft as f32;
+        let mut output = Vec::with_capacity(total.saturating_sub(self.produced) as usize);
+        while self.produced < total {
+            output.push(self.compute(self.produced * self.down));
+            self.produced += 1;
+        }
+        output
+    }
 
-    for mel_idx in 0..n_mels {
-        let left = mel_points[mel_idx];
-        let center = mel_points[mel_idx + 1];
-        let right = mel_points[mel_idx + 2];
+    // Compute one output sample located at input position pos / up
+    fn compute(&mut self, pos: u64) -> f32 {
+        let center = pos / self.up;
+        let phase = pos % self.up;
+        let taps = 2 * self.half_taps;
+        let first = center as i64 - self.half_taps as i64 + 1;
 
-        for freq_idx in 0..freq_bins {
-            let freq = freq_idx as f32 * freq_bin_width;
+        if self.table.is_empty() {
+            let mut coeffs = std::mem::take(&mut self.scratch);
+            coeffs.resize(taps, 0.0);
+            self.fill_taps(phase, &mut coeffs);
+            let acc = self.convolve(first, &coeffs);
+            self.scratch = coeffs;
+            acc
+        } else {
+            self.convolve(
+                first,
+                &self.table[phase as usize * taps..(phase as usize + 1) * taps],
+            )
+        }
+    }
 
-            if freq >= left && freq <= center {
-                filterbank[[mel_idx, freq_idx]] = (freq - left) / (center - left);
-            } else if freq > center && freq <= right {
-                filterbank[[mel_idx, freq_idx]] = (right - freq) / (right - center);
+    // Weighted sum of the input samples starting at absolute index `first`
+    fn convolve(&self, first: i64, coeffs: &[f32]) -> f32 {
+        let mut acc = 0.0f32;
+        for (j, &c) in coeffs.iter().enumerate() {
+            let idx = first + j as i64;
+            if idx < self.offset as i64 {
+                continue;
             }
+            let local = (idx - self.offset as i64) as usize;
+            if let Some(&x) = self.history.get(local) {
+                acc += x * c;
+            }
+        }
+        acc
+    }
+
+    // Windowed-sinc taps for one phase, normalized to unity DC gain
+    fn fill_taps(&self, phase: u64, out: &mut [f32]) {
+        let frac = phase as f64 / self.up as f64;
+        let half = self.half_taps as f64;
+        let norm = bessel_i0(self.beta);
+
+        let mut sum = 0.0f64;
+        for (j, o) in out.iter_mut().enumerate() {
+            // Distance from the output position, in input samples
+            let d = j as f64 - half + 1.0 - frac;
+            let x = d / half;
+            let window = if x.abs() >= 1.0 {
+                0.0
+            } else {
+                bessel_i0(self.beta * (1.0 - x * x).sqrt()) / norm
+            };
+            let h = self.cutoff * sinc(self.cutoff * d) * window;
+            sum += h;
+            *o = h as f32;
+        }
+
+        if sum.abs() > f64::EPSILON {
+            let scale = (1.0 / sum) as f32;
+            out.iter_mut().for_each(|o| *o *= scale);
         }
     }
 
-    filterbank
+    // Drop input that no future output sample can reach
+    fn trim_history(&mut self) {
+        let pos = self.produced * self.down;
+        let first_needed = (pos / self.up + 1).saturating_sub(self.half_taps as u64);
+        if first_needed > self.offset {
+            let drop = ((first_needed - self.offset) as usize).min(self.history.len());
+            self.history.drain(..drop);
+            self.offset += drop as u64;
+        }
+    }
+}
+
+/// Convert mono audio between sample rates with a band-limited resampler.
+///
+/// Returns the input unchanged when both rates are equal.
+pub fn resample(
+    audio: &[f32],
+    from_rate: u32,
+    to_rate: u32,
+    quality: ResampleQuality,
+) -> Result<Vec<f32>> {
+    if from_rate == to_rate {
+        return Ok(audio.to_vec());
+    }
+    let mut resampler = Resampler::new(from_rate, to_rate, quality)?;
+    let mut output = resampler.process(audio);
+    output.extend(resampler.flush());
+    Ok(output)
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-12 {
+        1.0
+    } else {
+        let px = std::f64::consts::PI * x;
+        px.sin() / px
+    }
+}
+
+// Zeroth-order modified Bessel function of the first kind (power series)
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half_x = x / 2.0;
+    for k in 1..50 {
+        term *= (half_x / k as f64) * (half_x / k as f64);
+        sum += term;
+        if term < sum * 1e-16 {
+            break;
+        }
+    }
+    sum
+}
+
+/// Duration range a model accepts in a single call, in seconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputLimits {
+    pub min_duration: f32,
+    /// `None` for models without a length limit (streaming models)
+    pub max_duration: Option<f32>,
+}
+
+impl InputLimits {
+    /// Limits of a model whose shortest input is one frame of `frontend`
+    pub fn for_frontend(frontend: &MelFrontend, max_duration: Option<f32>) -> Self {
+        Self {
+            min_duration: frontend.min_input_len() as f32 / frontend.config().sample_rate as f32,
+            max_duration,
+        }
+    }
+
+    /// Only reject empty and non-finite input
+    pub fn streaming() -> Self {
+        Self {
+            min_duration: 0.0,
+            max_duration: None,
+        }
+    }
+}
+
+/// Check interleaved input audio before it reaches feature extraction.
+///
+/// Returns [`Error::EmptyAudio`], [`Error::NonFiniteAudio`], [`Error::AudioTooShort`] or
+/// [`Error::AudioTooLong`] so callers can tell degenerate input apart from model failures.
+pub fn validate_audio(
+    audio: &[f32],
+    sample_rate: u32,
+    channels: u16,
+    limits: &InputLimits,
+) -> Result<()> {
+    if sample_rate == 0 || channels == 0 {
+        return Err(Error::Audio(format!(
+            "Invalid audio format: {sample_rate}Hz, {channels} channels"
+        )));
+    }
+    if audio.is_empty() {
+        return Err(Error::EmptyAudio);
+    }
+    if let Some(index) = audio.iter().position(|x| !x.is_finite()) {
+        return Err(Error::NonFiniteAudio { index });
+    }
+
+    let duration = (audio.len() / channels as usize) as f32 / sample_rate as f32;
+    if duration < limits.min_duration {
+        return Err(Error::AudioTooShort {
+            duration,
+            min_duration: limits.min_duration,
+        });
+    }
+    if let Some(max_duration) = limits.max_duration {
+        if duration > max_duration {
+            return Err(Error::AudioTooLong {
+                duration,
+                max_duration,
+            });
+        }
+    }
+    Ok(())
 }
 
 /// Extract mel spectrogram features from raw audio samples.
@@ -122,7 +738,8 @@ fn create_mel_filterbank(n_fft: usize, n_mels: usize, sample_rate: usize) -> Arr
 /// * `audio` - Audio samples as f32 values
 /// * `sample_rate` - Sample rate in Hz
 /// * `channels` - Number of audio channels
-/// * `config` - Preprocessor configuration
+/// * `frontend` - Mel frontend of the model (its config holds the model sample rate)
+/// * `resample_quality` - Resampler setting used when `sample_rate` differs from the model rate
 ///
 /// # Returns
 ///
@@ -131,49 +748,298 @@ pub fn extract_features_raw(
     mut audio: Vec<f32>,
     sample_rate: u32,
     channels: u16,
-    config: &PreprocessorConfig,
+    frontend: &MelFrontend,
+    resample_quality: ResampleQuality,
 ) -> Result<Array2<f32>> {
-    if sample_rate != config.sampling_rate as u32 {
-        return Err(Error::Audio(format!(
-            "Audio sample rate {} doesn't match expected {}. Please resample your audio first.",
-            sample_rate, config.sampling_rate
-        )));
+    if channels > 1 {
+        audio = downmix(&audio, channels);
     }
 
-    if channels > 1 {
-        let mono: Vec<f32> = audio
-            .chunks(channels as usize)
-            .map(|chunk| chunk.iter().sum::<f32>() / channels as f32)
+    audio = resample(
+        &audio,
+        sample_rate,
+        frontend.config().sample_rate as u32,
+        resample_quality,
+    )?;
+
+    Ok(frontend.compute(&audio))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    fn sine(freq: f32, rate: u32, secs: f32) -> Vec<f32> {
+        let n = (rate as f32 * secs) as usize;
+        (0..n)
+            .map(|i| (2.0 * PI * freq * i as f32 / rate as f32).sin())
+            .collect()
+    }
+
+    // Linear chirp from f0 to f1 Hz over `secs` seconds
+    fn sweep(f0: f64, f1: f64, rate: u32, secs: f64) -> Vec<f32> {
+        let n = (rate as f64 * secs) as usize;
+        let k = (f1 - f0) / secs;
+        (0..n)
+            .map(|i| {
+                let t = i as f64 / rate as f64;
+                (2.0 * std::f64::consts::PI * (f0 * t + 0.5 * k * t * t)).sin() as f32
+            })
+            .collect()
+    }
+
+    fn rms(x: &[f32]) -> f32 {
+        (x.iter().map(|v| v * v).sum::<f32>() / x.len() as f32).sqrt()
+    }
+
+    // Max error against the reference, skipping the filter edges
+    fn max_error(out: &[f32], reference: &[f32], margin: usize) -> f32 {
+        out[margin..out.len() - margin]
+            .iter()
+            .zip(&reference[margin..])
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn test_wav_detected_by_content_not_extension() {
+        let path = std::env::temp_dir().join(format!("parakeet_probe_{}.mp3", std::process::id()));
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 22050,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for s in [0i16, 16384, -16384, 0] {
+            writer.write_sample(s).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let (samples, spec) = load_audio(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            spec,
+            AudioSpec {
+                sample_rate: 22050,
+                channels: 2
+            }
+        );
+        assert_eq!(samples, vec![0.0, 0.5, -0.5, 0.0]);
+    }
+
+    #[cfg(feature = "symphonia")]
+    #[test]
+    fn test_decode_flac() {
+        // Two frames of 128 verbatim 16-bit stereo samples, the right channel the
+        // negated left
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/stereo.flac");
+        let (samples, spec) = load_audio(path).unwrap();
+
+        assert_eq!(
+            spec,
+            AudioSpec {
+                sample_rate: 16000,
+                channels: 2
+            }
+        );
+        let expected: Vec<f32> = (0..256)
+            .flat_map(|i| {
+                let left = ((i - 128) * 255) as f32 / 32768.0;
+                [left, -left]
+            })
+            .collect();
+        assert_eq!(samples, expected);
+    }
+
+    #[cfg(feature = "symphonia")]
+    #[test]
+    fn test_opus_is_rejected() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/mono.opus");
+        let err = load_audio(path).unwrap_err();
+        assert!(
+            matches!(&err, Error::Audio(msg) if msg.contains("Opus")),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_raw_s16_both_endianness() {
+        let le = RawAudioFormat::new(SampleFormat::S16, 16000, 1);
+        assert_eq!(
+            decode_raw_pcm(&[0x00, 0x40, 0x00, 0xC0], &le).unwrap(),
+            vec![0.5, -0.5]
+        );
+        let be = le.with_endianness(Endianness::Big);
+        assert_eq!(
+            decode_raw_pcm(&[0x40, 0x00, 0xC0, 0x00], &be).unwrap(),
+            vec![0.5, -0.5]
+        );
+    }
+
+    #[test]
+    fn test_raw_u8_s24_f32_and_mulaw() {
+        let u8_format = RawAudioFormat::new(SampleFormat::U8, 8000, 1);
+        assert_eq!(
+            decode_raw_pcm(&[128, 0, 192], &u8_format).unwrap(),
+            vec![0.0, -1.0, 0.5]
+        );
+
+        let s24 = RawAudioFormat::new(SampleFormat::S24, 48000, 1).with_endianness(Endianness::Big);
+        assert_eq!(
+            decode_raw_pcm(&[0xC0, 0x00, 0x00], &s24).unwrap(),
+            vec![-0.5]
+        );
+
+        let f32_format = RawAudioFormat::new(SampleFormat::F32, 16000, 1);
+        let bytes: Vec<u8> = [0.25f32, -1.0]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
             .collect();
-        audio = mono;
+        assert_eq!(
+            decode_raw_pcm(&bytes, &f32_format).unwrap(),
+            vec![0.25, -1.0]
+        );
+
+        let mulaw = RawAudioFormat::new(SampleFormat::MuLaw, 8000, 1);
+        assert_eq!(decode_raw_pcm(&[0xFF], &mulaw).unwrap(), vec![0.0]);
+    }
+
+    #[test]
+    fn test_raw_planar_is_interleaved() {
+        let format =
+            RawAudioFormat::new(SampleFormat::U8, 8000, 2).with_layout(ChannelLayout::Planar);
+        // Left: 0.0, 0.5 / Right: -1.0, -0.5
+        let out = decode_raw_pcm(&[128, 192, 0, 64], &format).unwrap();
+        assert_eq!(out, vec![0.0, -1.0, 0.5, -0.5]);
+    }
+
+    #[test]
+    fn test_raw_rejects_partial_frames() {
+        let format = RawAudioFormat::new(SampleFormat::S16, 16000, 2);
+        assert!(decode_raw_pcm(&[0; 6], &format).is_err());
+        let format = RawAudioFormat::new(SampleFormat::S16, 16000, 0);
+        assert!(decode_raw_pcm(&[0; 4], &format).is_err());
+    }
+
+    #[test]
+    fn test_same_rate_is_identity() {
+        let audio = sine(440.0, 16000, 0.1);
+        let out = resample(&audio, 16000, 16000, ResampleQuality::Fast).unwrap();
+        assert_eq!(out, audio);
+    }
+
+    #[test]
+    fn test_output_length() {
+        for (from, to) in [
+            (44100, 16000),
+            (48000, 16000),
+            (8000, 16000),
+            (22050, 16000),
+        ] {
+            let audio = vec![0.0f32; from as usize];
+            let out = resample(&audio, from, to, ResampleQuality::Balanced).unwrap();
+            assert_eq!(out.len(), to as usize, "{from} -> {to}");
+        }
     }
 
-    audio = apply_preemphasis(&audio, config.preemphasis);
+    #[test]
+    fn test_downsample_sine() {
+        let audio = sine(1000.0, 48000, 0.5);
+        let reference = sine(1000.0, 16000, 0.5);
+        for quality in [
+            ResampleQuality::Fast,
+            ResampleQuality::Balanced,
+            ResampleQuality::High,
+        ] {
+            let out = resample(&audio, 48000, 16000, quality).unwrap();
+            assert!(max_error(&out, &reference, 64) < 2e-3, "{quality:?}");
+        }
+    }
 
-    let spectrogram = stft(&audio, config.n_fft, config.hop_length, config.win_length);
+    #[test]
+    fn test_upsample_telephony_sine() {
+        let audio = sine(440.0, 8000, 0.5);
+        let reference = sine(440.0, 16000, 0.5);
+        let out = resample(&audio, 8000, 16000, ResampleQuality::High).unwrap();
+        assert!(max_error(&out, &reference, 128) < 1e-3);
+    }
 
-    let mel_filterbank =
-        create_mel_filterbank(config.n_fft, config.feature_size, config.sampling_rate);
-    let mel_spectrogram = mel_filterbank.dot(&spectrogram);
-    let mel_spectrogram = mel_spectrogram.mapv(|x| (x.max(1e-10)).ln());
+    #[test]
+    fn test_odd_rate_without_table() {
+        // 16001 -> 16000 Hz needs 16000 phases, more than the table holds
+        let audio = sine(440.0, 16001, 0.5);
+        let reference = sine(440.0, 16000, 0.5);
+        let out = resample(&audio, 16001, 16000, ResampleQuality::Fast).unwrap();
+        assert!(max_error(&out, &reference, 64) < 2e-3);
+    }
 
-    let mut mel_spectrogram = mel_spectrogram.t().to_owned();
+    #[test]
+    fn test_sweep_44k1_to_16k() {
+        // Sweep stays inside the 16 kHz passband
+        let audio = sweep(100.0, 6000.0, 44100, 1.0);
+        let reference = sweep(100.0, 6000.0, 16000, 1.0);
+        let out = resample(&audio, 44100, 16000, ResampleQuality::High).unwrap();
+        assert!(max_error(&out, &reference, 64) < 5e-3);
+    }
 
-    // Normalize each feature dimension to mean=0, std=1
-    let num_frames = mel_spectrogram.shape()[0];
-    let num_features = mel_spectrogram.shape()[1];
+    #[test]
+    fn test_sweep_above_nyquist_is_rejected() {
+        // 9-20 kHz sweep is entirely above the 8 kHz output Nyquist and must not alias back
+        let audio = sweep(9000.0, 20000.0, 48000, 1.0);
+        for quality in [ResampleQuality::Balanced, ResampleQuality::High] {
+            let out = resample(&audio, 48000, 16000, quality).unwrap();
+            assert!(rms(&out[64..out.len() - 64]) < 0.01, "{quality:?}");
+        }
+    }
 
-    for feat_idx in 0..num_features {
-        let mut column = mel_spectrogram.column_mut(feat_idx);
-        let mean: f32 = column.iter().sum::<f32>() / num_frames as f32;
-        let variance: f32 =
-            column.iter().map(|&x| (x - mean).powi(2)).sum::<f32>() / num_frames as f32;
-        let std = variance.sqrt().max(1e-10);
+    #[test]
+    fn test_streaming_matches_one_shot() {
+        let audio = sweep(100.0, 7000.0, 44100, 0.3);
+        let expected = resample(&audio, 44100, 16000, ResampleQuality::Balanced).unwrap();
 
-        for val in column.iter_mut() {
-            *val = (*val - mean) / std;
+        let mut resampler = Resampler::new(44100, 16000, ResampleQuality::Balanced).unwrap();
+        let mut out = Vec::new();
+        for chunk in audio.chunks(1000) {
+            out.extend(resampler.process(chunk));
         }
+        out.extend(resampler.flush());
+
+        assert_eq!(out, expected);
     }
 
-    Ok(mel_spectrogram)
+    #[test]
+    fn test_validate_audio() {
+        use crate::mel::{MelConfig, MelFrontend};
+
+        let frontend = MelFrontend::new(MelConfig::parakeet(&Default::default()));
+        let limits = InputLimits::for_frontend(&frontend, Some(1.0));
+        assert_eq!(limits.min_duration, 400.0 / 16000.0);
+
+        let check = |audio: &[f32], channels| validate_audio(audio, 16000, channels, &limits);
+        assert!(check(&[0.0; 16000], 1).is_ok());
+        assert!(matches!(check(&[], 1), Err(Error::EmptyAudio)));
+        assert!(matches!(
+            check(&[0.0; 399], 1),
+            Err(Error::AudioTooShort { .. })
+        ));
+        // 400 stereo samples are 200 frames
+        assert!(matches!(
+            check(&[0.0; 400], 2),
+            Err(Error::AudioTooShort { .. })
+        ));
+        assert!(matches!(
+            check(&[0.0, 0.1, f32::NAN], 1),
+            Err(Error::NonFiniteAudio { index: 2 })
+        ));
+        assert!(matches!(
+            check(&[0.0; 16001], 1),
+            Err(Error::AudioTooLong { max_duration, .. }) if max_duration == 1.0
+        ));
+
+        let streaming = InputLimits::streaming();
+        assert!(validate_audio(&[0.0], 16000, 1, &streaming).is_ok());
+    }
 }
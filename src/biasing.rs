@@ -0,0 +1,234 @@
+//! Contextual biasing (hotword boosting).
+//!
+//! Phrases are tokenized with the model's tokenizer and stored in a prefix trie. While
+//! decoding, every token that continues a phrase earns the phrase's boost. In beam
+//! search a hypothesis that leaves a phrase half-way loses the boost it collected,
+//! so only complete phrases keep their bonus (the scheme of k2/icefall's
+//! `ContextGraph`). Greedy decoding adds the boost to the continuing tokens' scores.
+
+use std::collections::HashMap;
+
+/// A phrase to favour during decoding
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hotword {
+    pub phrase: String,
+    /// Log-score added per matched token (e.g. 1.0 to 3.0)
+    pub boost: f32,
+}
+
+/// Phrases to bias decoding towards, e.g. product names, people and jargon
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ContextBiasing {
+    pub hotwords: Vec<Hotword>,
+}
+
+impl ContextBiasing {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a phrase with its per-token boost
+    pub fn add(mut self, phrase: impl Into<String>, boost: f32) -> Self {
+        self.hotwords.push(Hotword {
+            phrase: phrase.into(),
+            boost,
+        });
+        self
+    }
+}
+
+/// A hotword found in the transcript
+#[derive(Debug, Clone, PartialEq)]
+pub struct HotwordMatch {
+    pub phrase: String,
+    /// Seconds
+    pub start: f32,
+    pub end: f32,
+}
+
+const ROOT: usize = 0;
+
+#[derive(Debug, Clone, Default)]
+struct TrieNode {
+    children: HashMap<u32, usize>,
+    // Boost for the token leading into this node
+    boost: f32,
+    // Hotword completed at this node
+    phrase: Option<usize>,
+}
+
+/// Token-level prefix trie over the tokenized hotwords
+#[derive(Debug, Clone)]
+pub(crate) struct BiasTrie {
+    nodes: Vec<TrieNode>,
+    phrases: Vec<String>,
+}
+
+/// Position of one hypothesis in the trie
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct BiasState {
+    node: usize,
+    // Boost collected by the current, unfinished match
+    pending: f32,
+    /// Total boost so far
+    pub score: f32,
+}
+
+impl BiasTrie {
+    /// Build from hotwords and their token ids; phrases without tokens are skipped
+    pub fn new(entries: impl IntoIterator<Item = (Hotword, Vec<u32>)>) -> Self {
+        let mut trie = Self {
+            nodes: vec![TrieNode::default()],
+            phrases: Vec::new(),
+        };
+        for (hotword, tokens) in entries {
+            if tokens.is_empty() {
+                continue;
+            }
+            let mut node = ROOT;
+            for token in tokens {
+                node = match trie.nodes[node].children.get(&token) {
+                    Some(&child) => child,
+                    None => {
+                        trie.nodes.push(TrieNode::default());
+                        let child = trie.nodes.len() - 1;
+                        trie.nodes[node].children.insert(token, child);
+                        child
+                    }
+                };
+                // Shared prefixes take the strongest boost
+                let boost = &mut trie.nodes[node].boost;
+                *boost = boost.max(hotword.boost);
+            }
+            trie.nodes[node].phrase = Some(trie.phrases.len());
+            trie.phrases.push(hotword.phrase);
+        }
+        trie
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.phrases.is_empty()
+    }
+
+    /// State after emitting `token`
+    pub fn advance(&self, state: BiasState, token: u32) -> BiasState {
+        let mut next = state;
+        let child = match self.nodes[state.node].children.get(&token) {
+            Some(&child) => Some(child),
+            None => {
+                // Leaving an unfinished match takes its boost back
+                next.score -= state.pending;
+                next.pending = 0.0;
+                next.node = ROOT;
+                self.nodes[ROOT].children.get(&token).copied()
+            }
+        };
+
+        if let Some(child) = child {
+            let node = &self.nodes[child];
+            next.node = child;
+            next.score += node.boost;
+            next.pending += node.boost;
+            if node.phrase.is_some() {
+                // Complete: the boost is kept from here on
+                next.pending = 0.0;
+                if node.children.is_empty() {
+                    next.node = ROOT;
+                }
+            }
+        }
+        next
+    }
+
+    /// Score of a finished hypothesis (an unfinished match earns nothing)
+    pub fn finish(&self, state: BiasState) -> f32 {
+        state.score - state.pending
+    }
+
+    /// Boosted tokens in `state` for greedy decoding: continuations of the current
+    /// match and first tokens of every phrase
+    pub fn boosts(&self, state: BiasState) -> impl Iterator<Item = (u32, f32)> + '_ {
+        let current = (state.node != ROOT).then(|| &self.nodes[state.node].children);
+        current
+            .into_iter()
+            .flatten()
+            .chain(&self.nodes[ROOT].children)
+            .map(|(&token, &child)| (token, self.nodes[child].boost))
+    }
+
+    /// Non-overlapping hotword occurrences in `tokens`, longest first at each position:
+    /// `(phrase, first_token, last_token)`
+    pub fn find_matches(&self, tokens: &[u32]) -> Vec<(&str, usize, usize)> {
+        let mut matches = Vec::new();
+        let mut start = 0;
+        while start < tokens.len() {
+            let mut node = ROOT;
+            let mut found = None;
+            for (offset, token) in tokens[start..].iter().enumerate() {
+                match self.nodes[node].children.get(token) {
+                    Some(&child) => node = child,
+                    None => break,
+                }
+                if let Some(phrase) = self.nodes[node].phrase {
+                    found = Some((phrase, start + offset));
+                }
+            }
+            match found {
+                Some((phrase, last)) => {
+                    matches.push((self.phrases[phrase].as_str(), start, last));
+                    start = last + 1;
+                }
+                None => start += 1,
+            }
+        }
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trie() -> BiasTrie {
+        let biasing = ContextBiasing::new().add("nvidia", 2.0).add("nemo", 1.0);
+        let tokens = [vec![1, 2, 3], vec![4, 5]];
+        BiasTrie::new(biasing.hotwords.into_iter().zip(tokens))
+    }
+
+    #[test]
+    fn test_partial_match_is_taken_back() {
+        let trie = trie();
+        let mut state = BiasState::default();
+        for token in [1, 2] {
+            state = trie.advance(state, token);
+        }
+        assert_eq!(state.score, 4.0);
+        assert_eq!(trie.finish(state), 0.0);
+
+        // Diverging cancels the pending boost
+        state = trie.advance(state, 9);
+        assert_eq!(state.score, 0.0);
+
+        // A completed phrase keeps its boost
+        for token in [1, 2, 3, 9] {
+            state = trie.advance(state, token);
+        }
+        assert_eq!(trie.finish(state), 6.0);
+
+        // Diverging into the start of another phrase
+        let state = trie.advance(trie.advance(BiasState::default(), 1), 4);
+        assert_eq!(state.score, 1.0);
+    }
+
+    #[test]
+    fn test_find_matches_and_boosts() {
+        let trie = trie();
+        let matches = trie.find_matches(&[7, 1, 2, 3, 1, 2, 4, 5]);
+        assert_eq!(matches, vec![("nvidia", 1, 3), ("nemo", 6, 7)]);
+
+        let state = trie.advance(BiasState::default(), 1);
+        let mut boosts: Vec<_> = trie.boosts(state).collect();
+        boosts.sort_by_key(|&(token, _)| token);
+        assert_eq!(boosts, vec![(1, 2.0), (2, 2.0), (4, 1.0)]);
+    }
+}
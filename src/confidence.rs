@@ -0,0 +1,99 @@
+//! Token confidence from the model's output distributions.
+//!
+//! Every frame (CTC) or joint step (TDT, EOU) that emits a token has a probability
+//! distribution over the vocabulary. A measure maps it to a value in `[0, 1]`: 1 when
+//! all mass is on one token, 0 when the distribution is uniform. See Laptev and
+//! Ginsburg, "Fast Entropy-Based Methods of Word-Level Confidence Estimation for
+//! End-to-End Automatic Speech Recognition" (2022), which NeMo's measures follow.
+
+/// How a probability distribution is turned into a confidence value
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ConfidenceMeasure {
+    /// Probability of the most likely token, rescaled so a uniform distribution gives 0
+    #[default]
+    MaxProb,
+    /// One minus the normalized Shannon (Gibbs) entropy
+    Entropy,
+    /// One minus the normalized Tsallis entropy with parameter `alpha`. Values below 1
+    /// (NeMo recommends 0.33) separate correct from wrong words better than `Entropy`.
+    Tsallis { alpha: f32 },
+}
+
+impl ConfidenceMeasure {
+    /// Confidence of a distribution given as log-probabilities (or raw logits, which
+    /// are normalized first)
+    pub fn compute(&self, log_probs: &[f32]) -> f32 {
+        let n = log_probs.len();
+        if n < 2 {
+            return 1.0;
+        }
+        let max = log_probs.iter().fold(f32::NEG_INFINITY, |m, &x| m.max(x));
+        let log_sum = log_probs.iter().map(|&x| (x - max).exp()).sum::<f32>().ln() + max;
+        let probs = log_probs.iter().map(|&x| (x - log_sum).exp());
+        let n = n as f32;
+
+        let confidence = match *self {
+            ConfidenceMeasure::MaxProb => ((max - log_sum).exp() * n - 1.0) / (n - 1.0),
+            ConfidenceMeasure::Entropy => {
+                let entropy: f32 = probs.filter(|&p| p > 0.0).map(|p| -p * p.ln()).sum();
+                1.0 - entropy / n.ln()
+            }
+            ConfidenceMeasure::Tsallis { alpha } if (alpha - 1.0).abs() < 1e-6 => {
+                return ConfidenceMeasure::Entropy.compute(log_probs);
+            }
+            ConfidenceMeasure::Tsallis { alpha } => {
+                let entropy = (1.0 - probs.map(|p| p.powf(alpha)).sum::<f32>()) / (alpha - 1.0);
+                let max_entropy = (n.powf(1.0 - alpha) - 1.0) / (1.0 - alpha);
+                1.0 - entropy / max_entropy
+            }
+        };
+        confidence.clamp(0.0, 1.0)
+    }
+}
+
+/// Word confidence: the least confident token, since one wrong piece makes the word wrong
+pub(crate) fn word_confidence(tokens: impl IntoIterator<Item = Option<f32>>) -> Option<f32> {
+    tokens.into_iter().flatten().reduce(f32::min)
+}
+
+/// Sentence confidence: mean word confidence
+pub(crate) fn sentence_confidence(words: impl IntoIterator<Item = Option<f32>>) -> Option<f32> {
+    let (sum, count) = words
+        .into_iter()
+        .flatten()
+        .fold((0.0, 0), |(sum, count), c| (sum + c, count + 1));
+    (count > 0).then(|| sum / count as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_measures_range() {
+        let peaked = [0.0, -20.0, -20.0, -20.0];
+        let uniform = [1.0; 4];
+        let mixed = [0.6f32.ln(), 0.2f32.ln(), 0.1f32.ln(), 0.1f32.ln()];
+
+        for measure in [
+            ConfidenceMeasure::MaxProb,
+            ConfidenceMeasure::Entropy,
+            ConfidenceMeasure::Tsallis { alpha: 0.33 },
+        ] {
+            assert!(measure.compute(&peaked) > 0.99, "{measure:?}");
+            assert!(measure.compute(&uniform) < 1e-5, "{measure:?}");
+            let c = measure.compute(&mixed);
+            assert!(c > 0.0 && c < 1.0, "{measure:?}");
+        }
+        assert!(
+            (ConfidenceMeasure::MaxProb.compute(&mixed) - (0.6 * 4.0 - 1.0) / 3.0).abs() < 1e-5
+        );
+    }
+
+    #[test]
+    fn test_aggregation() {
+        assert_eq!(word_confidence([Some(0.9), None, Some(0.4)]), Some(0.4));
+        assert_eq!(word_confidence([None]), None);
+        assert_eq!(sentence_confidence([Some(1.0), Some(0.5)]), Some(0.75));
+    }
+}
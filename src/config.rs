@@ -1,6 +1,11 @@
+use crate::error::{Error, Result};
+use crate::mel::{FeatureNormalization, MelConfig};
+use ort::session::Session;
 use serde::{Deserialize, Serialize};
+use std::path::Path;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PreprocessorConfig {
     pub feature_extractor_type: String,
     pub feature_size: usize,
@@ -16,6 +21,7 @@ pub struct PreprocessorConfig {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ModelConfig {
     pub architectures: Vec<String>,
     pub vocab_size: usize,
@@ -49,3 +55,401 @@ impl Default for ModelConfig {
         }
     }
 }
+
+impl PreprocessorConfig {
+    /// Read a Hugging Face `preprocessor_config.json`. Missing keys take the CTC defaults.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        read_json(path.as_ref())
+    }
+
+    /// Find the preprocessing settings shipped with a model.
+    ///
+    /// Looks for, in order: `preprocessor_config.json`, NeMo `model_config.yaml`,
+    /// and the `features_size` of an onnx-asr style `config.json`.
+    /// `fallback` is returned (or completed) when none of them are present.
+    pub fn from_model_dir<P: AsRef<Path>>(dir: P, fallback: PreprocessorConfig) -> Result<Self> {
+        let dir = dir.as_ref();
+
+        let json_path = dir.join("preprocessor_config.json");
+        if json_path.is_file() {
+            return Self::from_file(json_path);
+        }
+
+        let yaml_path = dir.join("model_config.yaml");
+        if yaml_path.is_file() {
+            return Ok(NemoModelConfig::from_file(yaml_path)?.preprocessor_config(fallback));
+        }
+
+        let export_path = dir.join("config.json");
+        if export_path.is_file() {
+            let export: ExportConfig = read_json(&export_path)?;
+            if let Some(features) = export.features_size {
+                return Ok(Self {
+                    feature_size: features,
+                    ..fallback
+                });
+            }
+        }
+
+        Ok(fallback)
+    }
+}
+
+impl ModelConfig {
+    /// Read a Hugging Face `config.json`. Missing keys take the Parakeet-CTC-0.6b defaults.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        read_json(path.as_ref())
+    }
+
+    /// `config.json` from `dir`, NeMo `model_config.yaml` as a second choice,
+    /// or the Parakeet-CTC-0.6b defaults when neither exists.
+    pub fn from_model_dir<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref();
+
+        let json_path = dir.join("config.json");
+        if json_path.is_file() {
+            return Self::from_file(json_path);
+        }
+
+        let yaml_path = dir.join("model_config.yaml");
+        if yaml_path.is_file() {
+            let nemo = NemoModelConfig::from_file(yaml_path)?;
+            if let Some(vocab) = nemo.vocab_size() {
+                // NeMo CTC heads put the blank after the vocabulary
+                return Ok(Self {
+                    vocab_size: vocab + 1,
+                    pad_token_id: vocab,
+                    ..Self::default()
+                });
+            }
+        }
+
+        Ok(Self::default())
+    }
+}
+
+/// The parts of a NeMo `model_config.yaml` (found inside `.nemo` archives) used by the crate.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct NemoModelConfig {
+    pub sample_rate: Option<usize>,
+    pub preprocessor: NemoPreprocessorConfig,
+    pub encoder: NemoEncoderConfig,
+    pub decoder: NemoDecoderConfig,
+    pub joint: NemoJointConfig,
+    pub model_defaults: NemoModelDefaults,
+}
+
+/// `preprocessor` section (`AudioToMelSpectrogramPreprocessor`)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct NemoPreprocessorConfig {
+    pub sample_rate: Option<usize>,
+    pub features: Option<usize>,
+    pub n_fft: Option<usize>,
+    /// Window length in seconds
+    pub window_size: Option<f32>,
+    /// Hop length in seconds
+    pub window_stride: Option<f32>,
+    pub preemph: Option<f32>,
+    pub dither: Option<f32>,
+    pub normalize: Option<String>,
+}
+
+/// `encoder` section (`ConformerEncoder`)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct NemoEncoderConfig {
+    pub subsampling_factor: Option<usize>,
+    /// Length of the positional encoding table, in encoder frames
+    pub pos_emb_max_len: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct NemoDecoderConfig {
+    pub vocab_size: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct NemoJointConfig {
+    pub num_classes: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct NemoModelDefaults {
+    pub tdt_durations: Option<Vec<usize>>,
+}
+
+impl NemoModelConfig {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        serde_yaml::from_str(&text)
+            .map_err(|e| Error::Config(format!("Failed to parse {}: {e}", path.display())))
+    }
+
+    /// Vocabulary size without the blank token
+    pub fn vocab_size(&self) -> Option<usize> {
+        self.decoder.vocab_size.or(self.joint.num_classes)
+    }
+
+    /// Longest input the exported encoder accepts, in seconds.
+    ///
+    /// ONNX exports freeze the positional encoding table at `pos_emb_max_len` frames
+    /// (5000 by default, with 8x subsampling), so longer input fails inside the runtime.
+    pub fn max_duration(&self, preprocessor: &PreprocessorConfig) -> f32 {
+        let positions = self
+            .encoder
+            .pos_emb_max_len
+            .unwrap_or(DEFAULT_POS_EMB_MAX_LEN);
+        let subsampling = self
+            .encoder
+            .subsampling_factor
+            .unwrap_or(DEFAULT_SUBSAMPLING_FACTOR);
+        (positions * subsampling * preprocessor.hop_length) as f32
+            / preprocessor.sampling_rate as f32
+    }
+
+    /// Apply the `preprocessor` section on top of `fallback`
+    pub fn preprocessor_config(&self, fallback: PreprocessorConfig) -> PreprocessorConfig {
+        let pre = &self.preprocessor;
+        let sampling_rate = pre
+            .sample_rate
+            .or(self.sample_rate)
+            .unwrap_or(fallback.sampling_rate);
+        let to_samples = |secs: f32| (secs * sampling_rate as f32).round() as usize;
+
+        PreprocessorConfig {
+            feature_size: pre.features.unwrap_or(fallback.feature_size),
+            n_fft: pre.n_fft.unwrap_or(fallback.n_fft),
+            win_length: pre.window_size.map_or(fallback.win_length, to_samples),
+            hop_length: pre.window_stride.map_or(fallback.hop_length, to_samples),
+            preemphasis: pre.preemph.unwrap_or(fallback.preemphasis),
+            sampling_rate,
+            ..fallback
+        }
+    }
+
+    /// Apply the `preprocessor` section's `normalize` on top of `mel`.
+    ///
+    /// `normalize` values other than `per_feature` and `NA` are rejected with
+    /// [`Error::Config`]. A preset without
+    /// normalization (the streaming EOU frontend, whose features are computed
+    /// incrementally) ignores `per_feature`. `dither` is ignored: NeMo only dithers
+    /// during training, so dither stays opt-in through the models' `set_dither`.
+    pub fn mel_config(&self, mel: MelConfig) -> Result<MelConfig> {
+        let pre = &self.preprocessor;
+        let normalization = match pre.normalize.as_deref() {
+            None | Some("per_feature") => mel.normalization,
+            Some("NA") | Some("") => FeatureNormalization::None,
+            Some(other) => {
+                return Err(Error::Config(format!(
+                    "Unsupported preprocessor normalize {other:?}, expected per_feature or NA"
+                )))
+            }
+        };
+        Ok(MelConfig {
+            normalization,
+            ..mel
+        })
+    }
+}
+
+const DEFAULT_POS_EMB_MAX_LEN: usize = 5000;
+const DEFAULT_SUBSAMPLING_FACTOR: usize = 8;
+
+/// [`NemoModelConfig::max_duration`] from the model directory's `model_config.yaml`,
+/// or `None` when the directory has no such file or it does not set
+/// `encoder.pos_emb_max_len`.
+pub(crate) fn max_duration_from_model_dir(
+    dir: &Path,
+    preprocessor: &PreprocessorConfig,
+) -> Result<Option<f32>> {
+    let nemo = nemo_config_from_model_dir(dir)?;
+    Ok(nemo
+        .encoder
+        .pos_emb_max_len
+        .map(|_| nemo.max_duration(preprocessor)))
+}
+
+/// The model directory's `model_config.yaml`, or defaults when there is none
+pub(crate) fn nemo_config_from_model_dir(dir: &Path) -> Result<NemoModelConfig> {
+    let yaml_path = dir.join("model_config.yaml");
+    if yaml_path.is_file() {
+        NemoModelConfig::from_file(yaml_path)
+    } else {
+        Ok(NemoModelConfig::default())
+    }
+}
+
+// onnx-asr style `config.json` (e.g. istupakov/parakeet-tdt-0.6b-v3-onnx)
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ExportConfig {
+    features_size: Option<usize>,
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T> {
+    let text = std::fs::read_to_string(path)?;
+    serde_json::from_str(&text)
+        .map_err(|e| Error::Config(format!("Failed to parse {}: {e}", path.display())))
+}
+
+/// Shape of a graph input or output as declared in the ONNX model (dynamic axes are <= 0)
+pub(crate) fn graph_shape(session: &Session, name: &str, is_input: bool) -> Result<Vec<i64>> {
+    let outlets = if is_input {
+        session.inputs()
+    } else {
+        session.outputs()
+    };
+    let outlet = outlets.iter().find(|o| o.name() == name).ok_or_else(|| {
+        let kind = if is_input { "input" } else { "output" };
+        Error::Config(format!("ONNX graph has no {kind} named '{name}'"))
+    })?;
+
+    Ok(outlet
+        .dtype()
+        .tensor_shape()
+        .map(|shape| shape.to_vec())
+        .unwrap_or_default())
+}
+
+/// Static size of `axis` of a graph input or output, `None` if the axis is dynamic.
+pub(crate) fn graph_dim(
+    session: &Session,
+    name: &str,
+    is_input: bool,
+    axis: usize,
+) -> Result<Option<usize>> {
+    Ok(static_dim(graph_shape(session, name, is_input)?.get(axis)))
+}
+
+pub(crate) fn static_dim(dim: Option<&i64>) -> Option<usize> {
+    dim.copied().filter(|&d| d > 0).map(|d| d as usize)
+}
+
+/// Error out if a static graph dimension disagrees with the configured value
+pub(crate) fn check_dim(what: &str, graph: Option<usize>, configured: usize) -> Result<()> {
+    match graph {
+        Some(dim) if dim != configured => Err(Error::Config(format!(
+            "{what} mismatch: model graph expects {dim}, configuration says {configured}"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_json_keeps_defaults() {
+        let config: PreprocessorConfig =
+            serde_json::from_str(r#"{"feature_size": 128, "hop_length": 80}"#).unwrap();
+        assert_eq!(config.feature_size, 128);
+        assert_eq!(config.hop_length, 80);
+        assert_eq!(config.n_fft, 512);
+
+        let config: ModelConfig = serde_json::from_str(
+            r#"{"vocab_size": 1025, "pad_token_id": 1024, "encoder_config": {"hidden_size": 1024}}"#,
+        )
+        .unwrap();
+        assert_eq!(config.vocab_size, 1025);
+    }
+
+    #[test]
+    fn test_nemo_yaml() {
+        let yaml = r#"
+sample_rate: 16000
+preprocessor:
+  _target_: nemo.collections.asr.modules.AudioToMelSpectrogramPreprocessor
+  sample_rate: 16000
+  normalize: per_feature
+  window_size: 0.025
+  window_stride: 0.01
+  window: hann
+  features: 128
+  n_fft: 512
+  dither: 1.0e-05
+decoder:
+  vocab_size: 8192
+model_defaults:
+  tdt_durations: [0, 1, 2, 3, 4]
+"#;
+        let nemo: NemoModelConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(nemo.vocab_size(), Some(8192));
+        assert_eq!(nemo.model_defaults.tdt_durations, Some(vec![0, 1, 2, 3, 4]));
+
+        let config = nemo.preprocessor_config(PreprocessorConfig::default());
+        assert_eq!(config.feature_size, 128);
+        assert_eq!(config.win_length, 400);
+        assert_eq!(config.hop_length, 160);
+        assert_eq!(config.preemphasis, 0.97);
+        assert_eq!(nemo.max_duration(&config), 400.0);
+
+        let parakeet = MelConfig::parakeet(&config);
+        let mel = nemo.mel_config(parakeet).unwrap();
+        assert_eq!(mel.normalization, FeatureNormalization::PerFeature);
+        // dither is a training setting
+        assert_eq!(mel.dither, None);
+
+        // The streaming EOU frontend cannot normalize per utterance
+        let eou = nemo.mel_config(MelConfig::eou()).unwrap();
+        assert_eq!(eou.normalization, FeatureNormalization::None);
+    }
+
+    #[test]
+    fn test_nemo_normalize() {
+        let parse = |yaml: &str| serde_yaml::from_str::<NemoModelConfig>(yaml).unwrap();
+        let parakeet = MelConfig::parakeet(&PreprocessorConfig::default());
+
+        let mel = parse("preprocessor:\n  normalize: NA\n")
+            .mel_config(parakeet.clone())
+            .unwrap();
+        assert_eq!(mel.normalization, FeatureNormalization::None);
+
+        // Presets are kept when the yaml says nothing
+        assert_eq!(
+            NemoModelConfig::default()
+                .mel_config(parakeet.clone())
+                .unwrap(),
+            parakeet
+        );
+
+        let all_features = parse("preprocessor:\n  normalize: all_features\n");
+        assert!(matches!(
+            all_features.mel_config(parakeet),
+            Err(Error::Config(_))
+        ));
+    }
+
+    #[test]
+    fn test_max_duration_needs_pos_emb_max_len() {
+        let dir = std::env::temp_dir().join(format!("parakeet-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = PreprocessorConfig::default();
+        assert_eq!(max_duration_from_model_dir(&dir, &config).unwrap(), None);
+
+        std::fs::write(dir.join("model_config.yaml"), "sample_rate: 16000\n").unwrap();
+        assert_eq!(max_duration_from_model_dir(&dir, &config).unwrap(), None);
+
+        std::fs::write(
+            dir.join("model_config.yaml"),
+            "encoder:\n  pos_emb_max_len: 5000\n  subsampling_factor: 8\n",
+        )
+        .unwrap();
+        let max_duration = max_duration_from_model_dir(&dir, &config).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(max_duration, Some(400.0));
+    }
+
+    #[test]
+    fn test_check_dim() {
+        assert!(check_dim("Feature size", None, 80).is_ok());
+        assert!(check_dim("Feature size", Some(80), 80).is_ok());
+        assert!(check_dim("Feature size", Some(128), 80).is_err());
+    }
+}
@@ -0,0 +1,352 @@
+//! Word confusion networks ("sausages") from N-best hypotheses.
+//!
+//! A confusion network is a sequence of slots, each holding competing words with their
+//! posterior probabilities. Hypothesis posteriors are the softmax of the N-best scores
+//! (the CTC prefix probabilities from beam search, plus LM and hotword scores). The best
+//! hypothesis fixes the initial slots, and every other hypothesis is aligned to them by
+//! edit distance, preferring words that overlap in time: matched and substituted words
+//! add their hypothesis' posterior to the slot, words the hypothesis skips add it to the
+//! slot's empty word, and inserted words open a new slot. Every slot's posteriors sum
+//! to 1.
+//!
+//! With greedy decoding the N-best list holds a single path and every slot a single
+//! word; use [`crate::DecodingOptions::beam`] for alternatives.
+
+use crate::decoder::{Hypothesis, TimedToken};
+use crate::timestamps::{process_timestamps, TimestampMode};
+use serde::Serialize;
+use std::fmt::Write;
+
+/// Word confusion network of one transcription.
+///
+/// # JSON format
+///
+/// [`ConfusionNetwork::to_json`] writes the structure as is. Times are in seconds,
+/// slots are in time order and their arcs sorted by descending posterior; an empty
+/// `word` is the option of saying nothing in that slot:
+///
+/// ```json
+/// {
+///   "slots": [
+///     {
+///       "start": 0.32,
+///       "end": 0.64,
+///       "arcs": [
+///         { "word": "their", "start": 0.32, "end": 0.64, "posterior": 0.7 },
+///         { "word": "there", "start": 0.32, "end": 0.56, "posterior": 0.2 },
+///         { "word": "", "start": 0.32, "end": 0.64, "posterior": 0.1 }
+///       ]
+///     }
+///   ]
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ConfusionNetwork {
+    pub slots: Vec<ConfusionSlot>,
+}
+
+/// Competing words over one stretch of time
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ConfusionSlot {
+    pub start: f32,
+    pub end: f32,
+    /// Alternatives, most probable first
+    pub arcs: Vec<ConfusionArc>,
+}
+
+/// One word of a slot
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ConfusionArc {
+    /// The word, or empty for no word
+    pub word: String,
+    /// Time span of the word in the most probable hypothesis containing it (the slot's
+    /// span for the empty word)
+    pub start: f32,
+    pub end: f32,
+    pub posterior: f32,
+}
+
+impl ConfusionSlot {
+    /// The most probable word; `None` if no word is the most probable option
+    pub fn best(&self) -> Option<&ConfusionArc> {
+        self.arcs.first().filter(|arc| !arc.word.is_empty())
+    }
+}
+
+impl ConfusionNetwork {
+    /// Build a confusion network from an N-best list, best hypothesis first
+    pub fn from_hypotheses(hypotheses: &[Hypothesis]) -> Self {
+        let Some(max) = hypotheses.iter().map(|h| h.score).reduce(f32::max) else {
+            return Self::default();
+        };
+        let weights: Vec<f32> = hypotheses.iter().map(|h| (h.score - max).exp()).collect();
+        let total: f32 = weights.iter().sum();
+
+        let mut slots: Vec<ConfusionSlot> = Vec::new();
+        let mut seen = 0.0;
+        for (hypothesis, weight) in hypotheses.iter().zip(weights) {
+            let posterior = weight / total;
+            let words = process_timestamps(&hypothesis.tokens, TimestampMode::Words);
+            slots = merge(slots, &words, posterior, seen);
+            seen += posterior;
+        }
+
+        for slot in &mut slots {
+            let words = slot.arcs.iter().filter(|arc| !arc.word.is_empty());
+            slot.start = words
+                .clone()
+                .map(|arc| arc.start)
+                .fold(f32::INFINITY, f32::min);
+            slot.end = words.map(|arc| arc.end).fold(f32::NEG_INFINITY, f32::max);
+            for arc in slot.arcs.iter_mut().filter(|arc| arc.word.is_empty()) {
+                arc.start = slot.start;
+                arc.end = slot.end;
+            }
+            slot.arcs
+                .sort_by(|a, b| b.posterior.total_cmp(&a.posterior));
+        }
+        Self { slots }
+    }
+
+    /// Words of the most probable path
+    pub fn best_path(&self) -> Vec<&ConfusionArc> {
+        self.slots.iter().filter_map(ConfusionSlot::best).collect()
+    }
+
+    /// Serialize to the JSON format documented on [`ConfusionNetwork`]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("confusion network serializes to JSON")
+    }
+
+    /// Serialize as an HTK Standard Lattice Format (SLF) lattice.
+    ///
+    /// Node `i` sits at the start of slot `i` and the last node at the end of the last
+    /// slot; every arc of slot `i` is a link from node `i` to node `i + 1` with the
+    /// posterior in `p=` (as SRILM's `lattice-tool` reads it) and its log in `a=`. Empty
+    /// words are written as `!NULL`.
+    pub fn to_htk_slf(&self, utterance: &str) -> String {
+        let links: usize = self.slots.iter().map(|slot| slot.arcs.len()).sum();
+        let mut slf = String::new();
+        let _ = writeln!(slf, "VERSION=1.0");
+        let _ = writeln!(slf, "UTTERANCE={utterance}");
+        let _ = writeln!(slf, "base=2.718282");
+        let _ = writeln!(slf, "start=0");
+        let _ = writeln!(slf, "end={}", self.slots.len());
+        let _ = writeln!(slf, "N={} L={links}", self.slots.len() + 1);
+
+        for (i, slot) in self.slots.iter().enumerate() {
+            let _ = writeln!(slf, "I={i} t={:.2}", slot.start);
+        }
+        let end = self.slots.last().map_or(0.0, |slot| slot.end);
+        let _ = writeln!(slf, "I={} t={end:.2}", self.slots.len());
+
+        let mut link = 0;
+        for (i, slot) in self.slots.iter().enumerate() {
+            for arc in &slot.arcs {
+                let word = if arc.word.is_empty() {
+                    "!NULL"
+                } else {
+                    arc.word.as_str()
+                };
+                let _ = writeln!(
+                    slf,
+                    "J={link} S={i} E={} W={word} a={:.4} p={:.6}",
+                    i + 1,
+                    arc.posterior.max(f32::MIN_POSITIVE).ln(),
+                    arc.posterior
+                );
+                link += 1;
+            }
+        }
+        slf
+    }
+}
+
+// Alignment step from the previous cell of the edit distance table
+#[derive(Clone, Copy)]
+enum Step {
+    Match,
+    Insert,
+    Delete,
+}
+
+// Align `words` (with posterior `posterior`) to the slots built from hypotheses with a
+// total posterior of `seen`
+fn merge(
+    slots: Vec<ConfusionSlot>,
+    words: &[TimedToken],
+    posterior: f32,
+    seen: f32,
+) -> Vec<ConfusionSlot> {
+    let (n, m) = (slots.len(), words.len());
+    let mut cost = vec![vec![0.0f32; m + 1]; n + 1];
+    let mut steps = vec![vec![Step::Match; m + 1]; n + 1];
+    for i in 1..=n {
+        cost[i][0] = i as f32;
+        steps[i][0] = Step::Delete;
+    }
+    for j in 1..=m {
+        cost[0][j] = j as f32;
+        steps[0][j] = Step::Insert;
+    }
+    for i in 1..=n {
+        for j in 1..=m {
+            let options = [
+                (
+                    cost[i - 1][j - 1] + substitution(&slots[i - 1], &words[j - 1]),
+                    Step::Match,
+                ),
+                (cost[i][j - 1] + 1.0, Step::Insert),
+                (cost[i - 1][j] + 1.0, Step::Delete),
+            ];
+            let (best, step) = options
+                .into_iter()
+                .min_by(|a, b| a.0.total_cmp(&b.0))
+                .unwrap();
+            cost[i][j] = best;
+            steps[i][j] = step;
+        }
+    }
+
+    let mut merged = Vec::with_capacity(n.max(m));
+    let mut slots = slots;
+    let (mut i, mut j) = (n, m);
+    while i > 0 || j > 0 {
+        match steps[i][j] {
+            Step::Match => {
+                let mut slot = slots.pop().unwrap();
+                add(&mut slot, &words[j - 1], posterior);
+                merged.push(slot);
+                i -= 1;
+                j -= 1;
+            }
+            Step::Insert => {
+                let word = &words[j - 1];
+                let mut slot = ConfusionSlot::default();
+                if seen > 0.0 {
+                    slot.arcs.push(ConfusionArc {
+                        posterior: seen,
+                        ..Default::default()
+                    });
+                }
+                add(&mut slot, word, posterior);
+                merged.push(slot);
+                j -= 1;
+            }
+            Step::Delete => {
+                let mut slot = slots.pop().unwrap();
+                add(&mut slot, &TimedToken::default(), posterior);
+                merged.push(slot);
+                i -= 1;
+            }
+        }
+    }
+    merged.reverse();
+    merged
+}
+
+// Cost of aligning `word` to `slot`: free for a word already in the slot, otherwise
+// between 1 and 1.5 depending on how much it overlaps the slot's words in time
+fn substitution(slot: &ConfusionSlot, word: &TimedToken) -> f32 {
+    let mut overlap = 0.0f32;
+    for arc in slot.arcs.iter().filter(|arc| !arc.word.is_empty()) {
+        if arc.word.eq_ignore_ascii_case(&word.text) {
+            return 0.0;
+        }
+        let shared = arc.end.min(word.end) - arc.start.max(word.start);
+        let union = arc.end.max(word.end) - arc.start.min(word.start);
+        if union > 0.0 {
+            overlap = overlap.max(shared.max(0.0) / union);
+        }
+    }
+    1.5 - 0.5 * overlap
+}
+
+fn add(slot: &mut ConfusionSlot, word: &TimedToken, posterior: f32) {
+    match slot
+        .arcs
+        .iter_mut()
+        .find(|arc| arc.word.eq_ignore_ascii_case(&word.text))
+    {
+        Some(arc) => arc.posterior += posterior,
+        None => slot.arcs.push(ConfusionArc {
+            word: word.text.clone(),
+            start: word.start,
+            end: word.end,
+            posterior,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hypothesis(words: &[(&str, f32)], score: f32) -> Hypothesis {
+        Hypothesis {
+            tokens: words
+                .iter()
+                .map(|&(word, start)| TimedToken {
+                    text: format!(" {word}"),
+                    start,
+                    end: start + 0.3,
+                    ..Default::default()
+                })
+                .collect(),
+            score,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sausage_from_n_best() {
+        let hypotheses = [
+            hypothesis(&[("i", 0.0), ("saw", 0.4), ("it", 0.8)], 0.0),
+            hypothesis(&[("i", 0.0), ("so", 0.4), ("it", 0.8)], -1.0),
+            hypothesis(&[("i", 0.0), ("saw", 0.4), ("a", 0.6), ("bit", 0.8)], -2.0),
+        ];
+        let network = ConfusionNetwork::from_hypotheses(&hypotheses);
+        let z = 1.0 + (-1.0f32).exp() + (-2.0f32).exp();
+        let p = [1.0 / z, (-1.0f32).exp() / z, (-2.0f32).exp() / z];
+
+        let words: Vec<_> = network
+            .best_path()
+            .iter()
+            .map(|a| a.word.as_str())
+            .collect();
+        assert_eq!(words, vec!["i", "saw", "it"]);
+        assert_eq!(network.slots.len(), 4);
+        for slot in &network.slots {
+            let total: f32 = slot.arcs.iter().map(|a| a.posterior).sum();
+            assert!((total - 1.0).abs() < 1e-5);
+        }
+
+        // "so" competes with "saw", the inserted "a" with no word
+        let second = &network.slots[1];
+        assert_eq!(second.arcs[1].word, "so");
+        assert!((second.arcs[1].posterior - p[1]).abs() < 1e-5);
+        let third = &network.slots[2];
+        assert_eq!(third.arcs[0].word, "");
+        assert!((third.arcs[1].posterior - p[2]).abs() < 1e-5);
+        assert!((third.start - 0.6).abs() < 1e-6 && (third.end - 0.9).abs() < 1e-6);
+        let last: Vec<_> = network.slots[3]
+            .arcs
+            .iter()
+            .map(|a| a.word.as_str())
+            .collect();
+        assert_eq!(last, vec!["it", "bit"]);
+    }
+
+    #[test]
+    fn test_htk_slf_export() {
+        let network = ConfusionNetwork::from_hypotheses(&[hypothesis(&[("hi", 0.0)], 0.0)]);
+        let slf = network.to_htk_slf("utt1");
+        assert!(slf.contains("UTTERANCE=utt1\n"));
+        assert!(slf.contains("N=2 L=1\n"));
+        assert!(slf.contains("I=1 t=0.30\n"));
+        assert!(slf.contains("J=0 S=0 E=1 W=hi a=0.0000 p=1.000000\n"));
+
+        let json: serde_json::Value = serde_json::from_str(&network.to_json()).unwrap();
+        assert_eq!(json["slots"][0]["arcs"][0]["word"], "hi");
+    }
+}
@@ -0,0 +1,334 @@
+//! CTC prefix beam search.
+//!
+//! Hypotheses are label prefixes (blanks and repeats already collapsed). For every
+//! prefix the probability of all alignments ending in blank and in non-blank is kept
+//! separately, so `a a` (one token) and `a _ a` (two tokens) are scored correctly.
+//! See Hannun et al., "First-Pass Large Vocabulary Continuous Speech Recognition
+//! using Bi-Directional Recurrent DNNs" (2014).
+
+use crate::decoding::{BeamSearchConfig, Fusion, FusionState};
+use ndarray::{Array2, ArrayView1};
+use std::collections::HashMap;
+
+/// A decoded label sequence
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CtcHypothesis {
+    pub tokens: Vec<u32>,
+    /// `(start_frame, end_frame)` of every token, end exclusive, taken from the most
+    /// probable alignment of the prefix
+    pub frames: Vec<(usize, usize)>,
+    /// Total log-probability of the prefix over all alignments
+    pub log_prob: f32,
+    /// Weighted LM score including word bonuses (0 without a language model)
+    pub lm_score: f32,
+    /// Hotword boost (0 without context biasing)
+    pub bias_score: f32,
+}
+
+impl CtcHypothesis {
+    pub fn score(&self) -> f32 {
+        self.log_prob + self.lm_score + self.bias_score
+    }
+}
+
+// Single alignment, kept to take token timestamps from
+#[derive(Debug, Clone)]
+struct Path {
+    score: f32,
+    frames: Vec<(usize, usize)>,
+}
+
+impl Path {
+    const NONE: Path = Path {
+        score: f32::NEG_INFINITY,
+        frames: Vec::new(),
+    };
+
+    fn keep_better(&mut self, score: f32, frames: impl FnOnce() -> Vec<(usize, usize)>) {
+        if score > self.score {
+            self.score = score;
+            self.frames = frames();
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Beam {
+    tokens: Vec<u32>,
+    // Log-probabilities of all alignments ending in blank / in the last token
+    blank: f32,
+    non_blank: f32,
+    // Most probable alignment of each kind
+    blank_path: Path,
+    non_blank_path: Path,
+    fusion: FusionState,
+}
+
+impl Beam {
+    fn empty(tokens: Vec<u32>, fusion: FusionState) -> Self {
+        Self {
+            tokens,
+            fusion,
+            blank: f32::NEG_INFINITY,
+            non_blank: f32::NEG_INFINITY,
+            blank_path: Path::NONE,
+            non_blank_path: Path::NONE,
+        }
+    }
+
+    fn total(&self) -> f32 {
+        log_add(self.blank, self.non_blank)
+    }
+
+    // Ranking score with shallow fusion
+    fn score(&self) -> f32 {
+        self.total() + self.fusion.score()
+    }
+
+    fn best_path(&self) -> &Path {
+        if self.blank_path.score >= self.non_blank_path.score {
+            &self.blank_path
+        } else {
+            &self.non_blank_path
+        }
+    }
+}
+
+/// Log-softmax over the last axis, so raw logits and log-probabilities both work
+pub(crate) fn log_softmax(logits: &Array2<f32>) -> Array2<f32> {
+    let mut out = logits.clone();
+    for mut row in out.rows_mut() {
+        let max = row.fold(f32::NEG_INFINITY, |m, &x| m.max(x));
+        let log_sum = row.iter().map(|&x| (x - max).exp()).sum::<f32>().ln() + max;
+        row.mapv_inplace(|x| x - log_sum);
+    }
+    out
+}
+
+pub(crate) fn log_add(a: f32, b: f32) -> f32 {
+    if a == f32::NEG_INFINITY {
+        return b;
+    }
+    if b == f32::NEG_INFINITY {
+        return a;
+    }
+    let (hi, lo) = if a > b { (a, b) } else { (b, a) };
+    hi + (lo - hi).exp().ln_1p()
+}
+
+/// Run prefix beam search over `(frames, vocab)` log-probabilities.
+///
+/// Hypotheses are ranked by acoustic score plus the `fusion` scores (LM, hotwords).
+/// Returns up to `beam_width` hypotheses, best first.
+pub(crate) fn prefix_beam_search(
+    log_probs: &Array2<f32>,
+    blank: usize,
+    config: &BeamSearchConfig,
+    fusion: Fusion,
+) -> Vec<CtcHypothesis> {
+    let beam_width = config.beam_width.max(1);
+    let mut start = Beam::empty(Vec::new(), fusion.start());
+    start.blank = 0.0;
+    start.blank_path.score = 0.0;
+    let mut beams = vec![start];
+
+    for (t, row) in log_probs.rows().into_iter().enumerate() {
+        let blank_lp = row[blank];
+        if let Some(threshold) = config.blank_skip_threshold {
+            if blank_lp.exp() > threshold {
+                // Treat the frame as pure blank
+                for beam in &mut beams {
+                    let best = beam.best_path().clone();
+                    beam.blank = beam.total() + blank_lp;
+                    beam.non_blank = f32::NEG_INFINITY;
+                    beam.blank_path = Path {
+                        score: best.score + blank_lp,
+                        frames: best.frames,
+                    };
+                    beam.non_blank_path = Path::NONE;
+                }
+                continue;
+            }
+        }
+
+        let candidates = candidate_tokens(row, blank, config.token_prune_threshold);
+        let mut next: HashMap<Vec<u32>, Beam> = HashMap::with_capacity(beams.len() * 4);
+
+        for beam in &beams {
+            let best = beam.best_path();
+            let same = next
+                .entry(beam.tokens.clone())
+                .or_insert_with(|| Beam::empty(beam.tokens.clone(), beam.fusion.clone()));
+
+            // Blank: prefix unchanged, now ends in blank
+            same.blank = log_add(same.blank, beam.total() + blank_lp);
+            same.blank_path
+                .keep_better(best.score + blank_lp, || best.frames.clone());
+
+            let last = beam.tokens.last().copied();
+            if let Some(last) = last {
+                // Repeat without blank collapses into the last token
+                let lp = row[last as usize];
+                same.non_blank = log_add(same.non_blank, beam.non_blank + lp);
+                same.non_blank_path
+                    .keep_better(beam.non_blank_path.score + lp, || {
+                        let mut frames = beam.non_blank_path.frames.clone();
+                        if let Some(span) = frames.last_mut() {
+                            span.1 = t + 1;
+                        }
+                        frames
+                    });
+            }
+
+            for &(token, lp) in &candidates {
+                // The same token again only starts a new one after a blank
+                let (score, path) = if Some(token) == last {
+                    (beam.blank, &beam.blank_path)
+                } else {
+                    (beam.total(), best)
+                };
+                if score == f32::NEG_INFINITY {
+                    continue;
+                }
+
+                let mut tokens = beam.tokens.clone();
+                tokens.push(token);
+                let entry = next
+                    .entry(tokens.clone())
+                    .or_insert_with(|| Beam::empty(tokens, fusion.advance(&beam.fusion, token)));
+                entry.non_blank = log_add(entry.non_blank, score + lp);
+                entry.non_blank_path.keep_better(path.score + lp, || {
+                    let mut frames = path.frames.clone();
+                    frames.push((t, t + 1));
+                    frames
+                });
+            }
+        }
+
+        beams = next.into_values().collect();
+        sort_beams(&mut beams);
+        beams.truncate(beam_width);
+    }
+
+    let mut hypotheses: Vec<CtcHypothesis> = beams
+        .into_iter()
+        .map(|beam| {
+            let (lm_score, bias_score) = fusion.finish(&beam.fusion);
+            CtcHypothesis {
+                log_prob: beam.total(),
+                lm_score,
+                bias_score,
+                frames: beam.best_path().frames.clone(),
+                tokens: beam.tokens,
+            }
+        })
+        .collect();
+    // End-of-sentence LM scores and unfinished hotwords can reorder the final beam
+    hypotheses.sort_by(|a, b| {
+        b.score()
+            .partial_cmp(&a.score())
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    hypotheses
+}
+
+// Non-blank tokens of a frame worth expanding, with their log-probabilities
+fn candidate_tokens(row: ArrayView1<f32>, blank: usize, prune: f32) -> Vec<(u32, f32)> {
+    let best = row.fold(f32::NEG_INFINITY, |m, &x| m.max(x));
+    row.iter()
+        .enumerate()
+        .filter(|&(id, &lp)| id != blank && lp >= best + prune && lp > f32::NEG_INFINITY)
+        .map(|(id, &lp)| (id as u32, lp))
+        .collect()
+}
+
+fn sort_beams(beams: &mut [Beam]) {
+    beams.sort_by(|a, b| {
+        b.score()
+            .partial_cmp(&a.score())
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.tokens.cmp(&b.tokens))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    fn probs(rows: Array2<f32>) -> Array2<f32> {
+        rows.mapv(f32::ln)
+    }
+
+    #[test]
+    fn test_beam_sums_alignments_greedy_misses() {
+        // vocab: 0 = "a", 1 = blank. Greedy picks blank twice (p = 0.36), but "a" has
+        // p = 0.16 + 0.24 + 0.24 = 0.64 over its three alignments.
+        let log_probs = probs(array![[0.4, 0.6], [0.4, 0.6]]);
+        let best =
+            &prefix_beam_search(&log_probs, 1, &BeamSearchConfig::new(4), Fusion::default())[0];
+        assert_eq!(best.tokens, vec![0]);
+        assert!((best.log_prob - 0.64f32.ln()).abs() < 1e-5);
+        // Most probable alignment is "a _" or "_ a"; either way one frame long
+        assert_eq!(best.frames[0].1 - best.frames[0].0, 1);
+    }
+
+    #[test]
+    fn test_repeats_and_blanks() {
+        // "a a _ a b" -> a a b
+        let log_probs = probs(array![
+            [0.9, 0.05, 0.05],
+            [0.9, 0.05, 0.05],
+            [0.05, 0.05, 0.9],
+            [0.9, 0.05, 0.05],
+            [0.05, 0.9, 0.05],
+        ]);
+        let hyps = prefix_beam_search(&log_probs, 2, &BeamSearchConfig::new(8), Fusion::default());
+        assert_eq!(hyps[0].tokens, vec![0, 0, 1]);
+        assert_eq!(hyps[0].frames, vec![(0, 2), (3, 4), (4, 5)]);
+        assert!(hyps.windows(2).all(|w| w[0].log_prob >= w[1].log_prob));
+
+        let total: f32 = hyps.iter().map(|h| h.log_prob.exp()).sum();
+        assert!(total <= 1.0 + 1e-4);
+    }
+
+    #[test]
+    fn test_log_softmax_rows_normalize() {
+        let out = log_softmax(&array![[1.0, 2.0, 3.0], [0.0, 0.0, 0.0]]);
+        for row in out.rows() {
+            assert!((row.mapv(f32::exp).sum() - 1.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_language_model_breaks_acoustic_tie() {
+        use crate::lm::{LanguageModel, LmScorer, NgramLm};
+
+        // 0 = "▁the", 1 = "▁cat", 2 = "▁hat", 3 = blank; "hat" is slightly more likely
+        let log_probs = probs(array![
+            [0.9, 0.02, 0.02, 0.06],
+            [0.02, 0.43, 0.53, 0.02],
+            [0.02, 0.02, 0.02, 0.94],
+        ]);
+        let config = BeamSearchConfig::new(8);
+        let acoustic = prefix_beam_search(&log_probs, 3, &config, Fusion::default());
+        assert_eq!(acoustic[0].tokens, vec![0, 2]);
+
+        let arpa = include_str!("../tests/data/tiny.arpa");
+        let lm = LanguageModel::new(NgramLm::from_arpa_str(arpa).unwrap())
+            .with_weight(1.0)
+            .with_word_bonus(0.0);
+        let pieces: Vec<String> = ["▁the", "▁cat", "▁hat", "<blank>"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let scorer = LmScorer::new(&lm, &pieces);
+        let fusion = Fusion {
+            lm: Some(&scorer),
+            bias: None,
+        };
+        let fused = prefix_beam_search(&log_probs, 3, &config, fusion);
+        assert_eq!(fused[0].tokens, vec![0, 1]);
+        assert!(fused[0].lm_score < 0.0);
+    }
+}
@@ -1,26 +1,64 @@
+use crate::biasing::{BiasState, BiasTrie, ContextBiasing, HotwordMatch};
+use crate::confidence::ConfidenceMeasure;
+use crate::confusion::ConfusionNetwork;
+use crate::ctc_beam::{log_softmax, prefix_beam_search};
+use crate::decoding::{BeamSearchConfig, DecodingOptions, Fusion, TokenControls};
 use crate::error::{Error, Result};
+use crate::lm::LmScorer;
 use ndarray::Array2;
+use std::borrow::Cow;
 use std::path::Path;
 
 // Token with its timestamp information
 // start and end are in seconds
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct TimedToken {
     pub text: String,
     pub start: f32,
     pub end: f32,
+    /// Source channel when channels are transcribed separately (`ChannelStrategy::PerChannel`)
+    pub channel: Option<usize>,
+    /// Confidence in `[0, 1]` (see [`crate::ConfidenceMeasure`]); words take their least
+    /// confident token, sentences the mean of their words
+    pub confidence: Option<f32>,
 }
 
-#[derive(Debug, Clone)]
+/// One entry of an N-best list
+#[derive(Debug, Clone, Default)]
+pub struct Hypothesis {
+    pub text: String,
+    /// Token-level timestamps, whatever the requested `TimestampMode`
+    pub tokens: Vec<TimedToken>,
+    /// Acoustic log-probability (natural log)
+    pub log_prob: f32,
+    /// Weighted LM score including word bonuses (0 without a language model)
+    pub lm_score: f32,
+    /// Score the list is ranked by: `log_prob + lm_score` plus hotword boosts
+    pub score: f32,
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct TranscriptionResult {
     pub text: String,
     pub tokens: Vec<TimedToken>,
+    /// Hotwords found in the transcript when decoding with `ContextBiasing`
+    pub hotwords: Vec<HotwordMatch>,
+    /// Best hypotheses, best first: up to `beam_width` from beam search, the single
+    /// greedy path otherwise. Empty when results of several segments or channels are
+    /// merged.
+    pub n_best: Vec<Hypothesis>,
+    /// Word alternatives with posteriors, built from `n_best`. `None` when results of
+    /// several segments or channels are merged.
+    pub confusion_network: Option<ConfusionNetwork>,
 }
 
 // CTC decoder for parakeet-ctc-0.6b model with token-level timestamps
 pub struct ParakeetDecoder {
     tokenizer: tokenizers::Tokenizer,
     pad_token_id: usize,
+    bias: Option<BiasTrie>,
+    confidence: ConfidenceMeasure,
+    controls: TokenControls,
 }
 
 impl ParakeetDecoder {
@@ -36,9 +74,69 @@ impl ParakeetDecoder {
         Ok(Self {
             tokenizer,
             pad_token_id,
+            bias: None,
+            confidence: ConfidenceMeasure::default(),
+            controls: TokenControls::default(),
         })
     }
 
+    /// Use the blank/pad id from the model configuration instead of the CTC-0.6b default
+    pub fn with_pad_token_id(mut self, pad_token_id: usize) -> Self {
+        self.pad_token_id = pad_token_id;
+        self
+    }
+
+    /// Boost the given hotwords in [`ParakeetDecoder::decode_with_timestamps`] and
+    /// [`ParakeetDecoder::decode_beam_with_timestamps`] (`None` turns biasing off).
+    /// Phrases are tokenized with the model's tokenizer.
+    pub fn set_context_biasing(&mut self, biasing: Option<&ContextBiasing>) -> Result<()> {
+        self.bias = match biasing {
+            Some(biasing) => {
+                let mut entries = Vec::with_capacity(biasing.hotwords.len());
+                for hotword in &biasing.hotwords {
+                    let encoding = self
+                        .tokenizer
+                        .encode(hotword.phrase.as_str(), false)
+                        .map_err(|e| Error::Tokenizer(format!("Failed to encode hotword: {e}")))?;
+                    entries.push((hotword.clone(), encoding.get_ids().to_vec()));
+                }
+                Some(BiasTrie::new(entries)).filter(|trie| !trie.is_empty())
+            }
+            None => None,
+        };
+        Ok(())
+    }
+
+    /// Measure used for [`TimedToken::confidence`]; a token's confidence is the mean
+    /// over the frames it was emitted on
+    pub fn set_confidence_measure(&mut self, measure: ConfidenceMeasure) {
+        self.confidence = measure;
+    }
+
+    /// Apply the hotwords, confidence measure and token controls of `options`. Fails if
+    /// a hotword cannot be tokenized or a token id is out of range.
+    pub fn set_decoding_options(&mut self, options: &DecodingOptions) -> Result<()> {
+        let vocab_size = self
+            .tokenizer
+            .get_vocab_size(true)
+            .max(self.pad_token_id + 1);
+        let controls = TokenControls::new(options, self.pad_token_id, vocab_size)?;
+        self.set_context_biasing(options.context_biasing.as_ref())?;
+        self.set_confidence_measure(options.confidence);
+        self.controls = controls;
+        Ok(())
+    }
+
+    // Logits with the token controls applied
+    fn controlled<'a>(&self, logits: &'a Array2<f32>) -> Cow<'a, Array2<f32>> {
+        if self.controls.is_empty() {
+            return Cow::Borrowed(logits);
+        }
+        let mut logits = logits.clone();
+        self.controls.apply_rows(&mut logits);
+        Cow::Owned(logits)
+    }
+
     pub fn decode(&self, logits: &Array2<f32>) -> Result<String> {
         let time_steps = logits.shape()[0];
 
@@ -128,24 +226,116 @@ impl ParakeetDecoder {
         hop_length: usize,
         sample_rate: usize,
     ) -> Result<TranscriptionResult> {
+        // Confidences come from the model's own distribution, not the controlled one
+        let frame_confidences = self.frame_confidences(logits);
+        let logits = &*self.controlled(logits);
         let time_steps = logits.shape()[0];
+        let log_probs = log_softmax(logits);
 
         let mut token_ids_with_frames = Vec::new();
-        for t in 0..time_steps {
-            let logits_t = logits.row(t);
-            let max_idx = logits_t
-                .iter()
-                .enumerate()
-                .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
-                .map(|(idx, _)| idx)
-                .unwrap_or(0);
-
-            token_ids_with_frames.push((max_idx as u32, t));
+        if let Some(bias) = &self.bias {
+            token_ids_with_frames = self.biased_greedy(&log_probs, bias);
+        } else {
+            for t in 0..time_steps {
+                let logits_t = logits.row(t);
+                let max_idx = logits_t
+                    .iter()
+                    .enumerate()
+                    .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+                    .map(|(idx, _)| idx)
+                    .unwrap_or(0);
+
+                token_ids_with_frames.push((max_idx as u32, t));
+            }
         }
 
         // CTC collapse with frame tracking
         let collapsed_with_frames = self.ctc_collapse_with_frames(&token_ids_with_frames);
 
+        // A token's confidence covers the frames it was emitted on; its time span may
+        // end earlier (before a blank) or later (trailing blanks at the end)
+        let confidences: Vec<f32> = collapsed_with_frames
+            .iter()
+            .map(|&(id, start, _)| {
+                mean(
+                    token_ids_with_frames[start..]
+                        .iter()
+                        .take_while(|&&(token_id, _)| token_id == id)
+                        .map(|&(_, t)| frame_confidences[t]),
+                )
+            })
+            .collect();
+
+        let mut result = self.timed_result(
+            &collapsed_with_frames,
+            &confidences,
+            hop_length,
+            sample_rate,
+        )?;
+
+        let log_prob = token_ids_with_frames
+            .iter()
+            .map(|&(id, t)| log_probs[[t, id as usize]])
+            .sum();
+        result.n_best = vec![Hypothesis {
+            text: result.text.clone(),
+            tokens: result.tokens.clone(),
+            log_prob,
+            lm_score: 0.0,
+            score: log_prob,
+        }];
+        result.confusion_network = Some(ConfusionNetwork::from_hypotheses(&result.n_best));
+        Ok(result)
+    }
+
+    fn frame_confidences(&self, logits: &Array2<f32>) -> Vec<f32> {
+        logits
+            .rows()
+            .into_iter()
+            .map(|row| self.confidence.compute(&row.to_vec()))
+            .collect()
+    }
+
+    // Greedy path with hotword boosts added to the log-probabilities of tokens that
+    // continue or start a phrase
+    fn biased_greedy(&self, log_probs: &Array2<f32>, bias: &BiasTrie) -> Vec<(u32, usize)> {
+        let mut state = BiasState::default();
+        let mut prev = self.pad_token_id as u32;
+        let mut path = Vec::with_capacity(log_probs.nrows());
+
+        for (t, row) in log_probs.rows().into_iter().enumerate() {
+            let (mut best, mut best_score) = (0, f32::NEG_INFINITY);
+            for (id, &lp) in row.iter().enumerate() {
+                if lp > best_score {
+                    (best, best_score) = (id as u32, lp);
+                }
+            }
+            for (id, boost) in bias.boosts(state) {
+                if let Some(&lp) = row.get(id as usize) {
+                    if id as usize != self.pad_token_id && lp + boost > best_score {
+                        (best, best_score) = (id, lp + boost);
+                    }
+                }
+            }
+
+            if best != self.pad_token_id as u32 && best != prev {
+                state = bias.advance(state, best);
+            }
+            prev = best;
+            path.push((best, t));
+        }
+        path
+    }
+
+    // Text and per-token timestamps for collapsed (token_id, start_frame, end_frame) triples
+    // and their confidences
+    fn timed_result(
+        &self,
+        collapsed_with_frames: &[(u32, usize, usize)],
+        confidences: &[f32],
+        hop_length: usize,
+        sample_rate: usize,
+    ) -> Result<TranscriptionResult> {
         // Extract just token IDs for decoding
         let token_ids: Vec<u32> = collapsed_with_frames.iter().map(|(id, _, _)| *id).collect();
 
@@ -183,6 +373,8 @@ impl ParakeetDecoder {
                         text: added_text.to_string(),
                         start: start_time,
                         end: end_time,
+                        channel: None,
+                        confidence: confidences.get(i).copied(),
                     });
                 }
 
@@ -190,22 +382,126 @@ impl ParakeetDecoder {
             }
         }
 
+        let to_secs = |frame: usize| (frame * hop_length) as f32 / sample_rate as f32;
+        let hotwords = match &self.bias {
+            Some(bias) => bias
+                .find_matches(&token_ids)
+                .into_iter()
+                .map(|(phrase, first, last)| HotwordMatch {
+                    phrase: phrase.to_string(),
+                    start: to_secs(collapsed_with_frames[first].1),
+                    end: to_secs(collapsed_with_frames[last].2),
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
         Ok(TranscriptionResult {
             text: full_text,
             tokens: timed_tokens,
+            hotwords,
+            n_best: Vec::new(),
+            confusion_network: None,
         })
     }
 
-    // Stub - falls back to greedy decoding. Full beam search with language model is TODO.
+    /// Decode with CTC prefix beam search.
+    ///
+    /// Logits are log-softmaxed first. With `config.language_model` set, hypotheses are
+    /// rescored with the n-gram LM as they grow (shallow fusion); hotwords from
+    /// [`ParakeetDecoder::set_context_biasing`] are boosted the same way.
+    ///
+    /// Timestamps come from the most probable alignment of each hypothesis, with the
+    /// same frame spans as [`ParakeetDecoder::decode_with_timestamps`] gives that path.
+    pub fn decode_beam_with_timestamps(
+        &self,
+        logits: &Array2<f32>,
+        config: &BeamSearchConfig,
+        hop_length: usize,
+        sample_rate: usize,
+    ) -> Result<TranscriptionResult> {
+        let log_probs = log_softmax(&self.controlled(logits));
+        let pieces: Vec<String> = match &config.language_model {
+            Some(_) => (0..log_probs.ncols() as u32)
+                .map(|id| self.tokenizer.id_to_token(id).unwrap_or_default())
+                .collect(),
+            None => Vec::new(),
+        };
+        let scorer = config
+            .language_model
+            .as_ref()
+            .map(|lm| LmScorer::new(lm, &pieces));
+        let fusion = Fusion {
+            lm: scorer.as_ref(),
+            bias: self.bias.as_ref(),
+        };
+        let hypotheses = prefix_beam_search(&log_probs, self.pad_token_id, config, fusion);
+
+        // From the model's distribution, before token controls
+        let frame_confidences = self.frame_confidences(logits);
+        let mut results = Vec::with_capacity(hypotheses.len());
+        for hypothesis in &hypotheses {
+            let mut path: Vec<(u32, usize)> = (0..log_probs.nrows())
+                .map(|t| (self.pad_token_id as u32, t))
+                .collect();
+            for (&id, &(start, end)) in hypothesis.tokens.iter().zip(&hypothesis.frames) {
+                for step in &mut path[start..end] {
+                    step.0 = id;
+                }
+            }
+            let collapsed = self.ctc_collapse_with_frames(&path);
+            let confidences: Vec<f32> = hypothesis
+                .frames
+                .iter()
+                .map(|&(start, end)| mean(frame_confidences[start..end].iter().copied()))
+                .collect();
+            results.push(self.timed_result(&collapsed, &confidences, hop_length, sample_rate)?);
+        }
+
+        let n_best: Vec<Hypothesis> = results
+            .iter()
+            .zip(&hypotheses)
+            .map(|(result, hypothesis)| Hypothesis {
+                text: result.text.clone(),
+                tokens: result.tokens.clone(),
+                log_prob: hypothesis.log_prob,
+                lm_score: hypothesis.lm_score,
+                score: hypothesis.score(),
+            })
+            .collect();
+        let mut best = results.into_iter().next().unwrap_or_default();
+        best.confusion_network = Some(ConfusionNetwork::from_hypotheses(&n_best));
+        best.n_best = n_best;
+        Ok(best)
+    }
+
+    /// Text of the best hypothesis from [`ParakeetDecoder::decode_beam_with_timestamps`]
+    /// with `beam_width` beams and no language model
     pub fn decode_with_beam_search(
         &self,
         logits: &Array2<f32>,
-        _beam_width: usize,
+        beam_width: usize,
     ) -> Result<String> {
-        self.decode(logits)
+        let config = BeamSearchConfig::new(beam_width);
+        Ok(self
+            .decode_beam_with_timestamps(logits, &config, 1, 1)?
+            .text)
     }
 
     pub fn pad_token_id(&self) -> usize {
         self.pad_token_id
     }
+
+    pub(crate) fn tokenizer(&self) -> &tokenizers::Tokenizer {
+        &self.tokenizer
+    }
+}
+
+fn mean(values: impl Iterator<Item = f32>) -> f32 {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), x| (sum + x, count + 1));
+    if count == 0 {
+        0.0
+    } else {
+        sum / count as f32
+    }
 }
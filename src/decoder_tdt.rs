@@ -1,26 +1,78 @@
+use crate::biasing::{BiasTrie, ContextBiasing, HotwordMatch};
 use crate::decoder::TranscriptionResult;
-use crate::error::Result;
+use crate::error::{Error, Result};
+use crate::tdt_beam::{duration_frames, DEFAULT_DURATION_BINS};
 use crate::vocab::Vocabulary;
 
 /// TDT greedy decoder for Parakeet TDT models
 #[derive(Debug)]
 pub struct ParakeetTDTDecoder {
     vocab: Vocabulary,
+    bias: Option<BiasTrie>,
+    duration_bins: Vec<usize>,
 }
 
 impl ParakeetTDTDecoder {
     /// Load decoder from vocab file
     pub fn from_vocab(vocab: Vocabulary) -> Self {
-        Self { vocab }
+        Self {
+            vocab,
+            bias: None,
+            duration_bins: DEFAULT_DURATION_BINS.to_vec(),
+        }
+    }
+
+    /// Use the model's duration bins (`model_defaults.tdt_durations` in NeMo configs):
+    /// the number of encoder frames each duration output stands for
+    pub fn with_duration_bins(mut self, duration_bins: Vec<usize>) -> Self {
+        if !duration_bins.is_empty() {
+            self.duration_bins = duration_bins;
+        }
+        self
+    }
+
+    /// Set the hotwords to boost (`None` turns biasing off). Phrases are tokenized
+    /// against the vocabulary; a phrase it cannot spell is an error.
+    pub fn set_context_biasing(&mut self, biasing: Option<&ContextBiasing>) -> Result<()> {
+        self.bias = match biasing {
+            Some(biasing) => {
+                let mut entries = Vec::with_capacity(biasing.hotwords.len());
+                for hotword in &biasing.hotwords {
+                    let ids = self.vocab.encode(&hotword.phrase).ok_or_else(|| {
+                        Error::Tokenizer(format!(
+                            "Hotword '{}' cannot be tokenized with this vocabulary",
+                            hotword.phrase
+                        ))
+                    })?;
+                    entries.push((hotword.clone(), ids));
+                }
+                Some(BiasTrie::new(entries)).filter(|trie| !trie.is_empty())
+            }
+            None => None,
+        };
+        Ok(())
+    }
+
+    pub(crate) fn bias(&self) -> Option<&BiasTrie> {
+        self.bias.as_ref()
+    }
+
+    pub fn vocab(&self) -> &Vocabulary {
+        &self.vocab
     }
 
     /// Decode tokens with timestamps
-    /// For TDT models, greedy decoding is done in the model, here we just convert to text
+    /// For TDT models, greedy decoding is done in the model, here we just convert to text.
+    /// `durations` holds the predicted duration output of each token, mapped to frames
+    /// through the duration bins: a token ends that many frames (at least one) after its
+    /// start, or where the next token starts if that is earlier. Time up to the next
+    /// token stays a gap, so pauses are not absorbed into the preceding word. Without
+    /// durations a token ends where the next one starts.
     pub fn decode_with_timestamps(
         &self,
         tokens: &[usize],
         frame_indices: &[usize],
-        _durations: &[usize],
+        durations: &[usize],
         hop_length: usize,
         sample_rate: usize,
     ) -> Result<TranscriptionResult> {
@@ -28,16 +80,13 @@ impl ParakeetTDTDecoder {
         let mut full_text = String::new();
         // TDT encoder does 8x subsampling
         let encoder_stride = 8;
+        let frame_to_secs =
+            |frame: usize| (frame * encoder_stride * hop_length) as f32 / sample_rate as f32;
 
         for (i, &token_id) in tokens.iter().enumerate() {
             if let Some(token_text) = self.vocab.id_to_text(token_id) {
-                let frame = frame_indices[i];
-                let start = (frame * encoder_stride * hop_length) as f32 / sample_rate as f32;
-                let end = if i + 1 < frame_indices.len() {
-                    (frame_indices[i + 1] * encoder_stride * hop_length) as f32 / sample_rate as f32
-                } else {
-                    start + 0.01
-                };
+                let start = frame_to_secs(frame_indices[i]);
+                let end = frame_to_secs(self.end_frame(i, frame_indices, durations));
 
                 // Handle SentencePiece format (▁ prefix for word start)
                 let mut display_text = token_text.replace('▁', " ");
@@ -62,26 +111,96 @@ impl ParakeetTDTDecoder {
                 }
 
                 // Skip special tokens
-                if !(token_text.starts_with('<')
-                    && token_text.ends_with('>')
-                    && token_text != "<unk>")
-                {
+                if self.is_shown(token_id) {
                     full_text.push_str(&display_text);
 
                     result_tokens.push(crate::decoder::TimedToken {
                         text: display_text,
                         start,
                         end,
+                        channel: None,
+                        confidence: None,
                     });
                 }
             }
         }
 
+        let hotwords = match &self.bias {
+            Some(bias) => {
+                let ids: Vec<u32> = tokens.iter().map(|&id| id as u32).collect();
+                bias.find_matches(&ids)
+                    .into_iter()
+                    .map(|(phrase, first, last)| {
+                        let start = frame_to_secs(frame_indices[first]);
+                        let end = frame_to_secs(self.end_frame(last, frame_indices, durations));
+                        HotwordMatch {
+                            phrase: phrase.to_string(),
+                            start,
+                            end,
+                        }
+                    })
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+
         Ok(TranscriptionResult {
             text: full_text.trim().to_string(),
             tokens: result_tokens,
+            hotwords,
+            n_best: Vec::new(),
+            confusion_network: None,
         })
     }
+
+    /// Like [`ParakeetTDTDecoder::decode_with_timestamps`], with the confidence of each
+    /// token (`confidences` holds one value per token)
+    pub fn decode_with_confidences(
+        &self,
+        tokens: &[usize],
+        frame_indices: &[usize],
+        durations: &[usize],
+        confidences: &[f32],
+        hop_length: usize,
+        sample_rate: usize,
+    ) -> Result<TranscriptionResult> {
+        let mut result =
+            self.decode_with_timestamps(tokens, frame_indices, durations, hop_length, sample_rate)?;
+        let shown = tokens
+            .iter()
+            .zip(confidences)
+            .filter(|(&token_id, _)| self.is_shown(token_id))
+            .map(|(_, &confidence)| confidence);
+        for (token, confidence) in result.tokens.iter_mut().zip(shown) {
+            token.confidence = Some(confidence);
+        }
+        Ok(result)
+    }
+
+    // Whether a token appears in the transcript (special tokens other than <unk> do not)
+    fn is_shown(&self, token_id: usize) -> bool {
+        self.vocab
+            .id_to_text(token_id)
+            .is_some_and(|text| !(text.starts_with('<') && text.ends_with('>') && text != "<unk>"))
+    }
+
+    // Frame token `i` ends on (exclusive)
+    fn end_frame(&self, i: usize, frame_indices: &[usize], durations: &[usize]) -> usize {
+        let frame = frame_indices[i];
+        let next = frame_indices
+            .get(i + 1)
+            .copied()
+            .filter(|&next| next > frame);
+        let duration = durations
+            .get(i)
+            .map(|&index| duration_frames(&self.duration_bins, index).max(1));
+        match (duration, next) {
+            (Some(duration), Some(next)) => (frame + duration).min(next),
+            (Some(duration), None) => frame + duration,
+            (None, Some(next)) => next,
+            (None, None) => frame + 1,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -89,10 +208,7 @@ mod tests {
     use super::*;
 
     fn make_vocab(tokens: &[&str]) -> Vocabulary {
-        Vocabulary {
-            id_to_token: tokens.iter().map(|s| s.to_string()).collect(),
-            _blank_id: 0,
-        }
+        Vocabulary::new(tokens.iter().map(|s| s.to_string()).collect(), 0)
     }
 
     #[test]
@@ -145,7 +261,13 @@ mod tests {
         let vocab = make_vocab(&["▁In", "2", "0", "2", "1"]);
         let decoder = ParakeetTDTDecoder::from_vocab(vocab);
         let result = decoder
-            .decode_with_timestamps(&[0, 1, 2, 3, 4], &[0, 1, 2, 3, 4], &[1, 1, 1, 1, 1], 160, 16000)
+            .decode_with_timestamps(
+                &[0, 1, 2, 3, 4],
+                &[0, 1, 2, 3, 4],
+                &[1, 1, 1, 1, 1],
+                160,
+                16000,
+            )
             .unwrap();
         assert_eq!(result.text, "In 2021");
     }
@@ -166,6 +288,26 @@ mod tests {
         assert_eq!(result.tokens[3].text, "0");
     }
 
+    #[test]
+    fn test_context_biasing_reports_hotwords() {
+        let vocab = make_vocab(&["▁n", "vid", "ia", "▁is", "<blk>"]);
+        assert_eq!(vocab.encode("nvidia is"), Some(vec![0, 1, 2, 3]));
+        assert_eq!(vocab.encode("xyz"), None);
+
+        let mut decoder = ParakeetTDTDecoder::from_vocab(vocab);
+        let biasing = ContextBiasing::new().add("nvidia", 2.0);
+        decoder.set_context_biasing(Some(&biasing)).unwrap();
+        let result = decoder
+            .decode_with_timestamps(&[3, 0, 1, 2], &[0, 2, 3, 4], &[1, 1, 1, 1], 160, 16000)
+            .unwrap();
+        assert_eq!(result.hotwords.len(), 1);
+        assert_eq!(result.hotwords[0].phrase, "nvidia");
+        assert!((result.hotwords[0].start - 0.16).abs() < 1e-6);
+
+        let unknown = ContextBiasing::new().add("xyz", 1.0);
+        assert!(decoder.set_context_biasing(Some(&unknown)).is_err());
+    }
+
     #[test]
     fn test_full_flow_with_timestamp_processing() {
         use crate::timestamps::{process_timestamps, TimestampMode};
@@ -184,11 +326,59 @@ mod tests {
 
         // Test Words mode (what Undertone likely uses)
         let words = process_timestamps(&result.tokens, TimestampMode::Words);
-        let text: String = words.iter().map(|t| t.text.as_str()).collect::<Vec<_>>().join(" ");
+        let text: String = words
+            .iter()
+            .map(|t| t.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
         assert_eq!(text, "like 100 bucks");
 
         // Test Tokens mode
         let tokens_text: String = result.tokens.iter().map(|t| t.text.as_str()).collect();
         assert_eq!(tokens_text.trim(), "like 100 bucks");
     }
+
+    #[test]
+    fn test_decode_with_confidences() {
+        let vocab = make_vocab(&["▁a", "b"]);
+        let decoder = ParakeetTDTDecoder::from_vocab(vocab);
+        let result = decoder
+            .decode_with_confidences(&[0, 1], &[0, 1], &[1, 1], &[0.9, 0.4], 160, 16000)
+            .unwrap();
+        let confidences: Vec<_> = result.tokens.iter().map(|t| t.confidence).collect();
+        assert_eq!(confidences, vec![Some(0.9), Some(0.4)]);
+    }
+
+    #[test]
+    fn test_word_ends_before_pause() {
+        use crate::timestamps::{process_timestamps, TimestampMode};
+
+        // "hello" lasts 3 frames, then 2s of silence before "world" on frame 25
+        let vocab = make_vocab(&["▁hello", "▁wor", "ld"]);
+        let decoder = ParakeetTDTDecoder::from_vocab(vocab);
+        let result = decoder
+            .decode_with_timestamps(&[0, 1, 2], &[0, 25, 26], &[3, 1, 2], 160, 16000)
+            .unwrap();
+
+        let words = process_timestamps(&result.tokens, TimestampMode::Words);
+        assert_eq!(words.len(), 2);
+        assert!((words[0].end - 0.24).abs() < 1e-6);
+        assert!((words[1].start - 2.0).abs() < 1e-6);
+        assert!((words[1].end - 2.24).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_durations_map_through_bins() {
+        let vocab = make_vocab(&["▁a", "b", "▁c"]);
+        let decoder = ParakeetTDTDecoder::from_vocab(vocab).with_duration_bins(vec![0, 2, 4, 8]);
+        let result = decoder
+            .decode_with_timestamps(&[0, 1, 2], &[0, 0, 20], &[0, 3, 2], 160, 16000)
+            .unwrap();
+
+        let ends: Vec<f32> = result.tokens.iter().map(|t| t.end).collect();
+        // Duration 0 still spans a frame; bin 3 is 8 frames; bin 2 is 4 frames
+        assert!((ends[0] - 0.08).abs() < 1e-6);
+        assert!((ends[1] - 0.64).abs() < 1e-6);
+        assert!((ends[2] - 1.92).abs() < 1e-6);
+    }
 }
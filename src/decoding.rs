@@ -0,0 +1,292 @@
+//! Decoding strategy selection shared by the CTC and TDT models.
+
+use crate::biasing::{BiasState, BiasTrie, ContextBiasing};
+use crate::confidence::ConfidenceMeasure;
+use crate::error::{Error, Result};
+use crate::lm::{LanguageModel, LmScorer, LmState};
+use ndarray::Array2;
+use std::collections::{HashMap, HashSet};
+
+/// How token sequences are searched for in the model output
+#[derive(Debug, Clone, Default)]
+pub enum DecodingStrategy {
+    /// Best token per frame (fastest, the default)
+    #[default]
+    Greedy,
+    /// Keep the `beam_width` most probable hypotheses while decoding
+    Beam(BeamSearchConfig),
+}
+
+/// Beam search settings
+#[derive(Debug, Clone)]
+pub struct BeamSearchConfig {
+    /// Number of hypotheses kept after every frame
+    pub beam_width: usize,
+    /// Tokens whose log-probability is this far below the best token of a frame are
+    /// not expanded (e.g. -10.0; `f32::NEG_INFINITY` expands every token). TDT applies
+    /// the same threshold to durations.
+    pub token_prune_threshold: f32,
+    /// Frames where the blank probability exceeds this value are skipped entirely,
+    /// which speeds up search on long pauses without changing the result much (CTC only)
+    pub blank_skip_threshold: Option<f32>,
+    /// N-gram LM for shallow fusion
+    pub language_model: Option<LanguageModel>,
+}
+
+impl Default for BeamSearchConfig {
+    fn default() -> Self {
+        Self {
+            beam_width: 8,
+            token_prune_threshold: -10.0,
+            blank_skip_threshold: None,
+            language_model: None,
+        }
+    }
+}
+
+impl BeamSearchConfig {
+    pub fn new(beam_width: usize) -> Self {
+        Self {
+            beam_width,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_language_model(mut self, language_model: LanguageModel) -> Self {
+        self.language_model = Some(language_model);
+        self
+    }
+}
+
+/// Decoding options of a model, e.g. [`crate::Parakeet::set_decoding_options`]
+///
+/// The token controls (`logit_bias`, `suppressed_tokens`, `blank_penalty`) change the
+/// model's token logits before anything else looks at them, so they apply the same way
+/// to greedy and beam decoding, hotwords and confidences. Token ids are those of the
+/// model's `tokenizer.json` or `vocab.txt`.
+#[derive(Debug, Clone, Default)]
+pub struct DecodingOptions {
+    pub strategy: DecodingStrategy,
+    /// Hotwords to boost, used by greedy and beam decoding
+    pub context_biasing: Option<ContextBiasing>,
+    /// How [`crate::TimedToken::confidence`] is computed
+    pub confidence: ConfidenceMeasure,
+    /// Added to the logit of a token: positive values favour it, negative ones penalize it
+    pub logit_bias: HashMap<u32, f32>,
+    /// Tokens that are never emitted. The blank token cannot be suppressed.
+    pub suppressed_tokens: HashSet<u32>,
+    /// Subtracted from the blank logit. Positive values make the model emit more
+    /// tokens, which helps models that drop words; negative ones make it emit fewer.
+    pub blank_penalty: f32,
+    /// Most tokens emitted on one encoder frame by the transducers (TDT and EOU); `None`
+    /// keeps the model default (10 for TDT, 5 for EOU). CTC emits one token per frame.
+    pub max_symbols_per_step: Option<usize>,
+}
+
+impl DecodingOptions {
+    pub fn greedy() -> Self {
+        Self::default()
+    }
+
+    /// Beam search with `beam_width` hypotheses and default pruning
+    pub fn beam(beam_width: usize) -> Self {
+        Self {
+            strategy: DecodingStrategy::Beam(BeamSearchConfig::new(beam_width)),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_context_biasing(mut self, context_biasing: ContextBiasing) -> Self {
+        self.context_biasing = Some(context_biasing);
+        self
+    }
+
+    pub fn with_confidence(mut self, confidence: ConfidenceMeasure) -> Self {
+        self.confidence = confidence;
+        self
+    }
+
+    pub fn with_logit_bias(mut self, token: u32, bias: f32) -> Self {
+        self.logit_bias.insert(token, bias);
+        self
+    }
+
+    pub fn with_suppressed_tokens(mut self, tokens: impl IntoIterator<Item = u32>) -> Self {
+        self.suppressed_tokens.extend(tokens);
+        self
+    }
+
+    pub fn with_blank_penalty(mut self, blank_penalty: f32) -> Self {
+        self.blank_penalty = blank_penalty;
+        self
+    }
+
+    pub fn with_max_symbols_per_step(mut self, max_symbols_per_step: usize) -> Self {
+        self.max_symbols_per_step = Some(max_symbols_per_step);
+        self
+    }
+}
+
+/// The token controls of [`DecodingOptions`], checked against one model's vocabulary
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TokenControls {
+    // (token, offset) added to the logits; suppressed tokens get -inf
+    offsets: Vec<(usize, f32)>,
+    max_symbols_per_step: Option<usize>,
+}
+
+impl TokenControls {
+    /// `vocab_size` counts the blank token `blank`
+    pub fn new(options: &DecodingOptions, blank: usize, vocab_size: usize) -> Result<Self> {
+        let check = |token: u32, what: &str| {
+            if token as usize >= vocab_size {
+                return Err(Error::Config(format!(
+                    "Token {token} in {what} is out of range for a vocabulary of {vocab_size}"
+                )));
+            }
+            Ok(token as usize)
+        };
+
+        let mut offsets = Vec::new();
+        for (&token, &bias) in &options.logit_bias {
+            if !options.suppressed_tokens.contains(&token) {
+                offsets.push((check(token, "logit_bias")?, bias));
+            }
+        }
+        for &token in &options.suppressed_tokens {
+            let token = check(token, "suppressed_tokens")?;
+            if token == blank {
+                return Err(Error::Config(
+                    "The blank token cannot be suppressed; use blank_penalty".to_string(),
+                ));
+            }
+            offsets.push((token, f32::NEG_INFINITY));
+        }
+        if options.blank_penalty != 0.0 {
+            match offsets.iter_mut().find(|(token, _)| *token == blank) {
+                Some((_, offset)) => *offset -= options.blank_penalty,
+                None => offsets.push((blank, -options.blank_penalty)),
+            }
+        }
+        offsets.sort_by_key(|&(token, _)| token);
+
+        if options.max_symbols_per_step == Some(0) {
+            return Err(Error::Config(
+                "max_symbols_per_step must be at least 1".to_string(),
+            ));
+        }
+        Ok(Self {
+            offsets,
+            max_symbols_per_step: options.max_symbols_per_step,
+        })
+    }
+
+    /// Adjust the logits of one step
+    pub fn apply(&self, logits: &mut [f32]) {
+        for &(token, offset) in &self.offsets {
+            if let Some(logit) = logits.get_mut(token) {
+                *logit += offset;
+            }
+        }
+    }
+
+    /// Adjust `(frames, vocab)` logits
+    pub fn apply_rows(&self, logits: &mut Array2<f32>) {
+        for &(token, offset) in &self.offsets {
+            if token < logits.ncols() {
+                logits
+                    .column_mut(token)
+                    .map_inplace(|logit| *logit += offset);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Symbol limit per frame, `default` unless the options set one
+    pub fn max_symbols(&self, default: usize) -> usize {
+        self.max_symbols_per_step.unwrap_or(default)
+    }
+}
+
+/// Scores added to the acoustic score of beam search hypotheses
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Fusion<'a> {
+    pub lm: Option<&'a LmScorer<'a>>,
+    pub bias: Option<&'a BiasTrie>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FusionState {
+    lm: LmState,
+    bias: BiasState,
+}
+
+impl FusionState {
+    /// Fused score so far, used for ranking
+    pub fn score(&self) -> f32 {
+        self.lm.score + self.bias.score
+    }
+}
+
+impl Fusion<'_> {
+    pub fn start(&self) -> FusionState {
+        FusionState {
+            lm: self.lm.map(LmScorer::start).unwrap_or_default(),
+            bias: BiasState::default(),
+        }
+    }
+
+    pub fn advance(&self, state: &FusionState, token: u32) -> FusionState {
+        FusionState {
+            lm: match self.lm {
+                Some(lm) => lm.advance(&state.lm, token),
+                None => LmState::default(),
+            },
+            bias: match self.bias {
+                Some(bias) => bias.advance(state.bias, token),
+                None => BiasState::default(),
+            },
+        }
+    }
+
+    /// `(lm_score, bias_score)` of a complete hypothesis
+    pub fn finish(&self, state: &FusionState) -> (f32, f32) {
+        (
+            self.lm.map_or(0.0, |lm| lm.finish(&state.lm)),
+            self.bias.map_or(0.0, |bias| bias.finish(state.bias)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_controls() {
+        let options = DecodingOptions::greedy()
+            .with_logit_bias(0, 1.5)
+            .with_logit_bias(1, 2.0)
+            .with_suppressed_tokens([1])
+            .with_blank_penalty(0.5);
+        let controls = TokenControls::new(&options, 3, 4).unwrap();
+        let mut logits = [0.0, 0.0, 0.0, 0.0];
+        controls.apply(&mut logits);
+        assert_eq!(logits, [1.5, f32::NEG_INFINITY, 0.0, -0.5]);
+
+        let mut rows = Array2::zeros((2, 4));
+        controls.apply_rows(&mut rows);
+        assert_eq!(rows.row(1).to_vec(), logits.to_vec());
+        assert_eq!(controls.max_symbols(10), 10);
+
+        let blank = DecodingOptions::greedy().with_suppressed_tokens([3]);
+        assert!(TokenControls::new(&blank, 3, 4).is_err());
+        let range = DecodingOptions::greedy().with_logit_bias(4, 1.0);
+        assert!(TokenControls::new(&range, 3, 4).is_err());
+        let zero = DecodingOptions::greedy().with_max_symbols_per_step(0);
+        assert!(TokenControls::new(&zero, 3, 4).is_err());
+    }
+}
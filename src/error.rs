@@ -10,6 +10,24 @@ pub enum Error {
     Model(String),
     Tokenizer(String),
     Config(String),
+    /// A transcript cannot be aligned to the audio, e.g. it has more tokens than frames
+    Alignment(String),
+    /// Input audio has no samples
+    EmptyAudio,
+    /// Input audio is shorter than the model's analysis window
+    AudioTooShort {
+        duration: f32,
+        min_duration: f32,
+    },
+    /// Input audio contains a NaN or infinite sample at `index`
+    NonFiniteAudio {
+        index: usize,
+    },
+    /// Input audio is longer than the model supports in one pass
+    AudioTooLong {
+        duration: f32,
+        max_duration: f32,
+    },
 }
 
 impl fmt::Display for Error {
@@ -21,6 +39,26 @@ impl fmt::Display for Error {
             Error::Model(msg) => write!(f, "Model error: {msg}"),
             Error::Tokenizer(msg) => write!(f, "Tokenizer error: {msg}"),
             Error::Config(msg) => write!(f, "Config error: {msg}"),
+            Error::Alignment(msg) => write!(f, "Alignment error: {msg}"),
+            Error::EmptyAudio => write!(f, "Input audio is empty"),
+            Error::AudioTooShort {
+                duration,
+                min_duration,
+            } => write!(
+                f,
+                "Input audio is too short: {duration:.3}s, the model needs at least {min_duration:.3}s"
+            ),
+            Error::NonFiniteAudio { index } => {
+                write!(f, "Input audio contains a non-finite sample at index {index}")
+            }
+            Error::AudioTooLong {
+                duration,
+                max_duration,
+            } => write!(
+                f,
+                "Input audio is too long: {duration:.1}s, the model supports at most {max_duration:.1}s \
+                 (split it, e.g. with transcribe_speech_segments)"
+            ),
         }
     }
 }
@@ -50,3 +88,10 @@ impl From<hound::Error> for Error {
         Error::Audio(e.to_string())
     }
 }
+
+#[cfg(feature = "symphonia")]
+impl From<symphonia::core::errors::Error> for Error {
+    fn from(e: symphonia::core::errors::Error) -> Self {
+        Error::Audio(e.to_string())
+    }
+}
@@ -0,0 +1,56 @@
+use ndarray::Array2;
+
+/// Frame-level model output on the audio's time axis: CTC log-probabilities
+/// ([`crate::Parakeet::log_probs`]) or encoder embeddings
+/// ([`crate::ParakeetTDT::encoder_output`], [`crate::ParakeetEOU::encoder_frames`])
+#[derive(Debug, Clone, Default)]
+pub struct Frames {
+    /// One row per frame, shape `(frames, dim)`
+    pub values: Array2<f32>,
+    /// Start of frame 0 in seconds
+    pub start: f32,
+    /// Seconds between consecutive frames (the encoder's subsampling times the hop)
+    pub frame_secs: f32,
+}
+
+impl Frames {
+    pub fn len(&self) -> usize {
+        self.values.nrows()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.nrows() == 0
+    }
+
+    /// Start time of `frame` in seconds
+    pub fn time(&self, frame: usize) -> f32 {
+        self.start + frame as f32 * self.frame_secs
+    }
+
+    /// Frame covering `secs`, if any
+    pub fn frame_at(&self, secs: f32) -> Option<usize> {
+        if self.frame_secs <= 0.0 || secs < self.start {
+            return None;
+        }
+        let frame = ((secs - self.start) / self.frame_secs) as usize;
+        (frame < self.len()).then_some(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_mapping() {
+        let frames = Frames {
+            values: Array2::zeros((10, 4)),
+            start: 1.0,
+            frame_secs: 0.08,
+        };
+        assert!((frames.time(5) - 1.4).abs() < 1e-6);
+        assert_eq!(frames.frame_at(1.41), Some(5));
+        assert_eq!(frames.frame_at(0.5), None);
+        assert_eq!(frames.frame_at(1.8), None);
+    }
+}
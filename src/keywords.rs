@@ -0,0 +1,142 @@
+//! Keyword spotting over CTC log-probabilities.
+//!
+//! Each phrase is aligned to the log-probabilities with a sliding CTC alignment: the
+//! phrase's tokens (with optional blanks between them) may start on any frame and end
+//! on any later one, and every frame is scored by how far the phrase's label falls
+//! below the frame's best label. A phrase that greedy decoding would produce costs
+//! nothing; a misspelled or mumbled one costs a little on the frames where the
+//! decoder preferred another token. The score of a detection is
+//! `exp(total cost / aligned frames)` in `(0, 1]`: the geometric mean over its frames of
+//! the phrase label's probability relative to the best label. It ranks detections but is
+//! not a probability that the phrase was said.
+
+use ndarray::Array2;
+
+/// A spoken occurrence of a keyword phrase, see [`crate::Parakeet::spot_keywords`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeywordDetection {
+    /// The phrase as given
+    pub phrase: String,
+    /// Start and end in seconds
+    pub start: f32,
+    pub end: f32,
+    /// 1 when the phrase is exactly the most probable path over its frames, lower the
+    /// more (and the longer) the model preferred other tokens
+    pub score: f32,
+}
+
+/// Non-overlapping occurrences of `tokens` in `log_probs` (`(frames, vocab)`) scoring at
+/// least `threshold`, as `(start_frame, end_frame_exclusive, score)` in frame order
+pub(crate) fn spot(
+    log_probs: &Array2<f32>,
+    tokens: &[u32],
+    blank: usize,
+    threshold: f32,
+) -> Vec<(usize, usize, f32)> {
+    if tokens.is_empty() {
+        return Vec::new();
+    }
+    // Even state 2k is token k, odd states are the blanks between tokens
+    let states = 2 * tokens.len() - 1;
+    let label = |state: usize| match state % 2 {
+        0 => tokens[state / 2] as usize,
+        _ => blank,
+    };
+    let can_skip = |state: usize| {
+        state.is_multiple_of(2) && state >= 2 && tokens[state / 2] != tokens[state / 2 - 1]
+    };
+
+    // (cost, start frame) of the best partial alignment in each state
+    let mut current = vec![(f32::NEG_INFINITY, 0usize); states];
+    let mut candidates = Vec::new();
+    for (t, row) in log_probs.rows().into_iter().enumerate() {
+        let best = row.iter().fold(f32::NEG_INFINITY, |m, &x| m.max(x));
+        let mut next = current.clone();
+        for state in 0..states {
+            let mut from = current[state];
+            if state == 0 && from.0 < 0.0 {
+                from = (0.0, t);
+            }
+            if state >= 1 && current[state - 1].0 > from.0 {
+                from = current[state - 1];
+            }
+            if can_skip(state) && current[state - 2].0 > from.0 {
+                from = current[state - 2];
+            }
+            next[state] = (from.0 + row[label(state)] - best, from.1);
+        }
+        current = next;
+
+        let (cost, start) = current[states - 1];
+        let score = (cost / (t + 1 - start) as f32).exp();
+        if score >= threshold {
+            candidates.push((start, t + 1, score));
+        }
+    }
+
+    // Best first (the longer span on ties, so a held last token is kept whole), dropping
+    // candidates that overlap a better one
+    candidates.sort_by(|a, b| b.2.total_cmp(&a.2).then(b.1.cmp(&a.1)));
+    let mut detections: Vec<(usize, usize, f32)> = Vec::new();
+    for candidate in candidates {
+        if detections
+            .iter()
+            .all(|d| candidate.1 <= d.0 || candidate.0 >= d.1)
+        {
+            detections.push(candidate);
+        }
+    }
+    detections.sort_by_key(|d| d.0);
+    detections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array;
+
+    fn peaked(best: &[usize], vocab: usize) -> Array2<f32> {
+        Array::from_shape_fn((best.len(), vocab), |(t, v)| {
+            if v == best[t] {
+                0.9f32.ln()
+            } else {
+                (0.1 / (vocab - 1) as f32).ln()
+            }
+        })
+    }
+
+    #[test]
+    fn test_spots_exact_and_misspelled() {
+        // blank = 5; "0 1 2" at frames 2..5, then "0 3 2" (one token off) at 8..11
+        let log_probs = peaked(&[5, 5, 0, 1, 2, 5, 5, 5, 0, 3, 2, 5], 6);
+        let detections = spot(&log_probs, &[0, 1, 2], 5, 0.1);
+        assert_eq!(detections.len(), 2);
+        assert_eq!((detections[0].0, detections[0].1), (2, 5));
+        assert!((detections[0].2 - 1.0).abs() < 1e-6);
+        assert_eq!((detections[1].0, detections[1].1), (8, 11));
+        assert!(detections[1].2 < 0.5);
+
+        // A high threshold keeps the exact match only
+        let strict = spot(&log_probs, &[0, 1, 2], 5, 0.9);
+        assert_eq!(strict.len(), 1);
+        assert!(spot(&log_probs, &[4, 4], 5, 0.5).is_empty());
+    }
+
+    #[test]
+    fn test_score_is_per_frame() {
+        // Each token held for three frames
+        let held = peaked(&[5, 0, 0, 0, 1, 1, 1, 2, 2, 2, 5], 6);
+        let detections = spot(&held, &[0, 1, 2], 5, 0.5);
+        assert_eq!(detections.len(), 1);
+        assert_eq!((detections[0].0, detections[0].1), (1, 10));
+        assert!((detections[0].2 - 1.0).abs() < 1e-6);
+
+        // One glitched frame out of nine costs ln(0.02 / 0.9) / 9
+        let glitched = peaked(&[5, 0, 0, 0, 1, 3, 1, 2, 2, 2, 5], 6);
+        let detections = spot(&glitched, &[0, 1, 2], 5, 0.5);
+        assert_eq!(detections.len(), 1);
+        assert_eq!((detections[0].0, detections[0].1), (1, 10));
+        let expected = ((0.02f32 / 0.9).ln() / 9.0).exp();
+        assert!((detections[0].2 - expected).abs() < 1e-4);
+    }
+}
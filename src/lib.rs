@@ -31,46 +31,86 @@
 //! Your model directory should contain:
 //! - `model.onnx` - The ONNX model file
 //! - `model.onnx_data` - External model weights
-//! - `config.json` - Model configuration
-//! - `preprocessor_config.json` - Audio preprocessing configuration
+//! - `config.json` - Model configuration (optional, defaults to Parakeet-CTC-0.6b)
+//! - `preprocessor_config.json` - Audio preprocessing configuration (optional; a NeMo
+//!   `model_config.yaml` is used when present instead)
 //! - `tokenizer.json` - Tokenizer vocabulary
 //! - `tokenizer_config.json` - Tokenizer configuration
 //!
 //! ## Audio Requirements
 //!
-//! - Format: WAV
-//! - Sample Rate: 16kHz
-//! - Channels: Mono (stereo will be converted automatically)
-//! - Bit Depth: 16-bit PCM or 32-bit float
+//! - Format: WAV (FLAC, MP3, Ogg Vorbis, M4A/AAC with the `symphonia` feature)
+//! - Sample Rate: any (resampled to the model's 16kHz internally)
+//! - Channels: Mono, or multichannel mixed down / transcribed per channel (see `ChannelStrategy`)
+//! - Encoding: 8/16/24/32-bit PCM, 32/64-bit float, G.711 A-law/µ-law (incl. WAVE_FORMAT_EXTENSIBLE)
+//!
+//! Non-speech can be skipped with the [`vad`] module: detect speech segments and pass
+//! them to `Transcriber::transcribe_speech_segments`.
 
+mod align;
 mod audio;
+mod biasing;
+mod confidence;
 mod config;
+mod confusion;
+mod ctc_beam;
 mod decoder;
 mod decoder_tdt;
+mod decoding;
 mod error;
 mod execution;
+mod frames;
+mod keywords;
+mod lm;
+mod mel;
 mod model;
 mod model_eou;
 mod model_tdt;
+mod npy;
 mod parakeet;
 mod parakeet_eou;
 mod parakeet_tdt;
 #[cfg(feature = "sortformer")]
 pub mod sortformer;
+mod tdt_beam;
 mod timestamps;
 mod transcriber;
+pub mod vad;
 mod vocab;
+mod wav;
 
+pub use align::Alignment;
+pub use audio::{
+    decode_raw_pcm, downmix, extract_channel, extract_features_raw, load_audio, resample,
+    validate_audio, AudioSpec, ChannelLayout, Endianness, InputLimits, RawAudioFormat,
+    ResampleQuality, Resampler, SampleFormat,
+};
 pub use error::{Error, Result};
 pub use execution::{ExecutionProvider, ModelConfig as ExecutionConfig};
+pub use frames::Frames;
+pub use keywords::KeywordDetection;
+pub use mel::{
+    DitherConfig, FeatureNormalization, LogGuard, MelConfig, MelFrontend, MelNorm, MelScale,
+    PaddingMode, WindowAlignment, WindowType,
+};
 pub use parakeet::Parakeet;
 pub use parakeet_tdt::ParakeetTDT;
 pub use timestamps::TimestampMode;
 pub use transcriber::*;
 
-pub use config::{ModelConfig as ModelConfigJson, PreprocessorConfig};
+pub use config::{
+    ModelConfig as ModelConfigJson, NemoDecoderConfig, NemoEncoderConfig, NemoJointConfig,
+    NemoModelConfig, NemoModelDefaults, NemoPreprocessorConfig, PreprocessorConfig,
+};
 
-pub use decoder::{ParakeetDecoder, TimedToken, TranscriptionResult};
+pub use biasing::{ContextBiasing, Hotword, HotwordMatch};
+pub use confidence::ConfidenceMeasure;
+pub use confusion::{ConfusionArc, ConfusionNetwork, ConfusionSlot};
+pub use decoder::{Hypothesis, ParakeetDecoder, TimedToken, TranscriptionResult};
+pub use decoding::{BeamSearchConfig, DecodingOptions, DecodingStrategy};
+pub use lm::{LanguageModel, LmUnit, NgramLm};
 pub use model::ParakeetModel;
 pub use model_eou::ParakeetEOUModel;
+pub use model_tdt::{ParakeetTDTModel, TDTModelConfig};
+pub use npy::{load_npy, save_npy};
 pub use parakeet_eou::ParakeetEOU;
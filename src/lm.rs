@@ -0,0 +1,517 @@
+//! N-gram language models for shallow fusion in beam search.
+//!
+//! Models are read from ARPA files (as written by KenLM's `lmplz` or SRILM) or from a
+//! compact binary form produced by [`NgramLm::save_binary`], which loads much faster.
+//! Scores are natural-log probabilities with Katz-style backoff.
+//!
+//! With [`LmUnit::Word`] the LM scores whole words, assembled from SentencePiece
+//! tokens (`▁` starts a new word). With [`LmUnit::Token`] it scores the tokens
+//! themselves, so its vocabulary must use the model's token pieces.
+
+use crate::error::{Error, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+const BINARY_MAGIC: &[u8; 8] = b"PKLMBIN1";
+// Score of a word the LM has never seen and that has no <unk> entry (log10 -10)
+const UNKNOWN_LOG_PROB: f32 = -10.0 * std::f32::consts::LN_10;
+const WORD_BOUNDARY: char = '\u{2581}';
+
+#[derive(Debug, Clone, Copy)]
+struct NgramEntry {
+    log_prob: f32,
+    backoff: f32,
+}
+
+/// Backoff n-gram language model
+#[derive(Clone)]
+pub struct NgramLm {
+    order: usize,
+    words: Vec<String>,
+    ids: HashMap<String, u32>,
+    // ngrams[n - 1] holds the n-grams
+    ngrams: Vec<HashMap<Vec<u32>, NgramEntry>>,
+}
+
+impl fmt::Debug for NgramLm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NgramLm")
+            .field("order", &self.order)
+            .field(
+                "ngrams",
+                &self.ngrams.iter().map(HashMap::len).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl NgramLm {
+    /// Load an ARPA or binary model, detected from the file contents
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)?;
+        let parsed = if bytes.starts_with(BINARY_MAGIC) {
+            Self::from_binary_bytes(&bytes)
+        } else {
+            let text = std::str::from_utf8(&bytes)
+                .map_err(|_| Error::Config("ARPA file is not valid UTF-8".to_string()))?;
+            Self::from_arpa_str(text)
+        };
+        parsed.map_err(|e| match e {
+            Error::Config(msg) => Error::Config(format!("{}: {msg}", path.display())),
+            other => other,
+        })
+    }
+
+    /// Parse a model in ARPA text format
+    pub fn from_arpa_str(text: &str) -> Result<Self> {
+        let mut lm = Self {
+            order: 0,
+            words: Vec::new(),
+            ids: HashMap::new(),
+            ngrams: Vec::new(),
+        };
+        let mut in_data = false;
+        let mut section = 0;
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let error = |msg: &str| Error::Config(format!("ARPA line {}: {msg}", index + 1));
+            if line.is_empty() {
+                continue;
+            }
+            if line == "\\data\\" {
+                in_data = true;
+                continue;
+            }
+            if line == "\\end\\" {
+                break;
+            }
+            if let Some(n) = line
+                .strip_prefix('\\')
+                .and_then(|l| l.strip_suffix("-grams:"))
+            {
+                section = n.parse().map_err(|_| error("invalid section header"))?;
+                if section == 0 {
+                    return Err(error("invalid section header"));
+                }
+                if lm.ngrams.len() < section {
+                    lm.ngrams.resize_with(section, HashMap::new);
+                }
+                in_data = false;
+                continue;
+            }
+            if in_data || section == 0 {
+                // `ngram N=count` lines and anything before \data\
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != section + 1 && fields.len() != section + 2 {
+                return Err(error(&format!("expected a {section}-gram entry")));
+            }
+            let parse = |s: &str| {
+                s.parse::<f32>()
+                    .map(|log10| log10 * std::f32::consts::LN_10)
+                    .map_err(|_| error(&format!("invalid number '{s}'")))
+            };
+            let log_prob = parse(fields[0])?;
+            let backoff = match fields.get(section + 1) {
+                Some(b) => parse(b)?,
+                None => 0.0,
+            };
+            let key: Vec<u32> = fields[1..=section]
+                .iter()
+                .map(|word| lm.intern(word))
+                .collect();
+            lm.ngrams[section - 1].insert(key, NgramEntry { log_prob, backoff });
+        }
+
+        lm.order = lm.ngrams.len();
+        if lm.order == 0 || lm.ngrams[0].is_empty() {
+            return Err(Error::Config("ARPA model has no 1-grams".to_string()));
+        }
+        Ok(lm)
+    }
+
+    /// Load a model written by [`NgramLm::save_binary`]
+    pub fn from_binary<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_binary_bytes(&std::fs::read(path)?)
+    }
+
+    /// Write the model in the crate's binary format
+    pub fn save_binary<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut out = BufWriter::new(std::fs::File::create(path)?);
+        out.write_all(BINARY_MAGIC)?;
+        out.write_all(&(self.order as u32).to_le_bytes())?;
+        out.write_all(&(self.words.len() as u32).to_le_bytes())?;
+        for word in &self.words {
+            out.write_all(&(word.len() as u32).to_le_bytes())?;
+            out.write_all(word.as_bytes())?;
+        }
+        for ngrams in &self.ngrams {
+            out.write_all(&(ngrams.len() as u64).to_le_bytes())?;
+            for (key, entry) in ngrams {
+                for id in key {
+                    out.write_all(&id.to_le_bytes())?;
+                }
+                out.write_all(&entry.log_prob.to_le_bytes())?;
+                out.write_all(&entry.backoff.to_le_bytes())?;
+            }
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    fn from_binary_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut reader = ByteReader {
+            bytes,
+            pos: BINARY_MAGIC.len(),
+        };
+        if !bytes.starts_with(BINARY_MAGIC) {
+            return Err(Error::Config("Not a binary n-gram model".to_string()));
+        }
+
+        let order = reader.u32()? as usize;
+        let vocab_size = reader.u32()? as usize;
+        let mut words = Vec::with_capacity(vocab_size);
+        for _ in 0..vocab_size {
+            let len = reader.u32()? as usize;
+            let word = std::str::from_utf8(reader.take(len)?)
+                .map_err(|_| Error::Config("Invalid word in binary n-gram model".to_string()))?;
+            words.push(word.to_string());
+        }
+
+        let mut ngrams = Vec::with_capacity(order);
+        for n in 1..=order {
+            let count = reader.u64()? as usize;
+            let mut entries = HashMap::with_capacity(count);
+            for _ in 0..count {
+                let key = (0..n).map(|_| reader.u32()).collect::<Result<Vec<u32>>>()?;
+                if key.iter().any(|&id| id as usize >= words.len()) {
+                    return Err(Error::Config(
+                        "Word id out of range in binary n-gram model".to_string(),
+                    ));
+                }
+                let log_prob = reader.f32()?;
+                let backoff = reader.f32()?;
+                entries.insert(key, NgramEntry { log_prob, backoff });
+            }
+            ngrams.push(entries);
+        }
+
+        let ids = words
+            .iter()
+            .enumerate()
+            .map(|(id, word)| (word.clone(), id as u32))
+            .collect();
+        Ok(Self {
+            order,
+            words,
+            ids,
+            ngrams,
+        })
+    }
+
+    fn intern(&mut self, word: &str) -> u32 {
+        if let Some(&id) = self.ids.get(word) {
+            return id;
+        }
+        let id = self.words.len() as u32;
+        self.words.push(word.to_string());
+        self.ids.insert(word.to_string(), id);
+        id
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    /// Natural-log probability of `word` following `context` (oldest word first).
+    ///
+    /// Words missing from the LM are scored as `<unk>`.
+    pub fn log_prob(&self, context: &[&str], word: &str) -> f32 {
+        let mut ids = Vec::with_capacity(context.len());
+        for w in context {
+            match self.word_id(w) {
+                Some(id) => ids.push(id),
+                // An unknown word without <unk> breaks the context
+                None => ids.clear(),
+            }
+        }
+        match self.word_id(word) {
+            Some(id) => self.score(&ids, id),
+            None => UNKNOWN_LOG_PROB,
+        }
+    }
+
+    fn word_id(&self, word: &str) -> Option<u32> {
+        self.ids
+            .get(word)
+            .or_else(|| self.ids.get("<unk>"))
+            .copied()
+    }
+
+    fn score(&self, context: &[u32], word: u32) -> f32 {
+        let context = &context[context.len().saturating_sub(self.order - 1)..];
+        let mut backoff = 0.0;
+
+        for start in 0..=context.len() {
+            let history = &context[start..];
+            let mut key = history.to_vec();
+            key.push(word);
+            if let Some(entry) = self.ngrams[key.len() - 1].get(&key) {
+                return backoff + entry.log_prob;
+            }
+            if !history.is_empty() {
+                if let Some(entry) = self.ngrams[history.len() - 1].get(history) {
+                    backoff += entry.backoff;
+                }
+            }
+        }
+        backoff + UNKNOWN_LOG_PROB
+    }
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl ByteReader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8]> {
+        let slice = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| Error::Config("Truncated binary n-gram model".to_string()))?;
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+/// What the LM's vocabulary consists of
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LmUnit {
+    /// Whole words (the usual KenLM word-level model)
+    #[default]
+    Word,
+    /// The acoustic model's own token pieces
+    Token,
+}
+
+/// An n-gram LM fused into beam search:
+/// `score = log P_acoustic + weight * log P_lm + word_bonus * words`
+#[derive(Debug, Clone)]
+pub struct LanguageModel {
+    pub model: Arc<NgramLm>,
+    pub unit: LmUnit,
+    /// LM weight (alpha)
+    pub weight: f32,
+    /// Bonus per scored word or token (beta), offsets the LM's bias towards short output
+    pub word_bonus: f32,
+}
+
+impl LanguageModel {
+    pub fn new(model: NgramLm) -> Self {
+        Self {
+            model: Arc::new(model),
+            unit: LmUnit::Word,
+            weight: 0.5,
+            word_bonus: 1.0,
+        }
+    }
+
+    /// Load a word-level ARPA or binary model with the default weights
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self::new(NgramLm::from_file(path)?))
+    }
+
+    pub fn with_unit(mut self, unit: LmUnit) -> Self {
+        self.unit = unit;
+        self
+    }
+
+    pub fn with_weight(mut self, weight: f32) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    pub fn with_word_bonus(mut self, word_bonus: f32) -> Self {
+        self.word_bonus = word_bonus;
+        self
+    }
+}
+
+/// LM context of one hypothesis
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LmState {
+    context: Vec<u32>,
+    // Word being assembled from pieces (word unit only)
+    partial: String,
+    /// Weighted LM score plus bonuses so far
+    pub score: f32,
+}
+
+/// Scores token sequences of a decoder with a [`LanguageModel`]
+pub(crate) struct LmScorer<'a> {
+    lm: &'a LanguageModel,
+    // Token id -> piece text
+    pieces: &'a [String],
+}
+
+impl<'a> LmScorer<'a> {
+    pub fn new(lm: &'a LanguageModel, pieces: &'a [String]) -> Self {
+        Self { lm, pieces }
+    }
+
+    pub fn start(&self) -> LmState {
+        LmState {
+            context: self.lm.model.word_id("<s>").into_iter().collect(),
+            ..LmState::default()
+        }
+    }
+
+    /// State after appending `token`
+    pub fn advance(&self, state: &LmState, token: u32) -> LmState {
+        let mut next = state.clone();
+        let piece = self
+            .pieces
+            .get(token as usize)
+            .map(String::as_str)
+            .unwrap_or("");
+
+        match self.lm.unit {
+            LmUnit::Token => self.score_word(&mut next, piece, true),
+            LmUnit::Word => {
+                if piece.starts_with(WORD_BOUNDARY) && !next.partial.is_empty() {
+                    let word = std::mem::take(&mut next.partial);
+                    self.score_word(&mut next, &word, true);
+                }
+                next.partial
+                    .push_str(piece.trim_start_matches(WORD_BOUNDARY));
+            }
+        }
+        next
+    }
+
+    /// Final score of a complete hypothesis, including its last word and `</s>`
+    pub fn finish(&self, state: &LmState) -> f32 {
+        let mut last = state.clone();
+        if !last.partial.is_empty() {
+            let word = std::mem::take(&mut last.partial);
+            self.score_word(&mut last, &word, true);
+        }
+        self.score_word(&mut last, "</s>", false);
+        last.score
+    }
+
+    fn score_word(&self, state: &mut LmState, word: &str, bonus: bool) {
+        let model = &self.lm.model;
+        let log_prob = match model.word_id(word) {
+            Some(id) => {
+                let log_prob = model.score(&state.context, id);
+                state.context.push(id);
+                let keep = model.order.saturating_sub(1);
+                if state.context.len() > keep {
+                    state.context.drain(..state.context.len() - keep);
+                }
+                log_prob
+            }
+            None => {
+                state.context.clear();
+                UNKNOWN_LOG_PROB
+            }
+        };
+        state.score += self.lm.weight * log_prob;
+        if bonus {
+            state.score += self.lm.word_bonus;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TINY_ARPA: &str = include_str!("../tests/data/tiny.arpa");
+    const LN10: f32 = std::f32::consts::LN_10;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn test_arpa_scores_with_backoff() {
+        let lm = NgramLm::from_arpa_str(TINY_ARPA).unwrap();
+        assert_eq!(lm.order(), 3);
+
+        // Explicit trigram
+        assert!(close(lm.log_prob(&["<s>", "the"], "cat"), -0.09691 * LN10));
+        // "the hat sat": no trigram, no bigram -> backoff(hat) + backoff... + p(sat)
+        assert!(close(
+            lm.log_prob(&["the", "hat"], "sat"),
+            (-0.1 - 1.30103) * LN10
+        ));
+        // "<s> the hat": trigram missing, backoff(<s> the) + p(hat | the)
+        assert!(close(
+            lm.log_prob(&["<s>", "the"], "hat"),
+            (-0.1 - 0.69897) * LN10
+        ));
+        // Unknown words map to <unk>
+        assert!(close(lm.log_prob(&[], "dog"), -1.5 * LN10));
+    }
+
+    #[test]
+    fn test_binary_roundtrip() {
+        let lm = NgramLm::from_arpa_str(TINY_ARPA).unwrap();
+        let path = std::env::temp_dir().join(format!("parakeet-lm-{}.bin", std::process::id()));
+        lm.save_binary(&path).unwrap();
+        let loaded = NgramLm::from_file(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(loaded.order(), 3);
+        for (context, word) in [(&["<s>", "the"][..], "cat"), (&["cat"][..], "sat")] {
+            assert_eq!(loaded.log_prob(context, word), lm.log_prob(context, word));
+        }
+    }
+
+    #[test]
+    fn test_word_scorer_assembles_pieces() {
+        let lm = LanguageModel::new(NgramLm::from_arpa_str(TINY_ARPA).unwrap())
+            .with_weight(1.0)
+            .with_word_bonus(0.0);
+        let pieces: Vec<String> = ["▁the", "▁c", "at", "▁sat"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let scorer = LmScorer::new(&lm, &pieces);
+
+        let mut state = scorer.start();
+        for token in [0, 1, 2, 3] {
+            state = scorer.advance(&state, token);
+        }
+        // "the" and "cat" are complete, "sat" is pending until finish()
+        // p(the | <s>) = 10^-0.30103
+        let expected_so_far = (-std::f32::consts::LOG10_2 - 0.09691) * LN10;
+        assert!(close(state.score, expected_so_far));
+
+        let total = scorer.finish(&state);
+        let expected = expected_so_far + (-0.15490 - 0.47712) * LN10;
+        assert!(close(total, expected), "{total} vs {expected}");
+    }
+}
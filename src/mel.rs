@@ -0,0 +1,910 @@
+//! Log-mel spectrogram frontend shared by all models
+//!
+//! Every model in the crate feeds the encoder log-mel features, but the exported
+//! checkpoints were trained with slightly different preprocessing (mel scale,
+//! filterbank normalization, STFT padding, log guard, feature normalization).
+//! [`MelFrontend`] implements the whole pipeline once; each model picks the
+//! [`MelConfig`] preset that matches its NeMo preprocessor.
+//!
+//! Pipeline: dither -> preemphasis -> STFT (power) -> mel filterbank -> log -> normalization
+
+use crate::config::PreprocessorConfig;
+use crate::error::{Error, Result};
+use ndarray::Array2;
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::{Arc, Mutex};
+
+/// Hann window variant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowType {
+    /// Symmetric Hann (`torch.hann_window(periodic=False)`, NeMo)
+    Hann,
+    /// Periodic Hann (`scipy.signal.get_window`, librosa)
+    HannPeriodic,
+}
+
+/// Where a window shorter than `n_fft` sits inside the FFT frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowAlignment {
+    /// Window at the start of the frame, zeros after it
+    Left,
+    /// Window zero-padded equally on both sides (`torch.stft`, librosa)
+    Center,
+}
+
+/// Signal padding before framing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaddingMode {
+    /// No padding, the first frame starts at sample 0
+    None,
+    /// `n_fft / 2` zeros on both sides, so frame `t` is centered on sample `t * hop_length`
+    Center,
+}
+
+/// Hz <-> mel conversion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MelScale {
+    /// `2595 * log10(1 + f / 700)`
+    Htk,
+    /// Linear below 1kHz, logarithmic above (librosa default, `htk=False`)
+    Slaney,
+}
+
+/// Filterbank normalization
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MelNorm {
+    /// Triangles peak at 1
+    None,
+    /// Each triangle scaled to unit area (`norm='slaney'`)
+    Slaney,
+}
+
+/// How zeros are kept out of the log
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogGuard {
+    /// `ln(max(x, guard))`
+    Clamp(f32),
+    /// `ln(x + guard)` (NeMo `log_zero_guard_type="add"`)
+    Add(f32),
+}
+
+/// Normalization applied to the log-mel features
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeatureNormalization {
+    /// Raw log-mel (NeMo `normalize="NA"`)
+    None,
+    /// Zero mean, unit variance per mel bin over the utterance
+    PerFeature,
+}
+
+/// Mel frontend configuration.
+///
+/// Use one of the presets ([`MelConfig::parakeet`], [`MelConfig::eou`],
+/// [`MelConfig::sortformer`]) and adjust fields as needed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MelConfig {
+    pub sample_rate: usize,
+    pub n_fft: usize,
+    pub win_length: usize,
+    pub hop_length: usize,
+    pub n_mels: usize,
+    /// Preemphasis coefficient (0.0 disables)
+    pub preemphasis: f32,
+    pub window: WindowType,
+    pub window_alignment: WindowAlignment,
+    pub padding: PaddingMode,
+    pub mel_scale: MelScale,
+    pub mel_norm: MelNorm,
+    pub fmin: f32,
+    /// Upper filterbank edge in Hz (`None` = Nyquist)
+    pub fmax: Option<f32>,
+    pub log_guard: LogGuard,
+    pub normalization: FeatureNormalization,
+    /// Gaussian noise added to the input (`None` = off, the default for every preset)
+    pub dither: Option<DitherConfig>,
+}
+
+/// Dither: low-level Gaussian noise added to the waveform before preemphasis, as NeMo's
+/// `AudioToMelSpectrogramPreprocessor(dither=...)` does.
+///
+/// The noise for sample `i` is derived from `seed` and `i` alone, so the same seed
+/// always yields the same features, also when audio arrives in streaming chunks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DitherConfig {
+    /// Standard deviation of the noise
+    pub amount: f32,
+    pub seed: u64,
+}
+
+impl DitherConfig {
+    pub fn new(amount: f32, seed: u64) -> Self {
+        Self { amount, seed }
+    }
+
+    /// NeMo's default `dither=1e-5`
+    pub fn nemo(seed: u64) -> Self {
+        Self::new(1e-5, seed)
+    }
+
+    /// Noise value for the sample at `index`
+    fn noise(&self, index: u64) -> f32 {
+        let u1 = unit_uniform(splitmix64(self.seed ^ index.wrapping_mul(2)));
+        let u2 = unit_uniform(splitmix64(
+            self.seed ^ index.wrapping_mul(2).wrapping_add(1),
+        ));
+        // Box-Muller
+        let gauss = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+        self.amount * gauss as f32
+    }
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+// Uniform in (0, 1)
+fn unit_uniform(bits: u64) -> f64 {
+    ((bits >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+}
+
+impl MelConfig {
+    /// CTC and TDT preprocessing, with sizes taken from `preprocessor_config.json`
+    pub fn parakeet(config: &PreprocessorConfig) -> Self {
+        Self {
+            sample_rate: config.sampling_rate,
+            n_fft: config.n_fft,
+            win_length: config.win_length,
+            hop_length: config.hop_length,
+            n_mels: config.feature_size,
+            preemphasis: config.preemphasis,
+            window: WindowType::Hann,
+            window_alignment: WindowAlignment::Left,
+            padding: PaddingMode::None,
+            mel_scale: MelScale::Htk,
+            mel_norm: MelNorm::None,
+            fmin: 0.0,
+            fmax: None,
+            log_guard: LogGuard::Clamp(1e-10),
+            normalization: FeatureNormalization::PerFeature,
+            dither: None,
+        }
+    }
+
+    /// Realtime EOU preprocessing (128 mels, center padding, no normalization)
+    pub fn eou() -> Self {
+        Self {
+            sample_rate: 16000,
+            n_fft: 512,
+            win_length: 400,
+            hop_length: 160,
+            n_mels: 128,
+            preemphasis: 0.97,
+            window: WindowType::Hann,
+            window_alignment: WindowAlignment::Left,
+            padding: PaddingMode::Center,
+            mel_scale: MelScale::Htk,
+            mel_norm: MelNorm::Slaney,
+            fmin: 0.0,
+            fmax: Some(8000.0),
+            log_guard: LogGuard::Add(5.960_464_5e-8), // 2^-24
+            normalization: FeatureNormalization::None,
+            dither: None,
+        }
+    }
+
+    /// Sortformer preprocessing (librosa-style STFT, Slaney mel, no normalization).
+    /// See https://librosa.org/doc/main/generated/librosa.stft.html
+    pub fn sortformer() -> Self {
+        Self {
+            window: WindowType::HannPeriodic,
+            window_alignment: WindowAlignment::Center,
+            mel_scale: MelScale::Slaney,
+            ..Self::eou()
+        }
+    }
+
+    fn fft_bins(&self) -> usize {
+        self.n_fft / 2 + 1
+    }
+
+    // Samples covered by one frame, from its first sample to the last non-zero window tap
+    fn frame_span(&self) -> usize {
+        match self.window_alignment {
+            WindowAlignment::Left => self.win_length,
+            WindowAlignment::Center => self.n_fft,
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+const PARALLEL_MIN_FRAMES: usize = 256;
+
+/// Log-mel spectrogram extractor.
+///
+/// The window, FFT plan, sparse filterbank and frame buffers are built once in
+/// [`MelFrontend::new`], so keep one frontend around per model rather than per call.
+/// With the `rayon` feature, frames are computed in parallel.
+pub struct MelFrontend {
+    config: MelConfig,
+    // Window zero-padded to n_fft according to the alignment
+    window: Vec<f32>,
+    filterbank: Array2<f32>, // (n_mels, n_fft / 2 + 1)
+    // Non-zero run of each filter: (first bin, weights)
+    sparse_filterbank: Vec<(usize, Vec<f32>)>,
+    fft: Arc<dyn Fft<f32>>,
+    scratch: Mutex<FrameScratch>,
+}
+
+impl MelFrontend {
+    pub fn new(config: MelConfig) -> Self {
+        let window = fft_window(&config);
+        let filterbank = mel_filterbank(&config);
+        let sparse_filterbank = filterbank
+            .outer_iter()
+            .map(|row| {
+                let first = row.iter().position(|&w| w != 0.0).unwrap_or(0);
+                let last = row.iter().rposition(|&w| w != 0.0).map_or(0, |i| i + 1);
+                (first, row.iter().take(last).skip(first).copied().collect())
+            })
+            .collect();
+        let fft = FftPlanner::<f32>::new().plan_fft_forward(config.n_fft);
+        let scratch = Mutex::new(FrameScratch::new(config.n_fft, fft.as_ref()));
+
+        Self {
+            config,
+            window,
+            filterbank,
+            sparse_filterbank,
+            fft,
+            scratch,
+        }
+    }
+
+    pub fn config(&self) -> &MelConfig {
+        &self.config
+    }
+
+    /// Turn dithering on or off (`None`)
+    pub fn set_dither(&mut self, dither: Option<DitherConfig>) {
+        self.config.dither = dither;
+    }
+
+    /// Mel filterbank weights (n_mels x (n_fft / 2 + 1))
+    pub fn filterbank(&self) -> &Array2<f32> {
+        &self.filterbank
+    }
+
+    /// Number of feature frames produced for `num_samples` input samples
+    pub fn num_frames(&self, num_samples: usize) -> usize {
+        let padded = match self.config.padding {
+            PaddingMode::None => num_samples,
+            PaddingMode::Center => num_samples + 2 * (self.config.n_fft / 2),
+        };
+        match padded.checked_sub(self.config.frame_span()) {
+            Some(rest) => rest / self.config.hop_length + 1,
+            None => 0,
+        }
+    }
+
+    /// Fewest samples that produce at least one frame
+    pub fn min_input_len(&self) -> usize {
+        let padding = match self.config.padding {
+            PaddingMode::None => 0,
+            PaddingMode::Center => 2 * (self.config.n_fft / 2),
+        };
+        self.config.frame_span().saturating_sub(padding).max(1)
+    }
+
+    /// Compute log-mel features from mono audio at `config.sample_rate`.
+    ///
+    /// # Returns
+    ///
+    /// 2D array of features (time_steps x n_mels)
+    pub fn compute(&self, audio: &[f32]) -> Array2<f32> {
+        self.compute_at(audio, 0)
+    }
+
+    // `compute` for audio whose first sample is sample `offset` of a longer stream
+    // (only matters for the dither noise)
+    pub(crate) fn compute_at(&self, audio: &[f32], offset: u64) -> Array2<f32> {
+        let mut signal: Vec<f32> = audio
+            .iter()
+            .map(|&x| if x.is_finite() { x } else { 0.0 })
+            .collect();
+
+        if let Some(dither) = &self.config.dither {
+            for (i, x) in signal.iter_mut().enumerate() {
+                *x += dither.noise(offset + i as u64);
+            }
+        }
+        if self.config.preemphasis != 0.0 {
+            apply_preemphasis(&mut signal, self.config.preemphasis);
+        }
+        if self.config.padding == PaddingMode::Center {
+            let pad = self.config.n_fft / 2;
+            let mut padded = vec![0.0; pad];
+            padded.extend_from_slice(&signal);
+            padded.resize(padded.len() + pad, 0.0);
+            signal = padded;
+        }
+
+        let mut features = self.log_mel(&signal);
+        if self.config.normalization == FeatureNormalization::PerFeature {
+            normalize_per_feature(&mut features);
+        }
+        features
+    }
+
+    // Power STFT, mel projection and log for every frame of an already padded signal
+    fn log_mel(&self, signal: &[f32]) -> Array2<f32> {
+        let hop = self.config.hop_length;
+        let span = self.config.frame_span();
+        let num_frames = match signal.len().checked_sub(span) {
+            Some(rest) => rest / hop + 1,
+            None => 0,
+        };
+
+        let mut features = Array2::<f32>::zeros((num_frames, self.config.n_mels));
+
+        // Short inputs aren't worth the thread hand-off
+        #[cfg(feature = "rayon")]
+        if num_frames >= PARALLEL_MIN_FRAMES {
+            use ndarray::parallel::prelude::*;
+            features
+                .outer_iter_mut()
+                .into_par_iter()
+                .enumerate()
+                .for_each_init(
+                    || self.new_scratch(),
+                    |scratch, (t, mut row)| {
+                        let start = t * hop;
+                        let out = row.as_slice_mut().expect("rows are contiguous");
+                        self.frame_log_mel(&signal[start..start + span], scratch, out);
+                    },
+                );
+            return features;
+        }
+
+        // Reuse the cached buffers unless another thread is using them
+        let mut local = None;
+        let mut guard = self.scratch.try_lock().ok();
+        let scratch = match guard.as_deref_mut() {
+            Some(scratch) => scratch,
+            None => local.insert(self.new_scratch()),
+        };
+        for (t, mut row) in features.outer_iter_mut().enumerate() {
+            let start = t * hop;
+            let out = row.as_slice_mut().expect("rows are contiguous");
+            self.frame_log_mel(&signal[start..start + span], scratch, out);
+        }
+
+        features
+    }
+
+    pub(crate) fn new_scratch(&self) -> FrameScratch {
+        FrameScratch::new(self.config.n_fft, self.fft.as_ref())
+    }
+
+    /// Samples covered by one frame (the window span within the FFT frame)
+    pub(crate) fn frame_span(&self) -> usize {
+        self.config.frame_span()
+    }
+
+    /// Log-mel of a single frame.
+    ///
+    /// `frame` holds the `frame_span()` preprocessed samples starting at the frame start;
+    /// every frame of [`MelFrontend::compute`] goes through here, so incremental callers
+    /// get bit-identical values.
+    pub(crate) fn frame_log_mel(&self, frame: &[f32], scratch: &mut FrameScratch, out: &mut [f32]) {
+        for (i, slot) in scratch.buffer.iter_mut().enumerate() {
+            let x = frame.get(i).copied().unwrap_or(0.0);
+            *slot = Complex::new(x * self.window[i], 0.0);
+        }
+        self.fft
+            .process_with_scratch(&mut scratch.buffer, &mut scratch.fft_scratch);
+
+        for (p, v) in scratch.power.iter_mut().zip(&scratch.buffer) {
+            let mag_sq = v.norm_sqr();
+            *p = if mag_sq.is_finite() { mag_sq } else { 0.0 };
+        }
+
+        // Sparse multiply over each filter's non-zero bins; the explicit accumulation
+        // order keeps features identical across builds
+        for ((first, weights), value) in self.sparse_filterbank.iter().zip(out.iter_mut()) {
+            let energy: f32 = weights
+                .iter()
+                .zip(&scratch.power[*first..])
+                .map(|(w, p)| w * p)
+                .sum();
+            *value = match self.config.log_guard {
+                LogGuard::Clamp(guard) => energy.max(guard).ln(),
+                LogGuard::Add(guard) => (energy.max(0.0) + guard).ln(),
+            };
+        }
+    }
+}
+
+impl Clone for MelFrontend {
+    fn clone(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            window: self.window.clone(),
+            filterbank: self.filterbank.clone(),
+            sparse_filterbank: self.sparse_filterbank.clone(),
+            fft: Arc::clone(&self.fft),
+            scratch: Mutex::new(self.new_scratch()),
+        }
+    }
+}
+
+impl std::fmt::Debug for MelFrontend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MelFrontend")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Incremental [`MelFrontend::compute`] over a rolling audio buffer.
+///
+/// Streaming callers keep the last few seconds of audio and need the newest feature
+/// frames after every chunk. Frames are laid out exactly as if `compute` ran on the
+/// whole buffer, but each frame is keyed by the stream position of its first sample:
+/// frames that lie fully inside the buffer (and don't touch its first sample, whose
+/// preemphasis lacks a predecessor) never change, so they are kept in a ring and only
+/// the new frames and the right-padded tail are computed per chunk.
+///
+/// Requires a config without utterance-level normalization. Dither noise is keyed by
+/// stream position, so every sample keeps the same noise while it is buffered.
+pub(crate) struct StreamingMel {
+    frontend: MelFrontend,
+    scratch: FrameScratch,
+    buffer: VecDeque<f32>,
+    capacity: usize,
+    // Total samples pushed since creation
+    stream_len: u64,
+    // Stable frames by stream position of their first sample, ascending
+    cache: VecDeque<(u64, Vec<f32>)>,
+    frame: Vec<f32>,
+}
+
+impl StreamingMel {
+    /// `capacity` is the rolling buffer length in samples
+    pub(crate) fn new(frontend: MelFrontend, capacity: usize) -> Self {
+        debug_assert!(frontend.config.normalization == FeatureNormalization::None);
+        let scratch = frontend.new_scratch();
+        let frame = vec![0.0; frontend.frame_span()];
+        Self {
+            frontend,
+            scratch,
+            buffer: VecDeque::with_capacity(capacity),
+            capacity,
+            stream_len: 0,
+            cache: VecDeque::new(),
+            frame,
+        }
+    }
+
+    /// Append samples, dropping the oldest ones beyond the buffer capacity
+    pub(crate) fn push(&mut self, samples: &[f32]) {
+        let dither = self.frontend.config.dither;
+        let first = self.stream_len;
+        self.buffer
+            .extend(samples.iter().enumerate().map(|(i, &x)| {
+                let x = if x.is_finite() { x } else { 0.0 };
+                match &dither {
+                    Some(dither) => x + dither.noise(first + i as u64),
+                    None => x,
+                }
+            }));
+        let excess = self.buffer.len().saturating_sub(self.capacity);
+        self.buffer.drain(..excess);
+        self.stream_len += samples.len() as u64;
+    }
+
+    pub(crate) fn set_dither(&mut self, dither: Option<DitherConfig>) {
+        self.frontend.set_dither(dither);
+    }
+
+    /// Total samples pushed since creation
+    pub(crate) fn stream_len(&self) -> u64 {
+        self.stream_len
+    }
+
+    /// Samples currently buffered
+    pub(crate) fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// The last `count` frames of `compute(buffer)`, as (n_mels, frames)
+    pub(crate) fn latest_frames(&mut self, count: usize) -> Array2<f32> {
+        let config = &self.frontend.config;
+        let (hop, n_mels) = (config.hop_length, config.n_mels);
+        let pad = match config.padding {
+            PaddingMode::None => 0,
+            PaddingMode::Center => config.n_fft / 2,
+        };
+        let len = self.buffer.len();
+        let span = self.frontend.frame_span();
+        let origin = self.stream_len - len as u64;
+
+        let total = self.frontend.num_frames(len);
+        let first = total.saturating_sub(count);
+        let mut features = Array2::<f32>::zeros((n_mels, total - first));
+        let mut cache = VecDeque::with_capacity(total - first);
+
+        for (col, t) in (first..total).enumerate() {
+            // First sample of the frame in buffer coordinates (negative inside the left padding)
+            let start = (t * hop) as i64 - pad as i64;
+            let stable = start >= 1 && start as usize + span <= len;
+            let key = (origin as i64 + start) as u64;
+
+            let cached = if stable {
+                self.cache
+                    .binary_search_by_key(&key, |(k, _)| *k)
+                    .ok()
+                    .map(|i| std::mem::take(&mut self.cache[i].1))
+            } else {
+                None
+            };
+
+            let values = match cached {
+                Some(values) => values,
+                None => {
+                    self.fill_frame(start);
+                    let mut values = vec![0.0; n_mels];
+                    self.frontend
+                        .frame_log_mel(&self.frame, &mut self.scratch, &mut values);
+                    values
+                }
+            };
+
+            features
+                .column_mut(col)
+                .assign(&ndarray::ArrayView1::from(&values));
+            if stable {
+                cache.push_back((key, values));
+            }
+        }
+
+        self.cache = cache;
+        features
+    }
+
+    // Preemphasized buffer samples of the frame starting at `start`, zeros outside the buffer
+    fn fill_frame(&mut self, start: i64) {
+        let coef = self.frontend.config.preemphasis;
+        let len = self.buffer.len() as i64;
+        for (i, out) in self.frame.iter_mut().enumerate() {
+            let pos = start + i as i64;
+            *out = if pos < 0 || pos >= len {
+                0.0
+            } else {
+                let pos = pos as usize;
+                let x = self.buffer[pos];
+                if coef != 0.0 && pos > 0 {
+                    x - coef * self.buffer[pos - 1]
+                } else {
+                    x
+                }
+            };
+        }
+    }
+}
+
+/// Per-thread buffers for [`MelFrontend::frame_log_mel`]
+pub(crate) struct FrameScratch {
+    buffer: Vec<Complex<f32>>,
+    fft_scratch: Vec<Complex<f32>>,
+    power: Vec<f32>,
+}
+
+impl FrameScratch {
+    fn new(n_fft: usize, fft: &dyn Fft<f32>) -> Self {
+        Self {
+            buffer: vec![Complex::new(0.0, 0.0); n_fft],
+            fft_scratch: vec![Complex::new(0.0, 0.0); fft.get_inplace_scratch_len()],
+            power: vec![0.0; n_fft / 2 + 1],
+        }
+    }
+}
+
+fn apply_preemphasis(signal: &mut [f32], coef: f32) {
+    for i in (1..signal.len()).rev() {
+        signal[i] -= coef * signal[i - 1];
+    }
+}
+
+/// Check that supplied features are `(time, n_mels)` for `config` and, with
+/// `max_duration`, that they cover no more audio than the model accepts
+pub(crate) fn check_features(
+    features: &Array2<f32>,
+    config: &MelConfig,
+    max_duration: Option<f32>,
+) -> Result<()> {
+    let (frames, bins) = features.dim();
+    if bins != config.n_mels {
+        return Err(Error::Model(format!(
+            "Expected features of shape (time, {}), got ({frames}, {bins})",
+            config.n_mels
+        )));
+    }
+    if frames == 0 {
+        return Err(Error::EmptyAudio);
+    }
+    if let Some(max_duration) = max_duration {
+        let duration = (frames * config.hop_length) as f32 / config.sample_rate as f32;
+        if duration > max_duration {
+            return Err(Error::AudioTooLong {
+                duration,
+                max_duration,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn normalize_per_feature(features: &mut Array2<f32>) {
+    let num_frames = features.nrows();
+    if num_frames == 0 {
+        return;
+    }
+
+    for mut column in features.columns_mut() {
+        let mean: f32 = column.iter().sum::<f32>() / num_frames as f32;
+        let variance: f32 =
+            column.iter().map(|&x| (x - mean).powi(2)).sum::<f32>() / num_frames as f32;
+        let std = variance.sqrt().max(1e-10);
+
+        for val in column.iter_mut() {
+            *val = (*val - mean) / std;
+        }
+    }
+}
+
+fn fft_window(config: &MelConfig) -> Vec<f32> {
+    let len = config.win_length.min(config.n_fft);
+    let denom = match config.window {
+        WindowType::Hann => len.saturating_sub(1).max(1) as f32,
+        WindowType::HannPeriodic => len as f32,
+    };
+    let offset = match config.window_alignment {
+        WindowAlignment::Left => 0,
+        WindowAlignment::Center => (config.n_fft - len) / 2,
+    };
+
+    let mut window = vec![0.0f32; config.n_fft];
+    for i in 0..len {
+        window[offset + i] = 0.5 - 0.5 * ((2.0 * PI * i as f32) / denom).cos();
+    }
+    window
+}
+
+fn hz_to_mel(hz: f64, scale: MelScale) -> f64 {
+    match scale {
+        MelScale::Htk => 2595.0 * (1.0 + hz / 700.0).log10(),
+        MelScale::Slaney => {
+            let f_sp = 200.0 / 3.0;
+            let min_log_hz = 1000.0;
+            let min_log_mel = min_log_hz / f_sp;
+            let logstep = 6.4f64.ln() / 27.0;
+            if hz >= min_log_hz {
+                min_log_mel + (hz / min_log_hz).ln() / logstep
+            } else {
+                hz / f_sp
+            }
+        }
+    }
+}
+
+fn mel_to_hz(mel: f64, scale: MelScale) -> f64 {
+    match scale {
+        MelScale::Htk => 700.0 * (10.0f64.powf(mel / 2595.0) - 1.0),
+        MelScale::Slaney => {
+            let f_sp = 200.0 / 3.0;
+            let min_log_hz = 1000.0;
+            let min_log_mel = min_log_hz / f_sp;
+            let logstep = 6.4f64.ln() / 27.0;
+            if mel >= min_log_mel {
+                min_log_hz * (logstep * (mel - min_log_mel)).exp()
+            } else {
+                f_sp * mel
+            }
+        }
+    }
+}
+
+// Triangular filters in f64 (librosa's ramp formulation), stored as f32
+fn mel_filterbank(config: &MelConfig) -> Array2<f32> {
+    let bins = config.fft_bins();
+    let n_mels = config.n_mels;
+    let fmax = config.fmax.unwrap_or(config.sample_rate as f32 / 2.0);
+
+    let fft_freqs: Vec<f64> = (0..bins)
+        .map(|k| k as f64 * config.sample_rate as f64 / config.n_fft as f64)
+        .collect();
+
+    let mel_min = hz_to_mel(config.fmin as f64, config.mel_scale);
+    let mel_max = hz_to_mel(fmax as f64, config.mel_scale);
+    let mel_f: Vec<f64> = (0..=n_mels + 1)
+        .map(|i| {
+            let mel = mel_min + (mel_max - mel_min) * i as f64 / (n_mels + 1) as f64;
+            mel_to_hz(mel, config.mel_scale)
+        })
+        .collect();
+
+    let mut filterbank = Array2::<f32>::zeros((n_mels, bins));
+    for m in 0..n_mels {
+        let (left, center, right) = (mel_f[m], mel_f[m + 1], mel_f[m + 2]);
+        let enorm = match config.mel_norm {
+            MelNorm::None => 1.0,
+            MelNorm::Slaney => 2.0 / (right - left),
+        };
+        for (k, &freq) in fft_freqs.iter().enumerate() {
+            let lower = (freq - left) / (center - left);
+            let upper = (right - freq) / (right - center);
+            filterbank[[m, k]] = (0.0f64.max(lower.min(upper)) * enorm) as f32;
+        }
+    }
+
+    filterbank
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f32, secs: f32) -> Vec<f32> {
+        (0..(secs * 16000.0) as usize)
+            .map(|i| 0.5 * (2.0 * PI * freq * i as f32 / 16000.0).sin())
+            .collect()
+    }
+
+    #[test]
+    fn test_frame_counts_per_preset() {
+        let ctc = MelFrontend::new(MelConfig::parakeet(&PreprocessorConfig::default()));
+        let eou = MelFrontend::new(MelConfig::eou());
+        let sortformer = MelFrontend::new(MelConfig::sortformer());
+
+        for len in [0, 100, 399, 400, 16000, 16123] {
+            let audio = vec![0.1f32; len];
+            assert_eq!(ctc.compute(&audio).nrows(), ctc.num_frames(len));
+            assert_eq!(eou.compute(&audio).nrows(), eou.num_frames(len));
+            assert_eq!(
+                sortformer.compute(&audio).nrows(),
+                sortformer.num_frames(len)
+            );
+        }
+
+        // Short input yields no frames instead of underflowing
+        assert_eq!(ctc.num_frames(100), 0);
+        assert_eq!(ctc.num_frames(16000), 98);
+        assert_eq!(eou.num_frames(16000), 101);
+        assert_eq!(sortformer.num_frames(16000), 101);
+    }
+
+    #[test]
+    fn test_tone_peaks_in_expected_mel_bin() {
+        let frontend = MelFrontend::new(MelConfig::eou());
+        let features = frontend.compute(&sine(1000.0, 0.5));
+        let frame = features.row(features.nrows() / 2);
+        let peak = frame
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap()
+            .0;
+
+        let fb = frontend.filterbank();
+        let bin_1k = 1000 * 512 / 16000;
+        assert!(fb[[peak, bin_1k]] > 0.0, "peak mel bin {peak}");
+    }
+
+    #[test]
+    fn test_slaney_filters_have_unit_area() {
+        let config = MelConfig::sortformer();
+        let fb = MelFrontend::new(config.clone()).filterbank().clone();
+        let bin_hz = config.sample_rate as f32 / config.n_fft as f32;
+        // Area in Hz of a Slaney-normalized triangle is 1, up to the bin discretization
+        let areas: Vec<f32> = (40..config.n_mels)
+            .map(|m| fb.row(m).sum() * bin_hz)
+            .collect();
+        let mean_area = areas.iter().sum::<f32>() / areas.len() as f32;
+        assert!((mean_area - 1.0).abs() < 0.05, "mean area {mean_area}");
+    }
+
+    #[test]
+    fn test_per_feature_normalization_and_non_finite_input() {
+        let frontend = MelFrontend::new(MelConfig::parakeet(&PreprocessorConfig::default()));
+        let mut audio = sine(440.0, 1.0);
+        audio[100] = f32::NAN;
+        audio[200] = f32::INFINITY;
+
+        let features = frontend.compute(&audio);
+        assert!(features.iter().all(|x| x.is_finite()));
+        for column in features.columns() {
+            let mean = column.mean().unwrap();
+            assert!(mean.abs() < 0.05, "{mean}");
+        }
+    }
+
+    #[test]
+    fn test_dither_is_seeded() {
+        let dithered = |seed| {
+            MelFrontend::new(MelConfig {
+                dither: Some(DitherConfig::nemo(seed)),
+                ..MelConfig::sortformer()
+            })
+        };
+        let silence = vec![0.0f32; 4000];
+
+        let a = dithered(7).compute(&silence);
+        assert_eq!(a, dithered(7).compute(&silence));
+        assert_ne!(a, dithered(8).compute(&silence));
+
+        let plain = MelFrontend::new(MelConfig::sortformer()).compute(&silence);
+        assert_ne!(a, plain);
+        assert_eq!(
+            plain,
+            MelFrontend::new(MelConfig::sortformer()).compute(&silence)
+        );
+
+        // Noise has the requested level
+        let noise: Vec<f32> = (0..20000).map(|i| DitherConfig::nemo(1).noise(i)).collect();
+        let std = (noise.iter().map(|x| x * x).sum::<f32>() / noise.len() as f32).sqrt();
+        assert!((std / 1e-5 - 1.0).abs() < 0.05, "std {std}");
+    }
+
+    #[test]
+    fn test_streaming_matches_full_buffer_bit_for_bit() {
+        let frontend = MelFrontend::new(MelConfig {
+            dither: Some(DitherConfig::nemo(42)),
+            ..MelConfig::eou()
+        });
+        let capacity = 16000 * 2;
+        let mut stream = StreamingMel::new(frontend.clone(), capacity);
+        let mut reference = VecDeque::new();
+
+        // Speech-like signal in chunks of varying, not hop-aligned sizes, long enough
+        // for the rolling buffer to wrap
+        let audio: Vec<f32> = (0..16000 * 4)
+            .map(|i| {
+                let t = i as f32 / 16000.0;
+                0.3 * (2.0 * PI * 220.0 * t).sin() * (2.0 * PI * 3.0 * t).sin()
+                    + 0.05 * (2.0 * PI * 1730.0 * t).cos()
+            })
+            .collect();
+        let sizes = [2560, 2560, 1000, 2560, 333, 4096, 2560];
+
+        let mut pos = 0;
+        let mut step = 0;
+        while pos < audio.len() {
+            let size = sizes[step % sizes.len()].min(audio.len() - pos);
+            let chunk = &audio[pos..pos + size];
+            pos += size;
+            step += 1;
+
+            stream.push(chunk);
+            reference.extend(chunk.iter().copied());
+            while reference.len() > capacity {
+                reference.pop_front();
+            }
+            if reference.len() < 16000 {
+                continue;
+            }
+
+            let buffer: Vec<f32> = reference.iter().copied().collect();
+            let full = frontend.compute_at(&buffer, (pos - buffer.len()) as u64);
+            let expected = full
+                .slice(ndarray::s![full.nrows() - 25.., ..])
+                .t()
+                .to_owned();
+            assert_eq!(stream.latest_frames(25), expected, "chunk {step}");
+        }
+    }
+}
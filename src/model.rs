@@ -1,4 +1,4 @@
-use crate::config::ModelConfig;
+use crate::config::{check_dim, graph_dim, ModelConfig};
 use crate::error::{Error, Result};
 use crate::execution::ModelConfig as ExecutionConfig;
 use ndarray::Array2;
@@ -15,21 +15,56 @@ impl ParakeetModel {
         Self::from_pretrained_with_config(model_path, ExecutionConfig::default())
     }
 
+    /// Load the model with `config.json` (or `model_config.yaml`) from the model's directory,
+    /// falling back to the Parakeet-CTC-0.6b defaults when neither is present.
     pub fn from_pretrained_with_config<P: AsRef<Path>>(
         model_path: P,
         exec_config: ExecutionConfig,
     ) -> Result<Self> {
         let model_path = model_path.as_ref();
+        // Defaults match Parakeet-CTC-0.6b, please see: json files https://huggingface.co/onnx-community/parakeet-ctc-0.6b-ONNX/tree/main
+        let config = match model_path.parent() {
+            Some(dir) => ModelConfig::from_model_dir(dir)?,
+            None => ModelConfig::default(),
+        };
 
-        // Use default config (hardcoded constants for Parakeet-CTC-0.6b: please see: json files https://huggingface.co/onnx-community/parakeet-ctc-0.6b-ONNX/tree/main)
-        let config = ModelConfig::default();
+        Self::from_pretrained_with_model_config(model_path, exec_config, config)
+    }
 
+    /// Load the model with an explicit model configuration.
+    ///
+    /// The vocabulary size is checked against the graph's `logits` output.
+    pub fn from_pretrained_with_model_config<P: AsRef<Path>>(
+        model_path: P,
+        exec_config: ExecutionConfig,
+        config: ModelConfig,
+    ) -> Result<Self> {
         let builder = Session::builder()?;
         let builder = exec_config.apply_to_session_builder(builder)?;
-        let session = builder.commit_from_file(model_path)?;
+        let session = builder.commit_from_file(model_path.as_ref())?;
+
+        check_dim(
+            "Vocabulary size",
+            graph_dim(&session, "logits", false, 2)?,
+            config.vocab_size,
+        )?;
+        if config.pad_token_id >= config.vocab_size {
+            return Err(Error::Config(format!(
+                "pad_token_id {} is outside the vocabulary of size {}",
+                config.pad_token_id, config.vocab_size
+            )));
+        }
 
         Ok(Self { session, config })
     }
+
+    /// Feature dimension of the `input_features` input, if the graph fixes it
+    pub fn input_feature_size(&self) -> Result<Option<usize>> {
+        graph_dim(&self.session, "input_features", true, 2)
+    }
+
+    /// Run the model on log-mel features of shape `(time, feature_size)`, returning
+    /// logits of shape `(frames, vocab_size)`.
     pub fn forward(&mut self, features: Array2<f32>) -> Result<Array2<f32>> {
         let batch_size = 1;
         let time_steps = features.shape()[0];
@@ -1,5 +1,12 @@
+use crate::biasing::BiasTrie;
+use crate::confidence::ConfidenceMeasure;
+use crate::config::{graph_dim, graph_shape, static_dim};
+use crate::decoding::{BeamSearchConfig, Fusion, TokenControls};
 use crate::error::{Error, Result};
 use crate::execution::ModelConfig as ExecutionConfig;
+use crate::tdt_beam::{
+    self, JointOutput, TdtHypothesis, TdtJoint, DEFAULT_DURATION_BINS, MAX_SYMBOLS_PER_STEP,
+};
 use ndarray::{Array1, Array2, Array3};
 use ort::session::Session;
 use std::path::{Path, PathBuf};
@@ -8,12 +15,17 @@ use std::path::{Path, PathBuf};
 #[derive(Debug, Clone)]
 pub struct TDTModelConfig {
     pub vocab_size: usize,
+    /// Encoder frames each duration output advances decoding by
+    pub duration_bins: Vec<usize>,
 }
 
 impl TDTModelConfig {
     /// Create config with specified vocab size
     pub fn new(vocab_size: usize) -> Self {
-        Self { vocab_size }
+        Self {
+            vocab_size,
+            duration_bins: DEFAULT_DURATION_BINS.to_vec(),
+        }
     }
 }
 
@@ -53,12 +65,38 @@ impl ParakeetTDTModel {
         let builder = exec_config.apply_to_session_builder(builder)?;
         let decoder_joint = builder.commit_from_file(&decoder_joint_path)?;
 
+        // Joint output holds the token logits followed by the duration logits
+        let joint_shape = graph_shape(&decoder_joint, "outputs", false)?;
+        if let Some(joint_dim) = static_dim(joint_shape.last()) {
+            if joint_dim <= vocab_size {
+                return Err(Error::Config(format!(
+                    "Vocabulary size mismatch: decoder_joint outputs {joint_dim} logits, \
+                     which leaves no duration logits for a vocabulary of {vocab_size}"
+                )));
+            }
+        }
+
         Ok(Self {
             encoder,
             decoder_joint,
             config,
         })
     }
+
+    /// Use the model's duration bins (`model_defaults.tdt_durations` in NeMo configs):
+    /// the number of encoder frames each duration output skips
+    pub fn with_duration_bins(mut self, duration_bins: Vec<usize>) -> Self {
+        if !duration_bins.is_empty() {
+            self.config.duration_bins = duration_bins;
+        }
+        self
+    }
+
+    /// Feature dimension of the encoder's `audio_signal` input, if the graph fixes it
+    pub fn input_feature_size(&self) -> Result<Option<usize>> {
+        graph_dim(&self.encoder, "audio_signal", true, 1)
+    }
+
     //file names simply from: https://huggingface.co/istupakov/parakeet-tdt-0.6b-v3-onnx/tree/main
     fn find_encoder(dir: &Path) -> Result<PathBuf> {
         let candidates = [
@@ -89,7 +127,6 @@ impl ParakeetTDTModel {
         )))
     }
 
-
     fn find_decoder_joint(dir: &Path) -> Result<PathBuf> {
         let candidates = [
             "decoder_joint-model.onnx",
@@ -109,21 +146,69 @@ impl ParakeetTDTModel {
         )))
     }
 
-    /// Run greedy decoding - returns (token_ids, frame_indices, durations)
+    /// Encode log-mel features of shape `(time, feature_size)` and run greedy decoding -
+    /// returns (token_ids, frame_indices, durations)
     pub fn forward(
         &mut self,
         features: Array2<f32>,
     ) -> Result<(Vec<usize>, Vec<usize>, Vec<usize>)> {
+        let controls = TokenControls::default();
+        let best = self.decode_greedy(features, None, ConfidenceMeasure::default(), &controls)?;
+        let tokens = best.tokens.iter().map(|&id| id as usize).collect();
+        Ok((tokens, best.frames, best.durations))
+    }
+
+    /// Greedy decoding with optional hotword boosts and token controls, as a hypothesis
+    /// with per-token confidences
+    pub(crate) fn decode_greedy(
+        &mut self,
+        features: Array2<f32>,
+        bias: Option<&BiasTrie>,
+        confidence: ConfidenceMeasure,
+        controls: &TokenControls,
+    ) -> Result<TdtHypothesis> {
         // Run encoder
         let (encoder_out, encoder_len) = self.run_encoder(&features)?;
 
         // Run greedy decoding with decoder_joint
-        let (tokens, frame_indices, durations) = self.greedy_decode(&encoder_out, encoder_len)?;
+        self.greedy_decode(&encoder_out, encoder_len, bias, confidence, controls)
+    }
 
-        Ok((tokens, frame_indices, durations))
+    /// Encode features and run beam search; hypotheses come best first
+    pub(crate) fn beam_search(
+        &mut self,
+        features: Array2<f32>,
+        config: &BeamSearchConfig,
+        fusion: Fusion,
+        confidence: ConfidenceMeasure,
+        controls: &TokenControls,
+    ) -> Result<Vec<TdtHypothesis>> {
+        let (encoder_out, _) = self.run_encoder(&features)?;
+        let vocab_size = self.config.vocab_size;
+        let mut joint = OnnxJoint {
+            session: &mut self.decoder_joint,
+            encoder_out: &encoder_out,
+            vocab_size,
+            duration_bins: &self.config.duration_bins,
+            controls,
+        };
+        let time_steps = encoder_out.shape()[2];
+        let blank_id = vocab_size - 1;
+        let max_symbols = controls.max_symbols(MAX_SYMBOLS_PER_STEP);
+        tdt_beam::beam_search(
+            &mut joint,
+            time_steps,
+            blank_id,
+            config,
+            fusion,
+            confidence,
+            max_symbols,
+        )
     }
 
-    fn run_encoder(&mut self, features: &Array2<f32>) -> Result<(Array3<f32>, i64)> {
+    /// Encode log-mel features of shape `(time, feature_size)` - returns the encoder
+    /// output `[1, encoder_dim, frames]` and the number of valid frames
+    pub fn run_encoder(&mut self, features: &Array2<f32>) -> Result<(Array3<f32>, i64)> {
         let batch_size = 1;
         let time_steps = features.shape()[0];
         let feature_size = features.shape()[1];
@@ -178,124 +263,115 @@ impl ParakeetTDTModel {
         &mut self,
         encoder_out: &Array3<f32>,
         _encoder_len: i64,
-    ) -> Result<(Vec<usize>, Vec<usize>, Vec<usize>)> {
+        bias: Option<&BiasTrie>,
+        confidence: ConfidenceMeasure,
+        controls: &TokenControls,
+    ) -> Result<TdtHypothesis> {
         // encoder_out shape: [batch, encoder_dim, time]
-        let encoder_dim = encoder_out.shape()[1];
         let time_steps = encoder_out.shape()[2];
         let vocab_size = self.config.vocab_size;
-        let max_tokens_per_step = 10;
         let blank_id = vocab_size - 1;
+        let max_symbols = controls.max_symbols(MAX_SYMBOLS_PER_STEP);
+        let mut joint = OnnxJoint {
+            session: &mut self.decoder_joint,
+            encoder_out,
+            vocab_size,
+            duration_bins: &self.config.duration_bins,
+            controls,
+        };
+        tdt_beam::greedy_search(
+            &mut joint,
+            time_steps,
+            blank_id,
+            bias,
+            confidence,
+            max_symbols,
+        )
+    }
+}
 
-        // States: (num_layers=2, batch=1, hidden_dim=640)
-        let mut state_h = Array3::<f32>::zeros((2, 1, 640));
-        let mut state_c = Array3::<f32>::zeros((2, 1, 640));
-
-        let mut tokens = Vec::new();
-        let mut frame_indices = Vec::new();
-        let mut durations = Vec::new();
-
-        let mut t = 0;
-        let mut emitted_tokens = 0;
-        let mut last_emitted_token = blank_id as i32;
-
-        // Frame-by-frame RNN-T/TDT greedy decoding
-        while t < time_steps {
-            // Get single encoder frame: slice [0, :, t] and reshape to [1, encoder_dim, 1]
-            let frame = encoder_out.slice(ndarray::s![0, .., t]).to_owned();
-            let frame_reshaped = frame
-                .to_shape((1, encoder_dim, 1))
-                .map_err(|e| Error::Model(format!("Failed to reshape frame: {e}")))?
-                .to_owned();
-
-            // Current token for prediction network
-            let targets = Array2::from_shape_vec((1, 1), vec![last_emitted_token])
-                .map_err(|e| Error::Model(format!("Failed to create targets: {e}")))?;
-
-            // Run decoder_joint
-            let outputs = self.decoder_joint.run(ort::inputs!(
-                "encoder_outputs" => ort::value::Value::from_array(frame_reshaped)?,
-                "targets" => ort::value::Value::from_array(targets)?,
-                "target_length" => ort::value::Value::from_array(Array1::from_vec(vec![1i32]))?,
-                "input_states_1" => ort::value::Value::from_array(state_h.clone())?,
-                "input_states_2" => ort::value::Value::from_array(state_c.clone())?
-            ))?;
-
-            // Extract logits
-            let (_, logits_data) = outputs["outputs"]
-                .try_extract_tensor::<f32>()
-                .map_err(|e| Error::Model(format!("Failed to extract logits: {e}")))?;
-
-            // TDT outputs vocab_size + 5 durations
-            let vocab_logits: Vec<f32> = logits_data.iter().take(vocab_size).copied().collect();
-            let duration_logits: Vec<f32> = logits_data.iter().skip(vocab_size).copied().collect();
-
-            let token_id = vocab_logits
-                .iter()
-                .enumerate()
-                .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
-                .map(|(idx, _)| idx)
-                .unwrap_or(blank_id);
-
-            let duration_step = if !duration_logits.is_empty() {
-                duration_logits
-                    .iter()
-                    .enumerate()
-                    .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
-                    .map(|(idx, _)| idx)
-                    .unwrap_or(0)
-            } else {
-                0
-            };
-
-            // Check if blank token
-            if token_id != blank_id {
-                // Update states when we emit a token
-                if let Ok((h_shape, h_data)) =
-                    outputs["output_states_1"].try_extract_tensor::<f32>()
-                {
-                    let dims = h_shape.as_ref();
-                    state_h = Array3::from_shape_vec(
-                        (dims[0] as usize, dims[1] as usize, dims[2] as usize),
-                        h_data.to_vec(),
-                    )
-                    .map_err(|e| Error::Model(format!("Failed to update state_h: {e}")))?;
-                }
-                if let Ok((c_shape, c_data)) =
-                    outputs["output_states_2"].try_extract_tensor::<f32>()
-                {
-                    let dims = c_shape.as_ref();
-                    state_c = Array3::from_shape_vec(
-                        (dims[0] as usize, dims[1] as usize, dims[2] as usize),
-                        c_data.to_vec(),
-                    )
-                    .map_err(|e| Error::Model(format!("Failed to update state_c: {e}")))?;
-                }
+/// decoder_joint session over one utterance's encoder output
+struct OnnxJoint<'a> {
+    session: &'a mut Session,
+    // [batch, encoder_dim, time]
+    encoder_out: &'a Array3<f32>,
+    vocab_size: usize,
+    duration_bins: &'a [usize],
+    // Applied to the token logits of every step
+    controls: &'a TokenControls,
+}
 
-                tokens.push(token_id);
-                frame_indices.push(t);
-                durations.push(duration_step);
-                last_emitted_token = token_id as i32;
-                emitted_tokens += 1;
-
-                // Don't advance yet - try to emit more tokens from the same frame
-            } else {
-                // Blank token - advance frame pointer
-                // Duration prediction applies when we finally move to next frame after emitting tokens
-                if duration_step > 0 && emitted_tokens > 0 {
-                    t += duration_step;
-                } else {
-                    t += 1;
-                }
-                emitted_tokens = 0;
-            }
+impl TdtJoint for OnnxJoint<'_> {
+    // LSTM (h, c), each (num_layers=2, batch=1, hidden_dim=640)
+    type State = (Array3<f32>, Array3<f32>);
+
+    fn initial_state(&self) -> Self::State {
+        (Array3::zeros((2, 1, 640)), Array3::zeros((2, 1, 640)))
+    }
 
-            // Safety check: if we've emitted too many tokens from the same frame, advance
-            if emitted_tokens >= max_tokens_per_step {
-                t += 1;
-                emitted_tokens = 0;
+    fn duration_bins(&self) -> &[usize] {
+        self.duration_bins
+    }
+
+    fn step(
+        &mut self,
+        frame: usize,
+        token: u32,
+        state: &Self::State,
+    ) -> Result<JointOutput<Self::State>> {
+        let encoder_dim = self.encoder_out.shape()[1];
+
+        // Get single encoder frame: slice [0, :, t] and reshape to [1, encoder_dim, 1]
+        let frame = self.encoder_out.slice(ndarray::s![0, .., frame]).to_owned();
+        let frame_reshaped = frame
+            .to_shape((1, encoder_dim, 1))
+            .map_err(|e| Error::Model(format!("Failed to reshape frame: {e}")))?
+            .to_owned();
+
+        // Current token for prediction network
+        let targets = Array2::from_shape_vec((1, 1), vec![token as i32])
+            .map_err(|e| Error::Model(format!("Failed to create targets: {e}")))?;
+
+        let outputs = self.session.run(ort::inputs!(
+            "encoder_outputs" => ort::value::Value::from_array(frame_reshaped)?,
+            "targets" => ort::value::Value::from_array(targets)?,
+            "target_length" => ort::value::Value::from_array(Array1::from_vec(vec![1i32]))?,
+            "input_states_1" => ort::value::Value::from_array(state.0.clone())?,
+            "input_states_2" => ort::value::Value::from_array(state.1.clone())?
+        ))?;
+
+        // Extract logits
+        let (_, logits_data) = outputs["outputs"]
+            .try_extract_tensor::<f32>()
+            .map_err(|e| Error::Model(format!("Failed to extract logits: {e}")))?;
+
+        // TDT outputs vocab_size + 5 durations
+        let mut token_logits: Vec<f32> =
+            logits_data.iter().take(self.vocab_size).copied().collect();
+        let model_logits = (!self.controls.is_empty()).then(|| token_logits.clone());
+        self.controls.apply(&mut token_logits);
+        let duration_logits: Vec<f32> = logits_data.iter().skip(self.vocab_size).copied().collect();
+
+        let mut next = state.clone();
+        for (name, slot) in [
+            ("output_states_1", &mut next.0),
+            ("output_states_2", &mut next.1),
+        ] {
+            if let Ok((shape, data)) = outputs[name].try_extract_tensor::<f32>() {
+                let dims = shape.as_ref();
+                *slot = Array3::from_shape_vec(
+                    (dims[0] as usize, dims[1] as usize, dims[2] as usize),
+                    data.to_vec(),
+                )
+                .map_err(|e| Error::Model(format!("Failed to update {name}: {e}")))?;
             }
         }
 
-        Ok((tokens, frame_indices, durations))
+        Ok(JointOutput {
+            token_logits,
+            model_logits,
+            duration_logits,
+            state: next,
+        })
     }
 }
@@ -0,0 +1,172 @@
+//! Minimal NumPy `.npy` reader/writer for feature matrices.
+//!
+//! Lets features be cached between decoding runs and compared against arrays dumped
+//! from NeMo (`np.save("feats.npy", processed_signal.cpu().numpy())`). Only what that
+//! needs is supported: little-endian `f4`/`f8` arrays of two dimensions, or three with
+//! a leading batch dimension of 1.
+
+use crate::error::{Error, Result};
+use ndarray::{Array2, ShapeBuilder};
+use std::path::Path;
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Save a 2D f32 array as `.npy` (version 1.0, little-endian, C order).
+pub fn save_npy<P: AsRef<Path>>(path: P, array: &Array2<f32>) -> Result<()> {
+    std::fs::write(path, encode_npy(array))?;
+    Ok(())
+}
+
+/// Load a 2D float array from `.npy`.
+///
+/// `(1, rows, cols)` arrays (a batch of one, as NeMo produces) are squeezed to
+/// `(rows, cols)`. `f8` data is converted to f32. Note that NeMo stores features as
+/// `(n_mels, time)`, while this crate uses `(time, n_mels)`; transpose as needed.
+pub fn load_npy<P: AsRef<Path>>(path: P) -> Result<Array2<f32>> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path)?;
+    decode_npy(&bytes).map_err(|e| Error::Config(format!("{}: {e}", path.display())))
+}
+
+fn encode_npy(array: &Array2<f32>) -> Vec<u8> {
+    let (rows, cols) = array.dim();
+    let mut header =
+        format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({rows}, {cols}), }}");
+    // Pad so the data starts on a 64-byte boundary, header ends with '\n'
+    let unpadded = MAGIC.len() + 2 + 2 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    let mut out = Vec::with_capacity(MAGIC.len() + 4 + header.len() + rows * cols * 4);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&[1, 0]);
+    out.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    out.extend_from_slice(header.as_bytes());
+    for &x in array.iter() {
+        out.extend_from_slice(&x.to_le_bytes());
+    }
+    out
+}
+
+fn decode_npy(bytes: &[u8]) -> std::result::Result<Array2<f32>, String> {
+    if bytes.len() < 10 || &bytes[..6] != MAGIC {
+        return Err("not a .npy file".to_string());
+    }
+    let (header_len, header_start) = match bytes[6] {
+        1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+        2 | 3 if bytes.len() >= 12 => (
+            u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize,
+            12,
+        ),
+        v => return Err(format!("unsupported .npy version {v}")),
+    };
+    let data_start = header_start + header_len;
+    let header = bytes
+        .get(header_start..data_start)
+        .and_then(|h| std::str::from_utf8(h).ok())
+        .ok_or("truncated .npy header")?;
+
+    let descr = header_value(header, "descr")
+        .map(|v| v.trim_matches(|c| c == '\'' || c == '"'))
+        .ok_or("missing 'descr' in .npy header")?;
+    let fortran_order = header_value(header, "fortran_order")
+        .ok_or("missing 'fortran_order' in .npy header")?
+        == "True";
+    let shape = parse_shape(header)?;
+
+    let (rows, cols) = match shape[..] {
+        [rows, cols] | [1, rows, cols] => (rows, cols),
+        _ => return Err(format!("expected a 2D array, got shape {shape:?}")),
+    };
+
+    let data = &bytes[data_start..];
+    let values: Vec<f32> = match descr {
+        "<f4" | "f4" => read_values(data, rows * cols, |b: [u8; 4]| f32::from_le_bytes(b))?,
+        "<f8" | "f8" => read_values(data, rows * cols, |b: [u8; 8]| f64::from_le_bytes(b) as f32)?,
+        other => return Err(format!("unsupported dtype '{other}', expected <f4 or <f8")),
+    };
+
+    let shape = (rows, cols).set_f(fortran_order);
+    Array2::from_shape_vec(shape, values).map_err(|e| e.to_string())
+}
+
+fn read_values<const N: usize>(
+    data: &[u8],
+    count: usize,
+    convert: impl Fn([u8; N]) -> f32,
+) -> std::result::Result<Vec<f32>, String> {
+    if data.len() < count * N {
+        return Err(format!(
+            "expected {} bytes of data, found {}",
+            count * N,
+            data.len()
+        ));
+    }
+    Ok(data
+        .chunks_exact(N)
+        .take(count)
+        .map(|chunk| convert(chunk.try_into().unwrap()))
+        .collect())
+}
+
+// Raw text of a scalar entry in the header dict, e.g. `'<f4'` or `False`
+fn header_value<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    let start = header.find(&format!("'{key}'"))? + key.len() + 2;
+    let rest = header[start..].trim_start().strip_prefix(':')?;
+    let end = rest.find(',').unwrap_or(rest.len());
+    Some(rest[..end].trim())
+}
+
+fn parse_shape(header: &str) -> std::result::Result<Vec<usize>, String> {
+    let start = header
+        .find("'shape'")
+        .and_then(|i| header[i..].find('(').map(|j| i + j + 1))
+        .ok_or("missing 'shape' in .npy header")?;
+    let end = header[start..]
+        .find(')')
+        .map(|j| start + j)
+        .ok_or("malformed 'shape' in .npy header")?;
+
+    header[start..end]
+        .split(',')
+        .map(str::trim)
+        .filter(|dim| !dim.is_empty())
+        .map(|dim| {
+            dim.parse()
+                .map_err(|_| format!("invalid dimension '{dim}'"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array;
+
+    #[test]
+    fn test_roundtrip() {
+        let array = Array::from_shape_fn((7, 3), |(t, m)| t as f32 * 0.5 - m as f32);
+        let bytes = encode_npy(&array);
+        assert_eq!((bytes.len() - 7 * 3 * 4) % 64, 0);
+        assert_eq!(decode_npy(&bytes).unwrap(), array);
+    }
+
+    #[test]
+    fn test_numpy_variants() {
+        // np.save of a (1, 2, 3) float64 array in Fortran order
+        let header = "{'descr': '<f8', 'fortran_order': True, 'shape': (1, 2, 3), }";
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[1, 0]);
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        // Column-major: [[0, 1, 2], [3, 4, 5]] stored as 0, 3, 1, 4, 2, 5
+        for x in [0.0f64, 3.0, 1.0, 4.0, 2.0, 5.0] {
+            bytes.extend_from_slice(&x.to_le_bytes());
+        }
+
+        let array = decode_npy(&bytes).unwrap();
+        assert_eq!(array, ndarray::array![[0.0, 1.0, 2.0], [3.0, 4.0, 5.0]]);
+
+        assert!(decode_npy(b"not numpy").is_err());
+    }
+}
@@ -1,17 +1,38 @@
-use crate::audio;
-use crate::config::PreprocessorConfig;
-use crate::decoder::{ParakeetDecoder, TranscriptionResult};
+use crate::align::{group_sentences, normalize_word, segmental_align, Alignment};
+use crate::audio::{self, InputLimits, ResampleQuality};
+use crate::config::{
+    check_dim, max_duration_from_model_dir, nemo_config_from_model_dir, PreprocessorConfig,
+};
+use crate::ctc_beam::log_softmax;
+use crate::decoder::{ParakeetDecoder, TimedToken, TranscriptionResult};
+use crate::decoding::{DecodingOptions, DecodingStrategy};
 use crate::error::{Error, Result};
 use crate::execution::ModelConfig as ExecutionConfig;
+use crate::frames::Frames;
+use crate::keywords::{spot, KeywordDetection};
+use crate::mel::{check_features, DitherConfig, MelConfig, MelFrontend};
 use crate::model::ParakeetModel;
 use crate::timestamps::{process_timestamps, TimestampMode};
 use crate::transcriber::Transcriber;
+use ndarray::{s, Array2};
 use std::path::{Path, PathBuf};
 
+// Log-probs of long input (in 10ms feature frames, multiples of the 8x subsampling):
+// encoder windows with context on both sides
+const LONG_WINDOW_FRAMES: usize = 6000;
+const LONG_CONTEXT_FRAMES: usize = 400;
+// Output frames aligned per segmental alignment step (~30s at 80ms)
+const ALIGN_SEGMENT_FRAMES: usize = 375;
+
 pub struct Parakeet {
     model: ParakeetModel,
     decoder: ParakeetDecoder,
     preprocessor_config: PreprocessorConfig,
+    frontend: MelFrontend,
+    resample_quality: ResampleQuality,
+    // `None` = unchecked, see `max_duration()`
+    max_duration: Option<f32>,
+    decoding: DecodingOptions,
     model_dir: PathBuf,
 }
 
@@ -70,16 +91,31 @@ impl Parakeet {
             )));
         }
 
-        let preprocessor_config = PreprocessorConfig::default();
+        let preprocessor_config =
+            PreprocessorConfig::from_model_dir(&model_dir, PreprocessorConfig::default())?;
         let exec_config = config.unwrap_or_default();
 
         let model = ParakeetModel::from_pretrained_with_config(&model_path, exec_config)?;
-        let decoder = ParakeetDecoder::from_pretrained(&tokenizer_path)?;
+        check_dim(
+            "Feature size",
+            model.input_feature_size()?,
+            preprocessor_config.feature_size,
+        )?;
+        let decoder = ParakeetDecoder::from_pretrained(&tokenizer_path)?
+            .with_pad_token_id(model.pad_token_id());
+
+        let max_duration = max_duration_from_model_dir(&model_dir, &preprocessor_config)?;
+        let mel_config = nemo_config_from_model_dir(&model_dir)?
+            .mel_config(MelConfig::parakeet(&preprocessor_config))?;
 
         Ok(Self {
             model,
             decoder,
+            frontend: MelFrontend::new(mel_config),
             preprocessor_config,
+            resample_quality: ResampleQuality::default(),
+            max_duration,
+            decoding: DecodingOptions::default(),
             model_dir,
         })
     }
@@ -122,25 +158,104 @@ impl Parakeet {
     pub fn preprocessor_config(&self) -> &PreprocessorConfig {
         &self.preprocessor_config
     }
-}
 
-impl Transcriber for Parakeet {
-    fn transcribe_samples(
-        &mut self,
+    /// Set the resampler quality used when input audio is not at the model sample rate.
+    pub fn set_resample_quality(&mut self, quality: ResampleQuality) {
+        self.resample_quality = quality;
+    }
+
+    pub fn resample_quality(&self) -> ResampleQuality {
+        self.resample_quality
+    }
+
+    /// Add seeded Gaussian dither to the waveform before feature extraction (off by default).
+    ///
+    /// NeMo trains with `dither=1e-5`; [`DitherConfig::nemo`] matches that. Output stays
+    /// reproducible: the same seed always produces the same features.
+    pub fn set_dither(&mut self, dither: Option<DitherConfig>) {
+        self.frontend.set_dither(dither);
+    }
+
+    /// Longest audio accepted in one call, in seconds, or `None` when the length is not
+    /// checked at all.
+    ///
+    /// Derived from `encoder.pos_emb_max_len` when the model directory has a
+    /// `model_config.yaml` that sets it; longer input is rejected with
+    /// [`Error::AudioTooLong`]. Unlike [`crate::ParakeetTDT`], which assumes NeMo's
+    /// default table length, CTC exports without that setting get `None`: any length
+    /// is passed to the runtime, which fails with [`Error::Ort`] if the encoder
+    /// can't take it. Use [`Parakeet::set_max_duration`] to impose a limit.
+    pub fn max_duration(&self) -> Option<f32> {
+        self.max_duration
+    }
+
+    /// Override the duration limit, e.g. for an export with a longer positional encoding
+    pub fn set_max_duration(&mut self, max_duration: Option<f32>) {
+        self.max_duration = max_duration;
+    }
+
+    /// Switch between greedy decoding (default) and CTC prefix beam search, and set the
+    /// hotwords to boost and the token controls. Fails if a hotword cannot be tokenized
+    /// or a token id is out of range.
+    pub fn set_decoding_options(&mut self, options: DecodingOptions) -> Result<()> {
+        self.decoder.set_decoding_options(&options)?;
+        self.decoding = options;
+        Ok(())
+    }
+
+    pub fn decoding_options(&self) -> &DecodingOptions {
+        &self.decoding
+    }
+
+    /// Feature extractor configured for this model
+    pub fn frontend(&self) -> &MelFrontend {
+        &self.frontend
+    }
+
+    /// Compute the log-mel features the model is fed, shape `(time, feature_size)`.
+    ///
+    /// Audio is mixed down and resampled the same way as in [`Transcriber::transcribe_samples`].
+    pub fn extract_features(
+        &self,
         audio: Vec<f32>,
         sample_rate: u32,
         channels: u16,
+    ) -> Result<Array2<f32>> {
+        let limits = InputLimits::for_frontend(&self.frontend, self.max_duration);
+        audio::validate_audio(&audio, sample_rate, channels, &limits)?;
+        audio::extract_features_raw(
+            audio,
+            sample_rate,
+            channels,
+            &self.frontend,
+            self.resample_quality,
+        )
+    }
+
+    /// Transcribe precomputed features of shape `(time, feature_size)`, e.g. from
+    /// [`Parakeet::extract_features`] or [`crate::load_npy`].
+    pub fn transcribe_features(
+        &mut self,
+        features: Array2<f32>,
         mode: Option<TimestampMode>,
     ) -> Result<TranscriptionResult> {
-        let features =
-            audio::extract_features_raw(audio, sample_rate, channels, &self.preprocessor_config)?;
+        check_features(&features, self.frontend.config(), self.max_duration)?;
         let logits = self.model.forward(features)?;
 
-        let mut result = self.decoder.decode_with_timestamps(
-            &logits,
-            self.preprocessor_config.hop_length,
-            self.preprocessor_config.sampling_rate,
-        )?;
+        let hop_length = self.preprocessor_config.hop_length;
+        let sample_rate = self.preprocessor_config.sampling_rate;
+        let mut result = match &self.decoding.strategy {
+            DecodingStrategy::Greedy => {
+                self.decoder
+                    .decode_with_timestamps(&logits, hop_length, sample_rate)?
+            }
+            DecodingStrategy::Beam(config) => self.decoder.decode_beam_with_timestamps(
+                &logits,
+                config,
+                hop_length,
+                sample_rate,
+            )?,
+        };
 
         // Process timestamps to requested output mode
         let mode = mode.unwrap_or(TimestampMode::Tokens);
@@ -156,4 +271,288 @@ impl Transcriber for Parakeet {
 
         Ok(result)
     }
+
+    /// Find where each word of a known transcript is spoken (CTC forced alignment).
+    ///
+    /// The transcript is lowercased and stripped of punctuation for the CTC vocabulary,
+    /// but words are reported as written. Numbers should be spelled out as they are
+    /// spoken. There is no length limit: long audio is encoded in windows and aligned
+    /// segment by segment.
+    ///
+    /// # Errors
+    /// [`Error::Alignment`] if the transcript has no alignable words or more tokens
+    /// than the audio has frames, plus the input errors of [`Parakeet::extract_features`].
+    pub fn align(
+        &mut self,
+        audio: Vec<f32>,
+        sample_rate: u32,
+        channels: u16,
+        transcript: &str,
+    ) -> Result<Alignment> {
+        let limits = InputLimits::for_frontend(&self.frontend, None);
+        audio::validate_audio(&audio, sample_rate, channels, &limits)?;
+        let features = audio::extract_features_raw(
+            audio,
+            sample_rate,
+            channels,
+            &self.frontend,
+            self.resample_quality,
+        )?;
+        self.align_features(features, transcript)
+    }
+
+    /// [`Parakeet::align`] for an audio file
+    pub fn align_file<P: AsRef<Path>>(&mut self, path: P, transcript: &str) -> Result<Alignment> {
+        let (audio, spec) = audio::load_audio(path.as_ref())?;
+        self.align(audio, spec.sample_rate, spec.channels, transcript)
+    }
+
+    /// Find where the given phrases are spoken, without transcribing.
+    ///
+    /// Every phrase is matched against the CTC log-probabilities with a sliding
+    /// alignment, so it is found even where greedy decoding would misspell it. Phrases
+    /// are normalized like [`Parakeet::align`] transcripts. Detections score in
+    /// `(0, 1]`, the per-frame geometric mean of the phrase's probability relative to the
+    /// best token, so 1 means the phrase is exactly the most probable path over its
+    /// frames. The score ranks matches and is not a probability. Those below `threshold`
+    /// are dropped (0.5 is a reasonable start), as are those overlapping a better
+    /// detection of the same phrase. Results are in time order.
+    ///
+    /// # Errors
+    /// [`Error::Config`] if a phrase has nothing to spot after normalization, plus the
+    /// input errors of [`Parakeet::log_probs`].
+    pub fn spot_keywords(
+        &mut self,
+        audio: Vec<f32>,
+        sample_rate: u32,
+        channels: u16,
+        phrases: &[&str],
+        threshold: f32,
+    ) -> Result<Vec<KeywordDetection>> {
+        let log_probs = self.log_probs(audio, sample_rate, channels)?;
+        self.spot_in_log_probs(&log_probs, phrases, threshold)
+    }
+
+    /// [`Parakeet::spot_keywords`] for an audio file
+    pub fn spot_keywords_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        phrases: &[&str],
+        threshold: f32,
+    ) -> Result<Vec<KeywordDetection>> {
+        let (audio, spec) = audio::load_audio(path.as_ref())?;
+        self.spot_keywords(audio, spec.sample_rate, spec.channels, phrases, threshold)
+    }
+
+    /// [`Parakeet::spot_keywords`] for precomputed features of shape
+    /// `(time, feature_size)`
+    pub fn spot_keywords_features(
+        &mut self,
+        features: Array2<f32>,
+        phrases: &[&str],
+        threshold: f32,
+    ) -> Result<Vec<KeywordDetection>> {
+        let log_probs = self.log_probs_features(features)?;
+        self.spot_in_log_probs(&log_probs, phrases, threshold)
+    }
+
+    fn spot_in_log_probs(
+        &self,
+        log_probs: &Frames,
+        phrases: &[&str],
+        threshold: f32,
+    ) -> Result<Vec<KeywordDetection>> {
+        let blank = self.decoder.pad_token_id();
+        let mut detections = Vec::new();
+        for &phrase in phrases {
+            let normalized: Vec<String> = phrase
+                .split_whitespace()
+                .map(normalize_word)
+                .filter(|word| !word.is_empty())
+                .collect();
+            let tokens = self
+                .decoder
+                .tokenizer()
+                .encode(normalized.join(" "), false)
+                .map_err(|e| Error::Tokenizer(format!("Failed to encode keyword: {e}")))?
+                .get_ids()
+                .to_vec();
+            if tokens.is_empty() {
+                return Err(Error::Config(format!(
+                    "Keyword '{phrase}' has nothing to spot"
+                )));
+            }
+
+            for (start, end, score) in spot(&log_probs.values, &tokens, blank, threshold) {
+                detections.push(KeywordDetection {
+                    phrase: phrase.to_string(),
+                    start: log_probs.time(start),
+                    end: log_probs.time(end),
+                    score,
+                });
+            }
+        }
+        detections.sort_by(|a, b| a.start.total_cmp(&b.start));
+        Ok(detections)
+    }
+
+    /// [`Parakeet::align`] for precomputed features of shape `(time, feature_size)`
+    pub fn align_features(&mut self, features: Array2<f32>, transcript: &str) -> Result<Alignment> {
+        // Tokenize word by word to know which word every token belongs to
+        let tokenizer = self.decoder.tokenizer();
+        let mut words: Vec<String> = Vec::new();
+        let mut tokens = Vec::new();
+        let mut token_words = Vec::new();
+        for word in transcript.split_whitespace() {
+            let normalized = normalize_word(word);
+            let ids = if normalized.is_empty() {
+                Vec::new()
+            } else {
+                tokenizer
+                    .encode(normalized.as_str(), false)
+                    .map_err(|e| Error::Tokenizer(format!("Failed to encode transcript: {e}")))?
+                    .get_ids()
+                    .to_vec()
+            };
+            match (ids.is_empty(), words.last_mut()) {
+                // Punctuation on its own stays with the previous word
+                (true, Some(last)) => {
+                    last.push(' ');
+                    last.push_str(word);
+                }
+                (true, None) => {}
+                (false, _) => {
+                    tokens.extend_from_slice(&ids);
+                    token_words.extend(std::iter::repeat_n(words.len(), ids.len()));
+                    words.push(word.to_string());
+                }
+            }
+        }
+        if tokens.is_empty() {
+            return Err(Error::Alignment(
+                "transcript has no words to align".to_string(),
+            ));
+        }
+
+        let log_probs = self.log_probs_features(features)?;
+        let blank = self.decoder.pad_token_id();
+        let spans = segmental_align(&log_probs.values, &tokens, blank, ALIGN_SEGMENT_FRAMES)?;
+
+        let frame_secs = log_probs.frame_secs;
+        let tokenizer = self.decoder.tokenizer();
+        let mut alignment = Alignment::default();
+        let mut word_frames = vec![(0.0f32, 0usize); words.len()];
+        for ((&id, span), &word) in tokens.iter().zip(&spans).zip(&token_words) {
+            let piece = tokenizer.id_to_token(id).unwrap_or_default();
+            alignment.tokens.push(TimedToken {
+                text: piece.replace('▁', " "),
+                start: span.start as f32 * frame_secs,
+                end: span.end as f32 * frame_secs,
+                ..Default::default()
+            });
+            word_frames[word].0 += span.log_prob;
+            word_frames[word].1 += span.end - span.start;
+        }
+
+        for (word, text) in words.into_iter().enumerate() {
+            let first = token_words.iter().position(|&w| w == word).unwrap_or(0);
+            let last = token_words.iter().rposition(|&w| w == word).unwrap_or(0);
+            alignment.words.push(TimedToken {
+                text,
+                start: alignment.tokens[first].start,
+                end: alignment.tokens[last].end,
+                ..Default::default()
+            });
+            let (log_prob, frames) = word_frames[word];
+            alignment.word_scores.push(log_prob / frames.max(1) as f32);
+        }
+        alignment.sentences = group_sentences(&alignment.words);
+
+        Ok(alignment)
+    }
+
+    /// CTC log-probabilities (natural log, blank included) of every encoder frame, for
+    /// custom decoders and audio search.
+    ///
+    /// Unlike transcription there is no length limit: long audio is encoded in
+    /// overlapping windows. Decoding options such as token controls are not applied.
+    pub fn log_probs(
+        &mut self,
+        audio: Vec<f32>,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Result<Frames> {
+        let limits = InputLimits::for_frontend(&self.frontend, None);
+        audio::validate_audio(&audio, sample_rate, channels, &limits)?;
+        let features = audio::extract_features_raw(
+            audio,
+            sample_rate,
+            channels,
+            &self.frontend,
+            self.resample_quality,
+        )?;
+        self.log_probs_features(features)
+    }
+
+    /// [`Parakeet::log_probs`] for precomputed features of shape `(time, feature_size)`
+    pub fn log_probs_features(&mut self, features: Array2<f32>) -> Result<Frames> {
+        check_features(&features, self.frontend.config(), None)?;
+        let total = features.nrows();
+        if total <= LONG_WINDOW_FRAMES + 2 * LONG_CONTEXT_FRAMES {
+            let logits = self.model.forward(features)?;
+            let stride = (total as f32 / logits.nrows().max(1) as f32)
+                .round()
+                .max(1.0) as usize;
+            return Ok(self.frames(log_softmax(&logits), stride));
+        }
+
+        let mut windows = Vec::new();
+        let mut stride = 1;
+        for core_start in (0..total).step_by(LONG_WINDOW_FRAMES) {
+            let core_end = (core_start + LONG_WINDOW_FRAMES).min(total);
+            let start = core_start.saturating_sub(LONG_CONTEXT_FRAMES);
+            let end = (core_end + LONG_CONTEXT_FRAMES).min(total);
+            let logits = self
+                .model
+                .forward(features.slice(s![start..end, ..]).to_owned())?;
+            if core_start == 0 {
+                stride = ((end - start) as f32 / logits.nrows().max(1) as f32)
+                    .round()
+                    .max(1.0) as usize;
+            }
+
+            // Keep the frames of the window's core only
+            let first = ((core_start - start) / stride).min(logits.nrows());
+            let last = (core_end - start).div_ceil(stride).min(logits.nrows());
+            windows.push(log_softmax(&logits.slice(s![first..last, ..]).to_owned()));
+        }
+
+        let views: Vec<_> = windows.iter().map(|w| w.view()).collect();
+        let log_probs = ndarray::concatenate(ndarray::Axis(0), &views)
+            .map_err(|e| Error::Model(format!("Failed to join windowed logits: {e}")))?;
+        Ok(self.frames(log_probs, stride))
+    }
+
+    // Frames `stride` feature frames apart from the start of the audio
+    fn frames(&self, values: Array2<f32>, stride: usize) -> Frames {
+        Frames {
+            values,
+            start: 0.0,
+            frame_secs: (stride * self.preprocessor_config.hop_length) as f32
+                / self.preprocessor_config.sampling_rate as f32,
+        }
+    }
+}
+
+impl Transcriber for Parakeet {
+    fn transcribe_samples(
+        &mut self,
+        audio: Vec<f32>,
+        sample_rate: u32,
+        channels: u16,
+        mode: Option<TimestampMode>,
+    ) -> Result<TranscriptionResult> {
+        let features = self.extract_features(audio, sample_rate, channels)?;
+        self.transcribe_features(features, mode)
+    }
 }
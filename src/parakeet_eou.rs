@@ -1,21 +1,21 @@
+use crate::audio::{self, InputLimits, RawAudioFormat, ResampleQuality, Resampler};
+use crate::confidence::ConfidenceMeasure;
+use crate::config::nemo_config_from_model_dir;
+use crate::decoder::TimedToken;
+use crate::decoding::{DecodingOptions, DecodingStrategy, TokenControls};
 use crate::error::{Error, Result};
 use crate::execution::ModelConfig as ExecutionConfig;
+use crate::frames::Frames;
+use crate::mel::{DitherConfig, MelConfig, MelFrontend, StreamingMel};
 use crate::model_eou::{EncoderCache, ParakeetEOUModel};
-use ndarray::{s, Array2, Array3};
-use rustfft::{num_complex::Complex, FftPlanner};
-use std::collections::VecDeque;
-use std::f32::consts::PI;
+use ndarray::{s, Array2, Array3, Axis};
 use std::path::Path;
 
 const SAMPLE_RATE: usize = 16000;
-
-const N_FFT: usize = 512;
-const WIN_LENGTH: usize = 400;
-const HOP_LENGTH: usize = 160;
-const N_MELS: usize = 128;
-const PREEMPH: f32 = 0.97;
-const LOG_ZERO_GUARD: f32 = 5.960_464_5e-8;
-const FMAX: f32 = 8000.0;
+// Encoder output frame: 8x subsampling of 10ms feature frames
+const ENCODER_FRAME_SECS: f32 = 0.08;
+// Most tokens emitted on one encoder frame by default
+const MAX_SYMBOLS_PER_STEP: usize = 5;
 
 /// Parakeet RealTime EOU model for streaming ASR with end-of-utterance detection.
 /// Uses cache-aware streaming with audio buffering for pre-encode context.
@@ -28,10 +28,12 @@ pub struct ParakeetEOU {
     last_token: Array2<i32>,
     blank_id: i32,
     eou_id: i32,
-    mel_basis: Array2<f32>,
-    window: Vec<f32>,
-    audio_buffer: VecDeque<f32>,
-    buffer_size_samples: usize,
+    features: StreamingMel,
+    resampler: Option<Resampler>,
+    resample_quality: ResampleQuality,
+    decoding: DecodingOptions,
+    controls: TokenControls,
+    encoder_frames: Frames,
 }
 
 impl ParakeetEOU {
@@ -59,6 +61,7 @@ impl ParakeetEOU {
 
         let exec_config = config.unwrap_or_default();
         let model = ParakeetEOUModel::from_pretrained(path, exec_config)?;
+        let mel_config = nemo_config_from_model_dir(path)?.mel_config(MelConfig::eou())?;
 
         // Buffer size: 4 seconds of audio
         // Provides long history for feature extraction context
@@ -74,54 +77,154 @@ impl ParakeetEOU {
             last_token: Array2::from_elem((1, 1), blank_id),
             blank_id,
             eou_id,
-            mel_basis: Self::create_mel_filterbank(),
-            window: Self::create_window(),
-            audio_buffer: VecDeque::with_capacity(buffer_size_samples),
-            buffer_size_samples,
+            features: StreamingMel::new(MelFrontend::new(mel_config), buffer_size_samples),
+            resampler: None,
+            resample_quality: ResampleQuality::default(),
+            decoding: DecodingOptions::default(),
+            controls: TokenControls::default(),
+            encoder_frames: Frames::default(),
         })
     }
 
+    /// Declare the sample rate of the chunks passed to [`ParakeetEOU::transcribe`].
+    ///
+    /// Chunks at any rate other than 16kHz are converted with a streaming resampler
+    /// that carries its filter history across chunks, so chunk boundaries stay seamless.
+    /// `quality` also becomes the [`ParakeetEOU::resample_quality`].
+    pub fn set_input_sample_rate(
+        &mut self,
+        sample_rate: u32,
+        quality: ResampleQuality,
+    ) -> Result<()> {
+        self.resample_quality = quality;
+        self.resampler = if sample_rate == SAMPLE_RATE as u32 {
+            None
+        } else {
+            Some(Resampler::new(sample_rate, SAMPLE_RATE as u32, quality)?)
+        };
+        Ok(())
+    }
+
+    /// Set the resampler quality used when [`ParakeetEOU::transcribe_bytes`] switches to
+    /// a new input rate. A resampler that is already running keeps its quality.
+    pub fn set_resample_quality(&mut self, quality: ResampleQuality) {
+        self.resample_quality = quality;
+    }
+
+    pub fn resample_quality(&self) -> ResampleQuality {
+        self.resample_quality
+    }
+
+    /// Add seeded Gaussian dither to incoming audio before feature extraction (off by default).
+    ///
+    /// Noise is keyed by stream position, so results do not depend on how the audio is
+    /// chunked. Set it before streaming; it applies to chunks passed afterwards.
+    pub fn set_dither(&mut self, dither: Option<DitherConfig>) {
+        self.features.set_dither(dither);
+    }
+
+    /// Measure used for the token confidences of [`ParakeetEOU::transcribe_tokens`]
+    pub fn set_confidence_measure(&mut self, measure: ConfidenceMeasure) {
+        self.decoding.confidence = measure;
+    }
+
+    /// Set the confidence measure and token controls used while streaming.
+    ///
+    /// Streaming decoding is greedy: beam search and hotwords are rejected, as are
+    /// out-of-range token ids. The `<EOU>` token can be biased or suppressed like any
+    /// other, which tunes or turns off end-of-utterance detection.
+    pub fn set_decoding_options(&mut self, options: DecodingOptions) -> Result<()> {
+        if matches!(options.strategy, DecodingStrategy::Beam(_)) {
+            return Err(Error::Config(
+                "EOU streaming supports greedy decoding only".to_string(),
+            ));
+        }
+        if options.context_biasing.is_some() {
+            return Err(Error::Config(
+                "EOU streaming does not support context biasing".to_string(),
+            ));
+        }
+        let vocab_size = self
+            .tokenizer
+            .get_vocab_size(true)
+            .max(self.blank_id as usize + 1);
+        self.controls = TokenControls::new(&options, self.blank_id as usize, vocab_size)?;
+        self.decoding = options;
+        Ok(())
+    }
+
+    pub fn decoding_options(&self) -> &DecodingOptions {
+        &self.decoding
+    }
+
     /// Transcribe a chunk of audio samples.
     ///
     /// # Arguments
-    /// * `chunk` - Audio chunk (typically 160ms / 2560 samples at 16kHz)
+    /// * `chunk` - Audio chunk (typically 160ms / 2560 samples at 16kHz), at the rate set
+    ///   with [`ParakeetEOU::set_input_sample_rate`] (16kHz by default)
     /// * `reset_on_eou` - If true, reset decoder state when end-of-utterance is detected
     ///
     /// # Streaming Behavior
     /// Cache-aware streaming
     /// - Maintains 4-second ring buffer for feature extraction context
-    /// - Extracts features from full buffer
-    /// - Slices last (pre_encode_cache + new_frames) for encoder input
+    /// - Computes only the last (pre_encode_cache + new_frames) feature frames of the
+    ///   buffer, reusing frames from earlier chunks that no longer change
     /// - pre_encode_cache=9 frames, new_frames=~16, total=~25 frames to encoder
     pub fn transcribe(&mut self, chunk: &[f32], reset_on_eou: bool) -> Result<String> {
-        // Add new chunk to rolling buffer
-        self.audio_buffer.extend(chunk.iter().copied());
+        let tokens = self.transcribe_tokens(chunk, reset_on_eou)?;
+        Ok(tokens.into_iter().map(|token| token.text).collect())
+    }
 
-        // Trim buffer to keep only the most recent samples
-        while self.audio_buffer.len() > self.buffer_size_samples {
-            self.audio_buffer.pop_front();
+    /// Like [`ParakeetEOU::transcribe`], but returns the decoded tokens with their
+    /// confidence and stream time.
+    ///
+    /// Times are in seconds since the stream started and only resolve the encoder
+    /// frame a token was emitted on (80ms). A detected end of utterance with
+    /// `reset_on_eou` is returned as a final `" [EOU]"` token. An empty chunk (e.g. a
+    /// flush at the end of the stream) decodes nothing.
+    pub fn transcribe_tokens(
+        &mut self,
+        chunk: &[f32],
+        reset_on_eou: bool,
+    ) -> Result<Vec<TimedToken>> {
+        if chunk.is_empty() {
+            self.encoder_frames = Frames::default();
+            return Ok(Vec::new());
         }
+        let input_rate = self
+            .resampler
+            .as_ref()
+            .map_or(SAMPLE_RATE as u32, |r| r.from_rate());
+        audio::validate_audio(chunk, input_rate, 1, &InputLimits::streaming())?;
+
+        // Bring the chunk to 16kHz first if the stream has a different rate
+        let resampled;
+        let chunk = match self.resampler.as_mut() {
+            Some(resampler) => {
+                resampled = resampler.process(chunk);
+                resampled.as_slice()
+            }
+            None => chunk,
+        };
+
+        // Add new chunk to rolling buffer (trimmed to the most recent samples)
+        self.features.push(chunk);
+        self.encoder_frames = Frames::default();
 
         // Wait until buffer has minimum samples (at least 1 second for stable features)
         const MIN_BUFFER_SAMPLES: usize = SAMPLE_RATE; // 1 second
-        if self.audio_buffer.len() < MIN_BUFFER_SAMPLES {
-            return Ok(String::new());
+        if self.features.len() < MIN_BUFFER_SAMPLES {
+            return Ok(Vec::new());
         }
 
-        // Extract features from FULL buffer (provides context for feature extraction)
-        let buffer_slice: Vec<f32> = self.audio_buffer.iter().copied().collect();
-        let full_features = self.extract_mel_features(&buffer_slice);
-        let total_frames = full_features.shape()[2];
-
-        // Slice to take only (pre_encode_cache + new_frames) for encoder
+        // Take only (pre_encode_cache + new_frames) frames of the full-buffer features
         // pre_encode_cache = 9 frames, new_frames = ~16 for 160ms chunk
         const PRE_ENCODE_CACHE: usize = 9;
         const FRAMES_PER_CHUNK: usize = 16;
         const SLICE_LEN: usize = PRE_ENCODE_CACHE + FRAMES_PER_CHUNK;
 
-        let start_frame = total_frames.saturating_sub(SLICE_LEN);
-
-        let features = full_features.slice(s![.., .., start_frame..]).to_owned();
+        // (1, n_mels, T) as expected by the encoder
+        let features = self.features.latest_frames(SLICE_LEN).insert_axis(Axis(0));
         let time_steps = features.shape()[2];
 
         // Encode with cache - encoder sees full buffer context
@@ -132,19 +235,28 @@ impl ParakeetEOU {
 
         let total_frames = encoder_out.shape()[2];
         if total_frames == 0 {
-            return Ok(String::new());
+            return Ok(Vec::new());
         }
 
         // Process all output frames (typically 1 frame per chunk)
         let new_frames = encoder_out;
+        let stream_end = self.features.stream_len() as f32 / SAMPLE_RATE as f32;
+        self.encoder_frames = Frames {
+            values: new_frames.slice(s![0, .., ..]).t().to_owned(),
+            start: (stream_end - total_frames as f32 * ENCODER_FRAME_SECS).max(0.0),
+            frame_secs: ENCODER_FRAME_SECS,
+        };
 
-        let mut text_output = String::new();
+        let mut tokens = Vec::new();
 
         for t in 0..new_frames.shape()[2] {
             let current_frame = new_frames.slice(s![.., .., t..t + 1]).to_owned();
+            // The newest encoder frames cover the end of the stream
+            let start = (stream_end - (total_frames - t) as f32 * ENCODER_FRAME_SECS).max(0.0);
+            let end = start + ENCODER_FRAME_SECS;
             let mut syms_added = 0;
 
-            while syms_added < 5 {
+            while syms_added < self.controls.max_symbols(MAX_SYMBOLS_PER_STEP) {
                 let (logits, new_h, new_c) = self.model.run_decoder(
                     &current_frame,
                     &self.last_token,
@@ -152,7 +264,8 @@ impl ParakeetEOU {
                     &self.state_c,
                 )?;
 
-                let vocab = logits.slice(s![0, 0, ..]);
+                let mut vocab = logits.slice(s![0, 0, ..]).to_vec();
+                self.controls.apply(&mut vocab);
 
                 let mut max_idx = 0;
                 let mut max_val = f32::NEG_INFINITY;
@@ -167,10 +280,21 @@ impl ParakeetEOU {
                     break;
                 }
 
+                // Confidence reflects the model, not the token controls
+                let model_logits = logits.slice(s![0, 0, ..]).to_vec();
+                let confidence = Some(self.decoding.confidence.compute(&model_logits));
+
                 if max_idx == self.eou_id {
                     if reset_on_eou {
                         self.reset_states();
-                        return Ok(text_output + " [EOU]");
+                        tokens.push(TimedToken {
+                            text: " [EOU]".to_string(),
+                            start,
+                            end,
+                            confidence,
+                            ..Default::default()
+                        });
+                        return Ok(tokens);
                     }
                     break;
                 }
@@ -184,12 +308,49 @@ impl ParakeetEOU {
                 self.last_token.fill(max_idx);
 
                 if let Ok(decoded) = self.tokenizer.decode(&[max_idx as u32], true) {
-                    text_output.push_str(&decoded);
+                    tokens.push(TimedToken {
+                        text: decoded,
+                        start,
+                        end,
+                        confidence,
+                        ..Default::default()
+                    });
                 }
                 syms_added += 1;
             }
         }
-        Ok(text_output)
+        Ok(tokens)
+    }
+
+    /// Encoder frames of the chunk last passed to [`ParakeetEOU::transcribe_tokens`] (or
+    /// `transcribe`), shape `(frames, encoder_dim)`, with their time since the stream
+    /// started. Empty while the stream is still filling its first second of context.
+    pub fn encoder_frames(&self) -> &Frames {
+        &self.encoder_frames
+    }
+
+    /// Transcribe a chunk delivered as raw PCM bytes.
+    ///
+    /// The bytes are decoded and mixed to mono according to `format`. When the chunk
+    /// rate differs from the current input rate, the streaming resampler is reconfigured.
+    pub fn transcribe_bytes(
+        &mut self,
+        chunk: &[u8],
+        format: &RawAudioFormat,
+        reset_on_eou: bool,
+    ) -> Result<String> {
+        let samples = audio::decode_raw_pcm(chunk, format)?;
+        let samples = audio::downmix(&samples, format.channels);
+
+        let input_rate = self
+            .resampler
+            .as_ref()
+            .map_or(SAMPLE_RATE as u32, |r| r.from_rate());
+        if input_rate != format.sample_rate {
+            self.set_input_sample_rate(format.sample_rate, self.resample_quality)?;
+        }
+
+        self.transcribe(&samples, reset_on_eou)
     }
 
     fn reset_states(&mut self) {
@@ -199,109 +360,6 @@ impl ParakeetEOU {
         self.state_h.fill(0.0);
         self.state_c.fill(0.0);
         self.last_token.fill(self.blank_id);
-        // self.audio_buffer.clear();  // DON'T clear!!
-    }
-
-    fn extract_mel_features(&self, audio: &[f32]) -> Array3<f32> {
-        let audio_pre = Self::apply_preemphasis(audio);
-        let spec = self.stft(&audio_pre);
-        let mel = self.mel_basis.dot(&spec);
-        let mel_log = mel.mapv(|x| (x.max(0.0) + LOG_ZERO_GUARD).ln());
-        mel_log.insert_axis(ndarray::Axis(0))
-    }
-
-    fn apply_preemphasis(audio: &[f32]) -> Vec<f32> {
-        let mut result = Vec::with_capacity(audio.len());
-        if audio.is_empty() {
-            return result;
-        }
-
-        let safe_x = |x: f32| if x.is_finite() { x } else { 0.0 };
-
-        result.push(safe_x(audio[0]));
-        for i in 1..audio.len() {
-            result.push(safe_x(audio[i]) - PREEMPH * safe_x(audio[i - 1]));
-        }
-        result
-    }
-
-    fn stft(&self, audio: &[f32]) -> Array2<f32> {
-        let mut planner = FftPlanner::<f32>::new();
-        let fft = planner.plan_fft_forward(N_FFT);
-
-        let pad_amount = N_FFT / 2;
-        let mut padded_audio = vec![0.0; pad_amount];
-        padded_audio.extend_from_slice(audio);
-        padded_audio.extend(std::iter::repeat_n(0.0, pad_amount));
-
-        let num_frames = 1 + (padded_audio.len().saturating_sub(WIN_LENGTH)) / HOP_LENGTH;
-        let freq_bins = N_FFT / 2 + 1;
-        let mut spec = Array2::zeros((freq_bins, num_frames));
-
-        for frame_idx in 0..num_frames {
-            let start = frame_idx * HOP_LENGTH;
-            if start + WIN_LENGTH > padded_audio.len() {
-                break;
-            }
-
-            let mut buffer: Vec<Complex<f32>> = vec![Complex::new(0.0, 0.0); N_FFT];
-            for i in 0..WIN_LENGTH {
-                buffer[i] = Complex::new(padded_audio[start + i] * self.window[i], 0.0);
-            }
-            fft.process(&mut buffer);
-            for (i, val) in buffer.iter().take(freq_bins).enumerate() {
-                let mag_sq = val.norm_sqr();
-                spec[[i, frame_idx]] = if mag_sq.is_finite() { mag_sq } else { 0.0 };
-            }
-        }
-        spec
-    }
-
-    fn create_window() -> Vec<f32> {
-        (0..WIN_LENGTH)
-            .map(|i| 0.5 - 0.5 * ((2.0 * PI * i as f32) / ((WIN_LENGTH - 1) as f32)).cos())
-            .collect()
-    }
-
-    fn create_mel_filterbank() -> Array2<f32> {
-        let num_freqs = N_FFT / 2 + 1;
-
-        let hz_to_mel = |hz: f32| 2595.0 * (1.0 + hz / 700.0).log10();
-        let mel_to_hz = |mel: f32| 700.0 * (10.0_f32.powf(mel / 2595.0) - 1.0);
-
-        let mel_min = hz_to_mel(0.0);
-        let mel_max = hz_to_mel(FMAX);
-
-        let mel_points: Vec<f32> = (0..=N_MELS + 1)
-            .map(|i| mel_to_hz(mel_min + (mel_max - mel_min) * i as f32 / (N_MELS + 1) as f32))
-            .collect();
-
-        let fft_freqs: Vec<f32> = (0..num_freqs)
-            .map(|i| (SAMPLE_RATE as f32 / N_FFT as f32) * i as f32)
-            .collect();
-
-        let mut weights = Array2::zeros((N_MELS, num_freqs));
-
-        for i in 0..N_MELS {
-            let left = mel_points[i];
-            let center = mel_points[i + 1];
-            let right = mel_points[i + 2];
-            for (j, &freq) in fft_freqs.iter().enumerate() {
-                if freq >= left && freq <= center {
-                    weights[[i, j]] = (freq - left) / (center - left);
-                } else if freq > center && freq <= right {
-                    weights[[i, j]] = (right - freq) / (right - center);
-                }
-            }
-        }
-
-        for i in 0..N_MELS {
-            let enorm = 2.0 / (mel_points[i + 2] - mel_points[i]);
-            for j in 0..num_freqs {
-                weights[[i, j]] *= enorm;
-            }
-        }
-
-        weights
+        // self.features stays as is: the audio buffer must not be cleared!!
     }
 }
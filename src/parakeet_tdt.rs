@@ -1,13 +1,19 @@
-use crate::audio;
-use crate::config::PreprocessorConfig;
-use crate::decoder::TranscriptionResult;
+use crate::audio::{self, InputLimits, ResampleQuality};
+use crate::config::{check_dim, nemo_config_from_model_dir, PreprocessorConfig};
+use crate::confusion::ConfusionNetwork;
+use crate::decoder::{Hypothesis, TranscriptionResult};
 use crate::decoder_tdt::ParakeetTDTDecoder;
+use crate::decoding::{DecodingOptions, DecodingStrategy, Fusion, TokenControls};
 use crate::error::{Error, Result};
 use crate::execution::ModelConfig as ExecutionConfig;
+use crate::frames::Frames;
+use crate::lm::LmScorer;
+use crate::mel::{check_features, DitherConfig, MelConfig, MelFrontend};
 use crate::model_tdt::ParakeetTDTModel;
 use crate::timestamps::{process_timestamps, TimestampMode};
 use crate::transcriber::Transcriber;
 use crate::vocab::Vocabulary;
+use ndarray::{s, Array2};
 use std::path::{Path, PathBuf};
 
 /// Parakeet TDT model for multilingual ASR
@@ -15,6 +21,11 @@ pub struct ParakeetTDT {
     model: ParakeetTDTModel,
     decoder: ParakeetTDTDecoder,
     preprocessor_config: PreprocessorConfig,
+    frontend: MelFrontend,
+    resample_quality: ResampleQuality,
+    max_duration: Option<f32>,
+    decoding: DecodingOptions,
+    controls: TokenControls,
     model_dir: PathBuf,
 }
 
@@ -45,8 +56,9 @@ impl ParakeetTDT {
             )));
         }
 
-        // TDT-specific preprocessor config (128 features instead of 80)
-        let preprocessor_config = PreprocessorConfig {
+        // TDT-specific defaults (128 features instead of 80), overridden by the config
+        // files shipped with the model
+        let default_config = PreprocessorConfig {
             feature_extractor_type: "ParakeetFeatureExtractor".to_string(),
             feature_size: 128,
             hop_length: 160,
@@ -59,6 +71,7 @@ impl ParakeetTDT {
             sampling_rate: 16000,
             win_length: 400,
         };
+        let preprocessor_config = PreprocessorConfig::from_model_dir(path, default_config)?;
 
         let exec_config = config.unwrap_or_default();
 
@@ -66,13 +79,30 @@ impl ParakeetTDT {
         let vocab = Vocabulary::from_file(&vocab_path)?;
         let vocab_size = vocab.size();
 
-        let model = ParakeetTDTModel::from_pretrained(path, exec_config, vocab_size)?;
-        let decoder = ParakeetTDTDecoder::from_vocab(vocab);
+        let mut model = ParakeetTDTModel::from_pretrained(path, exec_config, vocab_size)?;
+        check_dim(
+            "Feature size",
+            model.input_feature_size()?,
+            preprocessor_config.feature_size,
+        )?;
+        let nemo = nemo_config_from_model_dir(path)?;
+        let max_duration = nemo.max_duration(&preprocessor_config);
+        let mel_config = nemo.mel_config(MelConfig::parakeet(&preprocessor_config))?;
+        let mut decoder = ParakeetTDTDecoder::from_vocab(vocab);
+        if let Some(duration_bins) = nemo.model_defaults.tdt_durations {
+            model = model.with_duration_bins(duration_bins.clone());
+            decoder = decoder.with_duration_bins(duration_bins);
+        }
 
         Ok(Self {
             model,
             decoder,
+            frontend: MelFrontend::new(mel_config),
             preprocessor_config,
+            resample_quality: ResampleQuality::default(),
+            max_duration: Some(max_duration),
+            decoding: DecodingOptions::default(),
+            controls: TokenControls::default(),
             model_dir: path.to_path_buf(),
         })
     }
@@ -84,27 +114,173 @@ impl ParakeetTDT {
     pub fn preprocessor_config(&self) -> &PreprocessorConfig {
         &self.preprocessor_config
     }
-}
 
-impl Transcriber for ParakeetTDT {
-    fn transcribe_samples(
+    /// Set the resampler quality used when input audio is not at the model sample rate.
+    pub fn set_resample_quality(&mut self, quality: ResampleQuality) {
+        self.resample_quality = quality;
+    }
+
+    pub fn resample_quality(&self) -> ResampleQuality {
+        self.resample_quality
+    }
+
+    /// Add seeded Gaussian dither to the waveform before feature extraction (off by default).
+    ///
+    /// NeMo trains with `dither=1e-5`; [`DitherConfig::nemo`] matches that. Output stays
+    /// reproducible: the same seed always produces the same features.
+    pub fn set_dither(&mut self, dither: Option<DitherConfig>) {
+        self.frontend.set_dither(dither);
+    }
+
+    /// Longest audio accepted in one call, in seconds (`None` = unchecked).
+    ///
+    /// Derived from the encoder's positional encoding length (400s for the released
+    /// models); longer input is rejected with [`Error::AudioTooLong`].
+    pub fn max_duration(&self) -> Option<f32> {
+        self.max_duration
+    }
+
+    /// Override the duration limit, e.g. for an export with a longer positional encoding
+    pub fn set_max_duration(&mut self, max_duration: Option<f32>) {
+        self.max_duration = max_duration;
+    }
+
+    /// Switch between greedy decoding (default) and TDT beam search, and set the
+    /// hotwords to boost and the token controls. Fails if a hotword cannot be spelled
+    /// with the model vocabulary or a token id is out of range.
+    ///
+    /// Beam search scores tokens and durations jointly and runs the decoder once per
+    /// hypothesis and frame, so it is several times slower than greedy decoding.
+    pub fn set_decoding_options(&mut self, options: DecodingOptions) -> Result<()> {
+        let vocab_size = self.decoder.vocab().size();
+        let controls = TokenControls::new(&options, vocab_size - 1, vocab_size)?;
+        self.decoder
+            .set_context_biasing(options.context_biasing.as_ref())?;
+        self.controls = controls;
+        self.decoding = options;
+        Ok(())
+    }
+
+    pub fn decoding_options(&self) -> &DecodingOptions {
+        &self.decoding
+    }
+
+    /// Feature extractor configured for this model
+    pub fn frontend(&self) -> &MelFrontend {
+        &self.frontend
+    }
+
+    /// Compute the log-mel features the encoder is fed, shape `(time, feature_size)`.
+    pub fn extract_features(
+        &self,
+        audio: Vec<f32>,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Result<Array2<f32>> {
+        let limits = InputLimits::for_frontend(&self.frontend, self.max_duration);
+        audio::validate_audio(&audio, sample_rate, channels, &limits)?;
+        audio::extract_features_raw(
+            audio,
+            sample_rate,
+            channels,
+            &self.frontend,
+            self.resample_quality,
+        )
+    }
+
+    /// Encoder embeddings of every encoder frame, shape `(frames, encoder_dim)`, for
+    /// custom decoders, audio search and analytics. Input limits are those of
+    /// transcription.
+    pub fn encoder_output(
         &mut self,
         audio: Vec<f32>,
         sample_rate: u32,
         channels: u16,
+    ) -> Result<Frames> {
+        let features = self.extract_features(audio, sample_rate, channels)?;
+        self.encoder_output_features(features)
+    }
+
+    /// [`ParakeetTDT::encoder_output`] for precomputed features of shape
+    /// `(time, feature_size)`
+    pub fn encoder_output_features(&mut self, features: Array2<f32>) -> Result<Frames> {
+        check_features(&features, self.frontend.config(), self.max_duration)?;
+        let feature_frames = features.nrows();
+        let (encoder_out, encoder_len) = self.model.run_encoder(&features)?;
+
+        let frames = (encoder_len.max(0) as usize).min(encoder_out.shape()[2]);
+        let values = encoder_out.slice(s![0, .., ..frames]).t().to_owned();
+        let stride = (feature_frames as f32 / frames.max(1) as f32)
+            .round()
+            .max(1.0) as usize;
+        Ok(Frames {
+            values,
+            start: 0.0,
+            frame_secs: (stride * self.preprocessor_config.hop_length) as f32
+                / self.preprocessor_config.sampling_rate as f32,
+        })
+    }
+
+    /// Transcribe precomputed features of shape `(time, feature_size)`.
+    pub fn transcribe_features(
+        &mut self,
+        features: Array2<f32>,
         mode: Option<TimestampMode>,
     ) -> Result<TranscriptionResult> {
-        let features =
-            audio::extract_features_raw(audio, sample_rate, channels, &self.preprocessor_config)?;
-        let (tokens, frame_indices, durations) = self.model.forward(features)?;
-
-        let mut result = self.decoder.decode_with_timestamps(
-            &tokens,
-            &frame_indices,
-            &durations,
-            self.preprocessor_config.hop_length,
-            self.preprocessor_config.sampling_rate,
-        )?;
+        check_features(&features, self.frontend.config(), self.max_duration)?;
+        let confidence = self.decoding.confidence;
+        let hypotheses = match &self.decoding.strategy {
+            DecodingStrategy::Greedy => {
+                vec![self.model.decode_greedy(
+                    features,
+                    self.decoder.bias(),
+                    confidence,
+                    &self.controls,
+                )?]
+            }
+            DecodingStrategy::Beam(config) => {
+                let scorer = config
+                    .language_model
+                    .as_ref()
+                    .map(|lm| LmScorer::new(lm, &self.decoder.vocab().id_to_token));
+                let fusion = Fusion {
+                    lm: scorer.as_ref(),
+                    bias: self.decoder.bias(),
+                };
+                self.model
+                    .beam_search(features, config, fusion, confidence, &self.controls)?
+            }
+        };
+
+        let mut results = Vec::with_capacity(hypotheses.len());
+        for hypothesis in &hypotheses {
+            let tokens: Vec<usize> = hypothesis.tokens.iter().map(|&id| id as usize).collect();
+            results.push(self.decoder.decode_with_confidences(
+                &tokens,
+                &hypothesis.frames,
+                &hypothesis.durations,
+                &hypothesis.confidences,
+                self.preprocessor_config.hop_length,
+                self.preprocessor_config.sampling_rate,
+            )?);
+        }
+        let n_best: Vec<Hypothesis> = results
+            .iter()
+            .zip(&hypotheses)
+            .map(|(result, hypothesis)| Hypothesis {
+                text: result.text.clone(),
+                tokens: result.tokens.clone(),
+                log_prob: hypothesis.log_prob,
+                lm_score: hypothesis.lm_score,
+                score: hypothesis.score(),
+            })
+            .collect();
+        let mut result = results
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::Model("Decoding produced no hypotheses".to_string()))?;
+        result.confusion_network = Some(ConfusionNetwork::from_hypotheses(&n_best));
+        result.n_best = n_best;
 
         // Apply timestamp mode conversion
         let mode = mode.unwrap_or(TimestampMode::Tokens);
@@ -144,3 +320,16 @@ impl Transcriber for ParakeetTDT {
         Ok(result)
     }
 }
+
+impl Transcriber for ParakeetTDT {
+    fn transcribe_samples(
+        &mut self,
+        audio: Vec<f32>,
+        sample_rate: u32,
+        channels: u16,
+        mode: Option<TimestampMode>,
+    ) -> Result<TranscriptionResult> {
+        let features = self.extract_features(audio, sample_rate, channels)?;
+        self.transcribe_features(features, mode)
+    }
+}
@@ -17,26 +17,20 @@
 //! CACHE_LEN = 188
 //! FEAT_DIM = 128
 //! EMB_DIM = 512
-//! Note, my stft code is adapted from: https://librosa.org/doc/main/generated/librosa.stft.html
+//! Features come from the shared `MelFrontend` with the `MelConfig::sortformer()` preset.
 
+use crate::audio::{self, InputLimits, ResampleQuality};
 use crate::error::{Error, Result};
 use crate::execution::ModelConfig;
+use crate::mel::{DitherConfig, MelConfig, MelFrontend};
+use crate::vad::{CondensedAudio, SpeechSegment};
 use ndarray::{s, Array1, Array2, Array3, Axis};
 use ort::session::Session;
-use rustfft::{num_complex::Complex, FftPlanner};
-use std::f32::consts::PI;
 use std::path::Path;
 
 // Model constants
-const N_FFT: usize = 512;
-const WIN_LENGTH: usize = 400;
-const HOP_LENGTH: usize = 160;
 const N_MELS: usize = 128;
-const PREEMPH: f32 = 0.97;
-const LOG_ZERO_GUARD: f32 = 5.960464478e-8;
 const SAMPLE_RATE: usize = 16000;
-const FMIN: f32 = 0.0;
-const FMAX: f32 = 8000.0;
 
 // Streaming constants
 const CHUNK_LEN: usize = 124; // Frames per chunk (~10s at 80ms)
@@ -178,8 +172,8 @@ pub struct Sortformer {
     fifo_preds: Array3<f32>,             // (1, 0..FIFO_LEN, NUM_SPEAKERS)
     mean_sil_emb: Array2<f32>,           // (1, EMB_DIM)
     n_sil_frames: usize,
-    // Mel filterbank (cached)
-    mel_basis: Array2<f32>,
+    frontend: MelFrontend,
+    resample_quality: ResampleQuality,
 }
 
 impl Sortformer {
@@ -200,8 +194,6 @@ impl Sortformer {
             .apply_to_session_builder(Session::builder()?)?
             .commit_from_file(model_path.as_ref())?;
 
-        let mel_basis = Self::create_mel_filterbank();
-
         let mut instance = Self {
             session,
             config,
@@ -211,12 +203,23 @@ impl Sortformer {
             fifo_preds: Array3::zeros((1, 0, NUM_SPEAKERS)),
             mean_sil_emb: Array2::zeros((1, EMB_DIM)),
             n_sil_frames: 0,
-            mel_basis,
+            frontend: MelFrontend::new(MelConfig::sortformer()),
+            resample_quality: ResampleQuality::default(),
         };
         instance.reset_state();
         Ok(instance)
     }
 
+    /// Set the resampler quality used when input audio is not at 16kHz
+    pub fn set_resample_quality(&mut self, quality: ResampleQuality) {
+        self.resample_quality = quality;
+    }
+
+    /// Add seeded Gaussian dither to the waveform before feature extraction (off by default).
+    pub fn set_dither(&mut self, dither: Option<DitherConfig>) {
+        self.frontend.set_dither(dither);
+    }
+
     /// Reset streaming state
     pub fn reset_state(&mut self) {
         self.spkcache = Array3::zeros((1, 0, EMB_DIM));
@@ -234,22 +237,22 @@ impl Sortformer {
         sample_rate: u32,
         channels: u16,
     ) -> Result<Vec<SpeakerSegment>> {
-        // Resample if needed
-        if sample_rate != SAMPLE_RATE as u32 {
-            return Err(Error::Audio(format!(
-                "Expected {} Hz, got {} Hz",
-                SAMPLE_RATE, sample_rate
-            )));
-        }
+        let limits = InputLimits::for_frontend(&self.frontend, None);
+        audio::validate_audio(&audio, sample_rate, channels, &limits)?;
 
         // Convert to mono
         if channels > 1 {
-            audio = audio
-                .chunks(channels as usize)
-                .map(|chunk| chunk.iter().sum::<f32>() / channels as f32)
-                .collect();
+            audio = audio::downmix(&audio, channels);
         }
 
+        // Resample if needed
+        audio = audio::resample(
+            &audio,
+            sample_rate,
+            SAMPLE_RATE as u32,
+            self.resample_quality,
+        )?;
+
         // Reset state for new audio
         self.reset_state();
 
@@ -259,7 +262,7 @@ impl Sortformer {
 
         // Process in chunks
         let chunk_stride = CHUNK_LEN * SUBSAMPLING;
-        let num_chunks = (total_frames + chunk_stride - 1) / chunk_stride;
+        let num_chunks = total_frames.div_ceil(chunk_stride);
 
         let mut all_chunk_preds = Vec::new();
 
@@ -301,6 +304,41 @@ impl Sortformer {
         Ok(segments)
     }
 
+    /// Diarize only the given speech regions (e.g. from a VAD).
+    ///
+    /// The speech is concatenated, diarized as one stream so speaker identities stay
+    /// consistent across segments, and the resulting times are mapped back to the
+    /// original audio. A speaker turn that spans a removed gap is split at the gap.
+    pub fn diarize_speech_segments(
+        &mut self,
+        audio: Vec<f32>,
+        sample_rate: u32,
+        channels: u16,
+        speech: &[SpeechSegment],
+    ) -> Result<Vec<SpeakerSegment>> {
+        audio::validate_audio(&audio, sample_rate, channels, &InputLimits::streaming())?;
+        let mono = audio::downmix(&audio, channels);
+        let condensed = CondensedAudio::new(&mono, sample_rate, speech);
+        if condensed.samples.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let segments = self.diarize(condensed.samples.clone(), sample_rate, 1)?;
+        Ok(segments
+            .into_iter()
+            .flat_map(|seg| {
+                condensed
+                    .map_span(seg.start, seg.end)
+                    .into_iter()
+                    .map(move |piece| SpeakerSegment {
+                        start: piece.start,
+                        end: piece.end,
+                        speaker_id: seg.speaker_id,
+                    })
+            })
+            .collect())
+    }
+
     /// NeMo's streaming_update with smart cache compression
     fn streaming_update(
         &mut self,
@@ -381,7 +419,7 @@ impl Sortformer {
             .map_err(|e| Error::Model(format!("Failed to reshape embs: {e}")))?;
 
             // Calculate valid frames
-            let valid_frames = (current_len + SUBSAMPLING - 1) / SUBSAMPLING;
+            let valid_frames = current_len.div_ceil(SUBSAMPLING);
 
             (preds, new_embs, valid_frames)
         };
@@ -564,7 +602,7 @@ impl Sortformer {
         min_pos_scores_per_spk: usize,
     ) -> Array2<f32> {
         // Count positive scores per speaker
-        let mut pos_count = vec![0usize; NUM_SPEAKERS];
+        let mut pos_count = [0usize; NUM_SPEAKERS];
         for t in 0..scores.shape()[0] {
             for s in 0..NUM_SPEAKERS {
                 if scores[[t, s]] > 0.0 {
@@ -609,8 +647,7 @@ impl Sortformer {
             sorted.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
 
             // Boost top K
-            for i in 0..n_boost_per_spk.min(sorted.len()) {
-                let t = sorted[i].0;
+            for &(t, _) in sorted.iter().take(n_boost_per_spk) {
                 if scores[[t, s]] != f32::NEG_INFINITY {
                     scores[[t, s]] -= scale_factor * 0.5f32.ln();
                 }
@@ -846,172 +883,8 @@ impl Sortformer {
         segments
     }
 
-    fn apply_preemphasis(audio: &[f32]) -> Vec<f32> {
-        let mut result = Vec::with_capacity(audio.len());
-        result.push(audio[0]);
-        for i in 1..audio.len() {
-            result.push(audio[i] - PREEMPH * audio[i - 1]);
-        }
-        result
-    }
-
-    fn hann_window(window_length: usize) -> Vec<f32> {
-        // Librosa uses periodic window (fftbins=True): divide by N, not N-1
-        (0..window_length)
-            .map(|i| 0.5 - 0.5 * ((2.0 * PI * i as f32) / window_length as f32).cos())
-            .collect()
-    }
-
-    fn stft(audio: &[f32]) -> Array2<f32> {
-        let mut planner = FftPlanner::<f32>::new();
-        let fft = planner.plan_fft_forward(N_FFT);
-
-        // Create Hann window of length win_length, then zero-pad to n_fft (centered)
-        // This is exactly what librosa does: util.pad_center(fft_window, size=n_fft)
-        let hann = Self::hann_window(WIN_LENGTH);
-        let win_offset = (N_FFT - WIN_LENGTH) / 2;
-        let mut fft_window = vec![0.0f32; N_FFT];
-        for i in 0..WIN_LENGTH {
-            fft_window[win_offset + i] = hann[i];
-        }
-
-        // Pad signal for center=True (like librosa/torch.stft)
-        // Padding is n_fft // 2 on each side
-        let pad_amount = N_FFT / 2;
-        let mut padded_audio = vec![0.0; pad_amount];
-        padded_audio.extend_from_slice(audio);
-        padded_audio.extend(vec![0.0; pad_amount]);
-
-        let num_frames = (padded_audio.len() - N_FFT) / HOP_LENGTH + 1;
-        let freq_bins = N_FFT / 2 + 1;
-        let mut spectrogram = Array2::<f32>::zeros((freq_bins, num_frames));
-
-        for frame_idx in 0..num_frames {
-            let start = frame_idx * HOP_LENGTH;
-            let mut frame: Vec<Complex<f32>> = vec![Complex::new(0.0, 0.0); N_FFT];
-
-            // Extract n_fft samples and multiply by zero-padded window
-            for i in 0..N_FFT {
-                if start + i < padded_audio.len() {
-                    frame[i] = Complex::new(padded_audio[start + i] * fft_window[i], 0.0);
-                }
-            }
-
-            fft.process(&mut frame);
-            for k in 0..freq_bins {
-                let magnitude = frame[k].norm();
-                // Power spectrum (magnitude^2) - NeMo uses mag_power=2.0
-                spectrogram[[k, frame_idx]] = magnitude * magnitude;
-            }
-        }
-
-        spectrogram
-    }
-
-    // Librosa's Slaney mel scale (htk=False, which is the default)
-    fn hz_to_mel_slaney(hz: f64) -> f64 {
-        let f_min = 0.0;
-        let f_sp = 200.0 / 3.0;
-        let min_log_hz = 1000.0;
-        let min_log_mel = (min_log_hz - f_min) / f_sp;
-        let logstep = (6.4f64).ln() / 27.0;
-
-        if hz >= min_log_hz {
-            min_log_mel + (hz / min_log_hz).ln() / logstep
-        } else {
-            (hz - f_min) / f_sp
-        }
-    }
-
-    fn mel_to_hz_slaney(mel: f64) -> f64 {
-        let f_min = 0.0;
-        let f_sp = 200.0 / 3.0;
-        let min_log_hz = 1000.0;
-        let min_log_mel = (min_log_hz - f_min) / f_sp;
-        let logstep = (6.4f64).ln() / 27.0;
-
-        if mel >= min_log_mel {
-            min_log_hz * (logstep * (mel - min_log_mel)).exp()
-        } else {
-            f_min + f_sp * mel
-        }
-    }
-
-    fn create_mel_filterbank() -> Array2<f32> {
-        // lets use f64 for intermediate calculations to avoid precision loss
-        let freq_bins = N_FFT / 2 + 1;
-        let mut filterbank = Array2::<f32>::zeros((N_MELS, freq_bins));
-
-        // FFT frequencies: fftfreqs[k] = k * sr / n_fft
-        let fftfreqs: Vec<f64> = (0..freq_bins)
-            .map(|k| k as f64 * SAMPLE_RATE as f64 / N_FFT as f64)
-            .collect();
-
-        // Mel center frequencies using Slaney scale (librosa default, htk=False)
-        let fmin_mel = Self::hz_to_mel_slaney(FMIN as f64);
-        let fmax_mel = Self::hz_to_mel_slaney(FMAX as f64);
-        let mel_f: Vec<f64> = (0..=N_MELS + 1)
-            .map(|i| {
-                let mel = fmin_mel + (fmax_mel - fmin_mel) * i as f64 / (N_MELS + 1) as f64;
-                Self::mel_to_hz_slaney(mel)
-            })
-            .collect();
-
-        // Differences between consecutive mel frequencies
-        let fdiff: Vec<f64> = mel_f.windows(2).map(|w| w[1] - w[0]).collect();
-
-        // Compute filterbank weights (reference: librosa's ramp method)
-        // https://librosa.org/doc/main/generated/librosa.stft.html
-        for i in 0..N_MELS {
-            for k in 0..freq_bins {
-                // Lower slope: (fftfreqs[k] - mel_f[i]) / fdiff[i]
-                let lower = (fftfreqs[k] - mel_f[i]) / fdiff[i];
-                // Upper slope: (mel_f[i+2] - fftfreqs[k]) / fdiff[i+1]
-                let upper = (mel_f[i + 2] - fftfreqs[k]) / fdiff[i + 1];
-                // Weight is max(0, min(lower, upper))
-                filterbank[[i, k]] = 0.0f64.max(lower.min(upper)) as f32;
-            }
-        }
-
-        // Apply Slaney normalization: 2.0 / (mel_f[i+2] - mel_f[i])
-        for i in 0..N_MELS {
-            let enorm = 2.0 / (mel_f[i + 2] - mel_f[i]);
-            for k in 0..freq_bins {
-                filterbank[[i, k]] *= enorm as f32;
-            }
-        }
-
-        filterbank
-    }
-
+    // (1, T, n_mels) as expected by the model
     fn extract_mel_features(&self, audio: &[f32]) -> Array3<f32> {
-        // 1. Add dither (small random noise to prevent log(0))
-        // NeMo uses dither=1e-5, but for determinism we skip random noise
-        // The log_zero_guard handles zero values
-
-        // 2. Apply preemphasis (NeMo uses preemph=0.97)
-        let preemphasized = Self::apply_preemphasis(audio);
-
-        // 3. STFT
-        let spectrogram = Self::stft(&preemphasized);
-
-        // 4. Apply mel filterbank (with Slaney normalization)
-        let mel_spec = self.mel_basis.dot(&spectrogram);
-
-        // 5. Log with guard value (NeMo uses log_zero_guard_value = 2^-24)
-        // NeMo uses normalize='NA' which means NO normalization
-        let log_mel_spec = mel_spec.mapv(|x| (x + LOG_ZERO_GUARD).ln());
-
-        let num_frames = log_mel_spec.shape()[1];
-        let mut features = Array3::<f32>::zeros((1, num_frames, N_MELS));
-
-        // Transpose to (batch, time, features) - NeMo outputs (B, D, T), model expects (B, T, D)
-        for t in 0..num_frames {
-            for m in 0..N_MELS {
-                features[[0, t, m]] = log_mel_spec[[m, t]];
-            }
-        }
-
-        features
+        self.frontend.compute(audio).insert_axis(Axis(0))
     }
 }
@@ -0,0 +1,621 @@
+//! TDT greedy and beam search.
+//!
+//! A hypothesis is a token sequence plus the frame it continues from. Every joint
+//! call scores a token and a duration together; blank with duration `d` jumps `d`
+//! frames, a token with duration 0 stays on the frame for another symbol. Frames are
+//! visited in order and hypotheses landing on the same frame with the same tokens are
+//! merged, in the spirit of NeMo's TDT beam search and modified ALSD (Saon et al.,
+//! "Alignment-Length Synchronous Decoding for RNN Transducer", 2020). Each hypothesis
+//! keeps its own prediction network state.
+
+use crate::biasing::{BiasState, BiasTrie};
+use crate::confidence::ConfidenceMeasure;
+use crate::ctc_beam::log_add;
+use crate::decoding::{BeamSearchConfig, Fusion, FusionState};
+use crate::error::Result;
+
+/// Most symbols emitted on one frame before decoding moves on, unless
+/// `DecodingOptions::max_symbols_per_step` says otherwise
+pub(crate) const MAX_SYMBOLS_PER_STEP: usize = 10;
+
+/// Frames each duration output stands for (`tdt_durations` of parakeet-tdt models)
+pub(crate) const DEFAULT_DURATION_BINS: [usize; 5] = [0, 1, 2, 3, 4];
+
+/// Frames duration output `index` stands for; indices past the last bin use the last
+pub(crate) fn duration_frames(bins: &[usize], index: usize) -> usize {
+    bins.get(index).or(bins.last()).copied().unwrap_or(index)
+}
+
+/// One decoder_joint evaluation
+#[derive(Debug, Clone)]
+pub(crate) struct JointOutput<S> {
+    /// Token logits used for the search, with any token controls applied
+    pub token_logits: Vec<f32>,
+    /// The model's token logits before token controls, if they differ
+    pub model_logits: Option<Vec<f32>>,
+    pub duration_logits: Vec<f32>,
+    /// Prediction network state after consuming the input token
+    pub state: S,
+}
+
+impl<S> JointOutput<S> {
+    /// Confidence of the model's own token distribution
+    pub fn confidence(&self, measure: ConfidenceMeasure) -> f32 {
+        measure.compute(self.model_logits.as_ref().unwrap_or(&self.token_logits))
+    }
+}
+
+/// Prediction network plus joint, abstracted so the search runs without ONNX
+pub(crate) trait TdtJoint {
+    type State: Clone;
+
+    fn initial_state(&self) -> Self::State;
+
+    /// Encoder frames each duration output advances by
+    fn duration_bins(&self) -> &[usize];
+
+    /// Feed `token` to the prediction network from `state` and join it with encoder
+    /// frame `frame`
+    fn step(
+        &mut self,
+        frame: usize,
+        token: u32,
+        state: &Self::State,
+    ) -> Result<JointOutput<Self::State>>;
+}
+
+/// A decoded token sequence
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TdtHypothesis {
+    pub tokens: Vec<u32>,
+    /// Encoder frame each token was emitted on
+    pub frames: Vec<usize>,
+    /// Predicted duration index of each token
+    pub durations: Vec<usize>,
+    /// Confidence of each token
+    pub confidences: Vec<f32>,
+    /// Log-probability of the sequence, summed over merged alignments
+    pub log_prob: f32,
+    /// Weighted LM score including word bonuses (0 without a language model)
+    pub lm_score: f32,
+    /// Hotword boost (0 without context biasing)
+    pub bias_score: f32,
+}
+
+impl TdtHypothesis {
+    pub fn score(&self) -> f32 {
+        self.log_prob + self.lm_score + self.bias_score
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Hyp<S> {
+    tokens: Vec<u32>,
+    frames: Vec<usize>,
+    durations: Vec<usize>,
+    confidences: Vec<f32>,
+    log_prob: f32,
+    // Log-probability of the single alignment `frames`/`durations` came from
+    best_alignment: f32,
+    // Last token and the prediction network state before it
+    last_token: u32,
+    state: S,
+    next_frame: usize,
+    // Symbols emitted on `next_frame` so far
+    symbols: usize,
+    fusion: FusionState,
+}
+
+impl<S> Hyp<S> {
+    fn score(&self) -> f32 {
+        self.log_prob + self.fusion.score()
+    }
+}
+
+/// Run beam search over `num_frames` encoder frames.
+///
+/// Returns up to `beam_width` hypotheses, best first by acoustic plus `fusion` score.
+/// At most `max_symbols` tokens are emitted per frame. `config.blank_skip_threshold`
+/// only applies to CTC and is ignored.
+pub(crate) fn beam_search<J: TdtJoint>(
+    joint: &mut J,
+    num_frames: usize,
+    blank: usize,
+    config: &BeamSearchConfig,
+    fusion: Fusion,
+    confidence: ConfidenceMeasure,
+    max_symbols: usize,
+) -> Result<Vec<TdtHypothesis>> {
+    let beam_width = config.beam_width.max(1);
+    let mut hyps = vec![Hyp {
+        tokens: Vec::new(),
+        frames: Vec::new(),
+        durations: Vec::new(),
+        confidences: Vec::new(),
+        log_prob: 0.0,
+        best_alignment: 0.0,
+        last_token: blank as u32,
+        state: joint.initial_state(),
+        next_frame: 0,
+        symbols: 0,
+        fusion: fusion.start(),
+    }];
+
+    for t in 0..num_frames {
+        let (mut active, mut kept): (Vec<_>, Vec<_>) =
+            hyps.into_iter().partition(|hyp| hyp.next_frame == t);
+
+        while let Some(hyp) = pop_best(&mut active) {
+            // Done with this frame once `beam_width` hypotheses beat everything left
+            let better = kept.iter().filter(|h| h.score() > hyp.score()).count();
+            if better >= beam_width {
+                break;
+            }
+
+            let out = joint.step(t, hyp.last_token, &hyp.state)?;
+            let token_lp = log_softmax(&out.token_logits);
+            let duration_lp = log_softmax(&out.duration_logits);
+            let token_confidence = out.confidence(confidence);
+            let prune = config.token_prune_threshold;
+            let durations = candidates(&duration_lp, None, prune, duration_lp.len());
+
+            for (token, lp) in candidates(&token_lp, Some(blank), prune, beam_width) {
+                for &(duration, duration_lp) in &durations {
+                    let is_blank = token == blank;
+                    let frames = duration_frames(joint.duration_bins(), duration);
+                    if is_blank && frames == 0 {
+                        // Would never leave the frame
+                        continue;
+                    }
+                    let step = lp + duration_lp;
+                    // A token may stay on the frame only up to the symbol limit
+                    let advance = if !is_blank && hyp.symbols + 1 >= max_symbols {
+                        frames.max(1)
+                    } else {
+                        frames
+                    };
+
+                    let mut next = Hyp {
+                        log_prob: hyp.log_prob + step,
+                        best_alignment: hyp.best_alignment + step,
+                        next_frame: t + advance,
+                        symbols: 0,
+                        ..hyp.clone()
+                    };
+                    if !is_blank {
+                        next.tokens.push(token as u32);
+                        next.frames.push(t);
+                        next.durations.push(duration);
+                        next.confidences.push(token_confidence);
+                        next.last_token = token as u32;
+                        next.state = out.state.clone();
+                        next.fusion = fusion.advance(&hyp.fusion, token as u32);
+                    }
+
+                    if advance == 0 {
+                        next.symbols = hyp.symbols + 1;
+                        merge_into(&mut active, next);
+                    } else {
+                        merge_into(&mut kept, next);
+                    }
+                }
+            }
+
+            // Bound the work per frame
+            if active.len() > beam_width {
+                sort_hyps(&mut active);
+                active.truncate(beam_width);
+            }
+        }
+
+        sort_hyps(&mut kept);
+        kept.truncate(beam_width);
+        hyps = kept;
+    }
+
+    let mut hypotheses: Vec<TdtHypothesis> = hyps
+        .into_iter()
+        .map(|hyp| {
+            let (lm_score, bias_score) = fusion.finish(&hyp.fusion);
+            TdtHypothesis {
+                tokens: hyp.tokens,
+                frames: hyp.frames,
+                durations: hyp.durations,
+                confidences: hyp.confidences,
+                log_prob: hyp.log_prob,
+                lm_score,
+                bias_score,
+            }
+        })
+        .collect();
+    hypotheses.sort_by(|a, b| {
+        b.score()
+            .partial_cmp(&a.score())
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    Ok(hypotheses)
+}
+
+/// Greedy decoding over `num_frames` encoder frames with optional hotword boosts.
+///
+/// Frames advance as in [`beam_search`]: a token with duration `d` moves on `d` frames
+/// (0 stays on the frame for another symbol) and blank takes its best duration that
+/// leaves the frame. The token after `max_symbols` on one frame moves on at least one.
+pub(crate) fn greedy_search<J: TdtJoint>(
+    joint: &mut J,
+    num_frames: usize,
+    blank: usize,
+    bias: Option<&BiasTrie>,
+    confidence: ConfidenceMeasure,
+    max_symbols: usize,
+) -> Result<TdtHypothesis> {
+    let mut hyp = TdtHypothesis {
+        tokens: Vec::new(),
+        frames: Vec::new(),
+        durations: Vec::new(),
+        confidences: Vec::new(),
+        log_prob: 0.0,
+        lm_score: 0.0,
+        bias_score: 0.0,
+    };
+    let mut state = joint.initial_state();
+    let mut last_token = blank as u32;
+    let mut bias_state = BiasState::default();
+    let mut symbols = 0;
+
+    let mut t = 0;
+    while t < num_frames {
+        let out = joint.step(t, last_token, &state)?;
+        let token_lp = log_softmax(&out.token_logits);
+        let duration_lp = log_softmax(&out.duration_logits);
+
+        let token = match bias {
+            Some(bias) => biased_argmax(&out.token_logits, blank, bias, bias_state),
+            None => argmax(token_lp.iter().copied().enumerate()).unwrap_or(blank),
+        };
+        let is_blank = token == blank;
+        let bins = joint.duration_bins();
+        let duration = argmax(
+            duration_lp
+                .iter()
+                .copied()
+                .enumerate()
+                .filter(|&(d, _)| !is_blank || duration_frames(bins, d) > 0),
+        );
+        let frames = duration.map_or(1, |d| duration_frames(bins, d));
+        let duration = duration.unwrap_or(0);
+
+        hyp.log_prob += token_lp[token] + duration_lp.get(duration).copied().unwrap_or(0.0);
+        if !is_blank {
+            hyp.tokens.push(token as u32);
+            hyp.frames.push(t);
+            hyp.durations.push(duration);
+            hyp.confidences.push(out.confidence(confidence));
+            state = out.state;
+            last_token = token as u32;
+            if let Some(bias) = bias {
+                bias_state = bias.advance(bias_state, token as u32);
+            }
+        }
+
+        // A token may stay on the frame only up to the symbol limit
+        let advance = if !is_blank && symbols + 1 >= max_symbols {
+            frames.max(1)
+        } else {
+            frames
+        };
+        if advance == 0 {
+            symbols += 1;
+        } else {
+            t += advance;
+            symbols = 0;
+        }
+    }
+
+    hyp.bias_score = bias.map_or(0.0, |bias| bias.finish(bias_state));
+    Ok(hyp)
+}
+
+// Best token after adding hotword boosts to the log-softmaxed vocabulary logits
+fn biased_argmax(logits: &[f32], blank_id: usize, bias: &BiasTrie, state: BiasState) -> usize {
+    let max = logits.iter().fold(f32::NEG_INFINITY, |m, &x| m.max(x));
+    let log_sum = logits.iter().map(|&x| (x - max).exp()).sum::<f32>().ln() + max;
+
+    let mut scores: Vec<f32> = logits.iter().map(|&x| x - log_sum).collect();
+    for (token, boost) in bias.boosts(state) {
+        let token = token as usize;
+        if token != blank_id {
+            if let Some(score) = scores.get_mut(token) {
+                *score = score.max(logits[token] - log_sum + boost);
+            }
+        }
+    }
+    scores
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(idx, _)| idx)
+        .unwrap_or(blank_id)
+}
+
+fn argmax(scores: impl Iterator<Item = (usize, f32)>) -> Option<usize> {
+    scores
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(i, _)| i)
+}
+
+pub(crate) fn log_softmax(logits: &[f32]) -> Vec<f32> {
+    let max = logits.iter().fold(f32::NEG_INFINITY, |m, &x| m.max(x));
+    let log_sum = logits.iter().map(|&x| (x - max).exp()).sum::<f32>().ln() + max;
+    logits.iter().map(|&x| x - log_sum).collect()
+}
+
+// Up to `limit` entries within `prune` of the best, best first. `keep` is included
+// regardless so blank is always an option.
+fn candidates(
+    log_probs: &[f32],
+    keep: Option<usize>,
+    prune: f32,
+    limit: usize,
+) -> Vec<(usize, f32)> {
+    let best = log_probs.iter().fold(f32::NEG_INFINITY, |m, &x| m.max(x));
+    let mut out: Vec<(usize, f32)> = log_probs
+        .iter()
+        .copied()
+        .enumerate()
+        .filter(|&(id, lp)| lp > f32::NEG_INFINITY && (lp >= best + prune || Some(id) == keep))
+        .collect();
+    out.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    let mut rank = 0;
+    out.retain(|&(id, _)| {
+        rank += 1;
+        rank <= limit || Some(id) == keep
+    });
+    out
+}
+
+fn pop_best<S>(hyps: &mut Vec<Hyp<S>>) -> Option<Hyp<S>> {
+    let best = hyps
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| {
+            a.score()
+                .partial_cmp(&b.score())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .map(|(i, _)| i)?;
+    Some(hyps.swap_remove(best))
+}
+
+// Alignments of the same tokens that reach the same frame are summed; timestamps come
+// from the more probable one
+fn merge_into<S>(hyps: &mut Vec<Hyp<S>>, hyp: Hyp<S>) {
+    let existing = hyps.iter_mut().find(|h| {
+        h.next_frame == hyp.next_frame && h.symbols == hyp.symbols && h.tokens == hyp.tokens
+    });
+    match existing {
+        Some(existing) => {
+            let log_prob = log_add(existing.log_prob, hyp.log_prob);
+            if hyp.best_alignment > existing.best_alignment {
+                *existing = hyp;
+            }
+            existing.log_prob = log_prob;
+        }
+        None => hyps.push(hyp),
+    }
+}
+
+fn sort_hyps<S>(hyps: &mut [Hyp<S>]) {
+    hyps.sort_by(|a, b| {
+        b.score()
+            .partial_cmp(&a.score())
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.tokens.cmp(&b.tokens))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // vocab: 0 = "a", 1 = "b", 2 = blank; three duration outputs standing for `bins`
+    // frames. The state is the list of tokens the prediction network has consumed, and
+    // must lead up to `token`.
+    // (frame, last token) -> (token probabilities, duration probabilities)
+    type Table = fn(usize, u32) -> ([f32; 3], [f32; 3]);
+
+    struct MockJoint {
+        table: Table,
+        bins: Vec<usize>,
+    }
+
+    impl TdtJoint for MockJoint {
+        type State = Vec<u32>;
+
+        fn initial_state(&self) -> Vec<u32> {
+            Vec::new()
+        }
+
+        fn duration_bins(&self) -> &[usize] {
+            &self.bins
+        }
+
+        fn step(
+            &mut self,
+            frame: usize,
+            token: u32,
+            state: &Vec<u32>,
+        ) -> Result<JointOutput<Vec<u32>>> {
+            // Only the start state has not consumed anything, and it is fed blank
+            assert_eq!(state.is_empty(), token == 2);
+            let (tokens, durations) = (self.table)(frame, token);
+            let mut next = state.clone();
+            next.push(token);
+            Ok(JointOutput {
+                token_logits: tokens.iter().map(|p| p.ln()).collect(),
+                model_logits: None,
+                duration_logits: durations.iter().map(|p| p.ln()).collect(),
+                state: next,
+            })
+        }
+    }
+
+    #[test]
+    fn test_beam_sums_alignments_greedy_misses() {
+        // Blank wins every frame (0.55 > 0.45), but "a" is more probable over all of its
+        // alignments: (0.225 * 0.495 + 0.225) * 0.495 = 0.17 vs 0.275 * 0.275 = 0.076
+        let mut joint = MockJoint {
+            table: |_, last| match last {
+                0 => ([0.005, 0.005, 0.99], [0.5, 0.5, 1e-6]),
+                _ => ([0.45, 1e-6, 0.55], [0.5, 0.5, 1e-6]),
+            },
+            bins: DEFAULT_DURATION_BINS.to_vec(),
+        };
+        let config = BeamSearchConfig::new(4);
+        let hyps = beam_search(
+            &mut joint,
+            2,
+            2,
+            &config,
+            Fusion::default(),
+            Default::default(),
+            MAX_SYMBOLS_PER_STEP,
+        )
+        .unwrap();
+        assert_eq!(hyps[0].tokens, vec![0]);
+        assert_eq!(hyps[0].frames, vec![0]);
+        assert!(hyps.iter().any(|h| h.tokens.is_empty()));
+        assert!(hyps.windows(2).all(|w| w[0].score() >= w[1].score()));
+    }
+
+    #[test]
+    fn test_durations_skip_frames() {
+        // "a" lasts two frames, then "b" on frame 2
+        let mut joint = MockJoint {
+            table: |frame, last| match (frame, last) {
+                (0, 2) => ([0.9, 0.05, 0.05], [0.05, 0.05, 0.9]),
+                (2, 0) => ([0.05, 0.9, 0.05], [0.05, 0.9, 0.05]),
+                _ => ([0.05, 0.05, 0.9], [0.05, 0.9, 0.05]),
+            },
+            bins: DEFAULT_DURATION_BINS.to_vec(),
+        };
+        let hyps = beam_search(
+            &mut joint,
+            4,
+            2,
+            &BeamSearchConfig::new(2),
+            Fusion::default(),
+            ConfidenceMeasure::MaxProb,
+            MAX_SYMBOLS_PER_STEP,
+        );
+        let best = &hyps.unwrap()[0];
+        assert_eq!(best.tokens, vec![0, 1]);
+        assert_eq!(best.frames, vec![0, 2]);
+        assert_eq!(best.durations, vec![2, 1]);
+        // (0.9 * 3 - 1) / 2
+        assert!(best.confidences.iter().all(|&c| (c - 0.85).abs() < 1e-5));
+    }
+
+    #[test]
+    fn test_durations_map_through_bins() {
+        // Duration output 1 stands for 2 frames, so "a" predicted with output 1 lands on
+        // frame 2, where "b" is emitted (with the default bins it would land on frame 1)
+        let mut joint = MockJoint {
+            table: |frame, last| match (frame, last) {
+                (0, 2) => ([0.9, 0.05, 0.05], [0.05, 0.9, 0.05]),
+                (2, 0) => ([0.05, 0.9, 0.05], [0.05, 0.9, 0.05]),
+                _ => ([0.05, 0.05, 0.9], [0.05, 0.9, 0.05]),
+            },
+            bins: vec![0, 2, 4],
+        };
+        let hyps = beam_search(
+            &mut joint,
+            6,
+            2,
+            &BeamSearchConfig::new(2),
+            Fusion::default(),
+            ConfidenceMeasure::MaxProb,
+            MAX_SYMBOLS_PER_STEP,
+        );
+        let best = &hyps.unwrap()[0];
+        assert_eq!(best.tokens, vec![0, 1]);
+        assert_eq!(best.frames, vec![0, 2]);
+        assert_eq!(best.durations, vec![1, 1]);
+    }
+
+    #[test]
+    fn test_beam_width_one_matches_greedy() {
+        let tables: [Table; 3] = [
+            // "a" lasts two frames, then "b"
+            |frame, last| match (frame, last) {
+                (0, 2) => ([0.9, 0.05, 0.05], [0.05, 0.05, 0.9]),
+                (2, 0) => ([0.05, 0.9, 0.05], [0.05, 0.9, 0.05]),
+                _ => ([0.05, 0.05, 0.9], [0.05, 0.9, 0.05]),
+            },
+            // "a" then "b" on the same frame (duration 0), blank jumps two frames
+            |frame, last| match (frame, last) {
+                (0, 2) | (4, 2) => ([0.9, 0.05, 0.05], [0.9, 0.05, 0.05]),
+                (0, 0) | (4, 0) => ([0.05, 0.9, 0.05], [0.05, 0.9, 0.05]),
+                _ => ([0.05, 0.05, 0.9], [0.9, 0.02, 0.08]),
+            },
+            // Blank everywhere
+            |_, _| ([0.05, 0.05, 0.9], [0.05, 0.9, 0.05]),
+        ];
+        for table in tables {
+            let bins = DEFAULT_DURATION_BINS.to_vec();
+            let greedy = greedy_search(
+                &mut MockJoint {
+                    table,
+                    bins: bins.clone(),
+                },
+                6,
+                2,
+                None,
+                ConfidenceMeasure::MaxProb,
+                MAX_SYMBOLS_PER_STEP,
+            )
+            .unwrap();
+            let beam = beam_search(
+                &mut MockJoint { table, bins },
+                6,
+                2,
+                &BeamSearchConfig::new(1),
+                Fusion::default(),
+                ConfidenceMeasure::MaxProb,
+                MAX_SYMBOLS_PER_STEP,
+            )
+            .unwrap();
+            assert_eq!(beam.len(), 1);
+            assert_eq!(greedy.tokens, beam[0].tokens);
+            assert_eq!(greedy.frames, beam[0].frames);
+            assert_eq!(greedy.durations, beam[0].durations);
+            assert_eq!(greedy.confidences, beam[0].confidences);
+        }
+    }
+
+    #[test]
+    fn test_confidence_ignores_token_controls() {
+        // A bias that makes token 0 certain must not make it look confident
+        let model = vec![0.2f32.ln(), 0.3f32.ln(), 0.5f32.ln()];
+        let out = JointOutput {
+            token_logits: vec![100.0, model[1], model[2]],
+            model_logits: Some(model.clone()),
+            duration_logits: Vec::new(),
+            state: (),
+        };
+        let measure = ConfidenceMeasure::MaxProb;
+        assert_eq!(out.confidence(measure), measure.compute(&model));
+        assert!(out.confidence(measure) < 0.5);
+    }
+
+    #[test]
+    fn test_greedy_symbol_limit() {
+        // "a" with duration 0 forever: three per frame, the third moving on
+        let mut joint = MockJoint {
+            table: |_, _| ([0.9, 0.05, 0.05], [0.9, 0.05, 0.05]),
+            bins: DEFAULT_DURATION_BINS.to_vec(),
+        };
+        let greedy = greedy_search(&mut joint, 2, 2, None, ConfidenceMeasure::MaxProb, 3).unwrap();
+        assert_eq!(greedy.frames, vec![0, 0, 0, 1, 1, 1]);
+    }
+}
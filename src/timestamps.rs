@@ -1,3 +1,4 @@
+use crate::confidence::{sentence_confidence, word_confidence};
 use crate::decoder::TimedToken;
 
 /// Timestamp output mode for transcription results
@@ -13,9 +14,10 @@ use crate::decoder::TimedToken;
 ///   alphabet without punctuation, so sentence segmentation is not possible.
 /// - **Parakeet TDT (Multilingual)**: Use `Sentences` mode. The TDT model predicts
 ///   punctuation, enabling natural sentence boundaries.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum TimestampMode {
     /// Raw token-level timestamps from the model
+    #[default]
     Tokens,
     /// Word-level timestamps (groups subword tokens)
     Words,
@@ -26,12 +28,6 @@ pub enum TimestampMode {
     Sentences,
 }
 
-impl Default for TimestampMode {
-    fn default() -> Self {
-        Self::Tokens
-    }
-}
-
 /// Convert token timestamps to the requested output mode
 ///
 /// Takes raw token-level timestamps from the model and optionally groups them
@@ -44,7 +40,8 @@ impl Default for TimestampMode {
 ///
 /// # Returns
 ///
-/// Vector of TimedToken with timestamps at the requested granularity
+/// Vector of TimedToken with timestamps at the requested granularity. A word's
+/// confidence is that of its least confident token, a sentence's the mean of its words.
 pub fn process_timestamps(tokens: &[TimedToken], mode: TimestampMode) -> Vec<TimedToken> {
     match mode {
         TimestampMode::Tokens => tokens.to_vec(),
@@ -62,6 +59,8 @@ fn group_by_words(tokens: &[TimedToken]) -> Vec<TimedToken> {
     let mut words = Vec::new();
     let mut current_word_text = String::new();
     let mut current_word_start = 0.0;
+    let mut current_word_channel = None;
+    let mut current_word_confidence = None;
     let mut last_word_lower = String::new();
 
     for (i, token) in tokens.iter().enumerate() {
@@ -75,6 +74,8 @@ fn group_by_words(tokens: &[TimedToken]) -> Vec<TimedToken> {
                         text: current_word_text.clone(),
                         start: current_word_start,
                         end: if i > 0 { tokens[i - 1].end } else { token.end },
+                        channel: current_word_channel,
+                        confidence: current_word_confidence,
                     });
                     last_word_lower = word_lower;
                 }
@@ -109,6 +110,8 @@ fn group_by_words(tokens: &[TimedToken]) -> Vec<TimedToken> {
                     text: current_word_text.clone(),
                     start: current_word_start,
                     end: tokens[i - 1].end,
+                    channel: current_word_channel,
+                    confidence: current_word_confidence,
                 });
                 last_word_lower = word_lower;
             }
@@ -118,7 +121,10 @@ fn group_by_words(tokens: &[TimedToken]) -> Vec<TimedToken> {
         // Start new word or append to current
         if current_word_text.is_empty() {
             current_word_start = token.start;
+            current_word_channel = token.channel;
+            current_word_confidence = None;
         }
+        current_word_confidence = word_confidence([current_word_confidence, token.confidence]);
 
         // Add token text, removing word boundary markers
         let token_text = token.text.trim_start_matches('▁').trim_start_matches(' ');
@@ -133,6 +139,8 @@ fn group_by_words(tokens: &[TimedToken]) -> Vec<TimedToken> {
                 text: current_word_text,
                 start: current_word_start,
                 end: tokens.last().unwrap().end,
+                channel: current_word_channel,
+                confidence: current_word_confidence,
             });
         }
     }
@@ -162,12 +170,16 @@ fn group_by_sentences(tokens: &[TimedToken]) -> Vec<TimedToken> {
             let sentence_text = format_sentence(&current_sentence);
             let start = current_sentence.first().unwrap().start;
             let end = current_sentence.last().unwrap().end;
+            let channel = current_sentence.first().unwrap().channel;
+            let confidence = sentence_confidence(current_sentence.iter().map(|w| w.confidence));
 
             if !sentence_text.is_empty() {
                 sentences.push(TimedToken {
                     text: sentence_text,
                     start,
                     end,
+                    channel,
+                    confidence,
                 });
             }
             current_sentence.clear();
@@ -179,12 +191,16 @@ fn group_by_sentences(tokens: &[TimedToken]) -> Vec<TimedToken> {
         let sentence_text = format_sentence(&current_sentence);
         let start = current_sentence.first().unwrap().start;
         let end = current_sentence.last().unwrap().end;
+        let channel = current_sentence.first().unwrap().channel;
+        let confidence = sentence_confidence(current_sentence.iter().map(|w| w.confidence));
 
         if !sentence_text.is_empty() {
             sentences.push(TimedToken {
                 text: sentence_text,
                 start,
                 end,
+                channel,
+                confidence,
             });
         }
     }
@@ -225,11 +241,13 @@ mod tests {
                 text: "▁Hello".to_string(),
                 start: 0.0,
                 end: 0.5,
+                ..Default::default()
             },
             TimedToken {
                 text: "▁world".to_string(),
                 start: 0.5,
                 end: 1.0,
+                ..Default::default()
             },
         ];
 
@@ -246,16 +264,19 @@ mod tests {
                 text: "▁twenty".to_string(),
                 start: 0.0,
                 end: 0.3,
+                ..Default::default()
             },
             TimedToken {
                 text: "-two".to_string(),
                 start: 0.3,
                 end: 0.6,
+                ..Default::default()
             },
             TimedToken {
                 text: "▁apples".to_string(),
                 start: 0.6,
                 end: 1.0,
+                ..Default::default()
             },
         ];
 
@@ -276,16 +297,19 @@ mod tests {
                 text: "▁Hello".to_string(),
                 start: 0.0,
                 end: 0.5,
+                ..Default::default()
             },
             TimedToken {
                 text: "▁world".to_string(),
                 start: 0.5,
                 end: 1.0,
+                ..Default::default()
             },
             TimedToken {
                 text: ".".to_string(),
                 start: 1.0,
                 end: 1.1,
+                ..Default::default()
             },
         ];
 
@@ -296,6 +320,28 @@ mod tests {
         assert_eq!(sentences[0].end, 1.1);
     }
 
+    #[test]
+    fn test_confidence_aggregation() {
+        let token = |text: &str, confidence| TimedToken {
+            text: text.to_string(),
+            confidence: Some(confidence),
+            ..Default::default()
+        };
+        let tokens = vec![
+            token("▁Hel", 0.9),
+            token("lo", 0.5),
+            token("▁world", 0.8),
+            token(".", 1.0),
+        ];
+
+        let words = group_by_words(&tokens);
+        let confidences: Vec<_> = words.iter().map(|w| w.confidence.unwrap()).collect();
+        assert_eq!(confidences, vec![0.5, 0.8, 1.0]);
+
+        let sentences = group_by_sentences(&tokens);
+        assert!((sentences[0].confidence.unwrap() - 2.3 / 3.0).abs() < 1e-6);
+    }
+
     #[test]
     fn test_repetition_preservation() {
         let words = vec![
@@ -303,16 +349,19 @@ mod tests {
                 text: "uh".to_string(),
                 start: 0.0,
                 end: 0.5,
+                ..Default::default()
             },
             TimedToken {
                 text: "uh".to_string(),
                 start: 0.5,
                 end: 1.0,
+                ..Default::default()
             },
             TimedToken {
                 text: "hello".to_string(),
                 start: 1.0,
                 end: 1.5,
+                ..Default::default()
             },
         ];
 
@@ -329,26 +378,31 @@ mod tests {
                 text: " like".to_string(),
                 start: 0.0,
                 end: 0.5,
+                ..Default::default()
             },
             TimedToken {
                 text: " ".to_string(), // Space-only token from ▁
                 start: 0.5,
                 end: 0.5,
+                ..Default::default()
             },
             TimedToken {
                 text: "1".to_string(),
                 start: 0.5,
                 end: 0.6,
+                ..Default::default()
             },
             TimedToken {
                 text: "0".to_string(),
                 start: 0.6,
                 end: 0.7,
+                ..Default::default()
             },
             TimedToken {
                 text: "0".to_string(),
                 start: 0.7,
                 end: 0.8,
+                ..Default::default()
             },
         ];
 
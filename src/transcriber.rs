@@ -1,9 +1,26 @@
-use crate::audio::load_audio;
+use crate::audio::{decode_raw_pcm, downmix, extract_channel, load_audio, RawAudioFormat};
 use crate::decoder::TranscriptionResult;
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::timestamps::TimestampMode;
+use crate::vad::SpeechSegment;
 use std::path::Path;
 
+/// How multichannel audio is turned into a transcript.
+///
+/// Stereo call recordings usually carry one party per channel; mixing them
+/// down loses who said what, so the channels can be transcribed separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChannelStrategy {
+    /// Average all channels into mono (default)
+    #[default]
+    Mix,
+    /// Transcribe only the given channel (0-based)
+    Select(usize),
+    /// Transcribe each channel on its own and merge the results into one timeline.
+    /// Every `TimedToken` carries its source channel in `channel`.
+    PerChannel,
+}
+
 /// Trait for common transcription functionality
 pub trait Transcriber {
     /// Transcribe audio samples.
@@ -18,6 +35,12 @@ pub trait Transcriber {
     /// # Returns
     ///
     /// A `TranscriptionResult` containing the transcribed text and timestamps at the requested level.
+    ///
+    /// # Errors
+    ///
+    /// Degenerate input is rejected before it reaches the model: [`Error::EmptyAudio`],
+    /// [`Error::NonFiniteAudio`], [`Error::AudioTooShort`] and [`Error::AudioTooLong`]
+    /// (which carries the model's maximum duration).
     fn transcribe_samples(
         &mut self,
         audio: Vec<f32>,
@@ -26,6 +49,138 @@ pub trait Transcriber {
         mode: Option<TimestampMode>,
     ) -> Result<TranscriptionResult>;
 
+    /// Transcribe a raw PCM byte payload described by out-of-band format metadata.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - Raw sample bytes (e.g. s16le, f32le, u8 or µ-law)
+    /// * `format` - Sample format, endianness, rate, channel count and layout of `bytes`
+    /// * `mode` - Optional timestamp output mode (Tokens, Words, or Sentences)
+    ///
+    /// # Returns
+    ///
+    /// A `TranscriptionResult`, or an error if `bytes` doesn't hold a whole number of frames.
+    fn transcribe_bytes(
+        &mut self,
+        bytes: &[u8],
+        format: &RawAudioFormat,
+        mode: Option<TimestampMode>,
+    ) -> Result<TranscriptionResult> {
+        let audio = decode_raw_pcm(bytes, format)?;
+        self.transcribe_samples(audio, format.sample_rate, format.channels, mode)
+    }
+
+    /// Transcribe interleaved multichannel audio with an explicit channel strategy.
+    ///
+    /// # Arguments
+    ///
+    /// * `audio` - Interleaved audio samples as f32 values
+    /// * `sample_rate` - Sample rate in Hz
+    /// * `channels` - Number of audio channels
+    /// * `strategy` - Mix, select one channel, or transcribe each channel separately
+    /// * `mode` - Optional timestamp output mode (Tokens, Words, or Sentences)
+    ///
+    /// # Returns
+    ///
+    /// With `PerChannel`, tokens from all channels are merged in start-time order and
+    /// `text` holds one `Channel N: ...` line per channel.
+    fn transcribe_samples_with_strategy(
+        &mut self,
+        audio: Vec<f32>,
+        sample_rate: u32,
+        channels: u16,
+        strategy: ChannelStrategy,
+        mode: Option<TimestampMode>,
+    ) -> Result<TranscriptionResult> {
+        match strategy {
+            ChannelStrategy::Mix => self.transcribe_samples(audio, sample_rate, channels, mode),
+            ChannelStrategy::Select(channel) => {
+                let mono = extract_channel(&audio, channels, channel)?;
+                let mut result = self.transcribe_samples(mono, sample_rate, 1, mode)?;
+                for token in &mut result.tokens {
+                    token.channel = Some(channel);
+                }
+                Ok(result)
+            }
+            ChannelStrategy::PerChannel => {
+                let mut results = Vec::with_capacity(channels as usize);
+                for channel in 0..channels as usize {
+                    let mono = extract_channel(&audio, channels, channel)?;
+                    results.push(self.transcribe_samples(mono, sample_rate, 1, mode)?);
+                }
+                Ok(merge_channel_results(results))
+            }
+        }
+    }
+
+    /// Transcribe only the speech regions of the audio, e.g. as found by a
+    /// [`VoiceActivityDetector`](crate::vad::VoiceActivityDetector).
+    ///
+    /// Each segment is transcribed on its own, so silence never reaches the encoder
+    /// and long recordings stay under the model's input length limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `audio` - Interleaved audio samples as f32 values
+    /// * `sample_rate` - Sample rate in Hz
+    /// * `channels` - Number of audio channels (mixed down before slicing)
+    /// * `segments` - Speech regions in seconds
+    /// * `mode` - Optional timestamp output mode (Tokens, Words, or Sentences)
+    ///
+    /// # Returns
+    ///
+    /// A `TranscriptionResult` with timestamps relative to the start of `audio`.
+    fn transcribe_speech_segments(
+        &mut self,
+        audio: Vec<f32>,
+        sample_rate: u32,
+        channels: u16,
+        segments: &[SpeechSegment],
+        mode: Option<TimestampMode>,
+    ) -> Result<TranscriptionResult> {
+        let mono = downmix(&audio, channels);
+        let mut texts = Vec::with_capacity(segments.len());
+        let mut tokens = Vec::new();
+        let mut hotwords = Vec::new();
+
+        for segment in segments {
+            let from = ((segment.start * sample_rate as f32) as usize).min(mono.len());
+            let to = ((segment.end * sample_rate as f32) as usize).clamp(from, mono.len());
+            if from == to {
+                continue;
+            }
+
+            let offset = from as f32 / sample_rate as f32;
+            let result =
+                match self.transcribe_samples(mono[from..to].to_vec(), sample_rate, 1, mode) {
+                    // A sliver shorter than one analysis window holds no words
+                    Err(Error::AudioTooShort { .. }) => continue,
+                    result => result?,
+                };
+            if !result.text.trim().is_empty() {
+                texts.push(result.text.trim().to_string());
+            }
+            tokens.extend(result.tokens.into_iter().map(|mut token| {
+                token.start += offset;
+                token.end += offset;
+                token
+            }));
+            hotwords.extend(result.hotwords.into_iter().map(|mut hit| {
+                hit.start += offset;
+                hit.end += offset;
+                hit
+            }));
+        }
+
+        Ok(TranscriptionResult {
+            text: texts.join(" "),
+            tokens,
+            hotwords,
+            n_best: Vec::new(),
+            confusion_network: None,
+        })
+    }
+
     /// Transcribe an audio file with timestamps
     ///
     /// # Arguments
@@ -47,6 +202,26 @@ pub trait Transcriber {
         self.transcribe_samples(audio, spec.sample_rate, spec.channels, mode)
     }
 
+    /// Transcribe an audio file with an explicit channel strategy.
+    ///
+    /// See [`Transcriber::transcribe_samples_with_strategy`].
+    fn transcribe_file_with_strategy<P: AsRef<Path>>(
+        &mut self,
+        audio_path: P,
+        strategy: ChannelStrategy,
+        mode: Option<TimestampMode>,
+    ) -> Result<TranscriptionResult> {
+        let (audio, spec) = load_audio(audio_path.as_ref())?;
+
+        self.transcribe_samples_with_strategy(
+            audio,
+            spec.sample_rate,
+            spec.channels,
+            strategy,
+            mode,
+        )
+    }
+
     /// Transcribes multiple audio files in batch.
     ///
     /// # Arguments
@@ -70,3 +245,93 @@ pub trait Transcriber {
         Ok(results)
     }
 }
+
+// Label each channel's tokens and interleave them by start time
+pub(crate) fn merge_channel_results(results: Vec<TranscriptionResult>) -> TranscriptionResult {
+    let mut tokens = Vec::new();
+    let mut hotwords = Vec::new();
+    let mut lines = Vec::new();
+
+    for (channel, result) in results.into_iter().enumerate() {
+        if !result.text.trim().is_empty() {
+            lines.push(format!("Channel {channel}: {}", result.text.trim()));
+        }
+        tokens.extend(result.tokens.into_iter().map(|mut token| {
+            token.channel = Some(channel);
+            token
+        }));
+        hotwords.extend(result.hotwords);
+    }
+
+    // Stable sort keeps each channel's token order for identical start times
+    tokens.sort_by(|a, b| a.start.total_cmp(&b.start));
+    hotwords.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+    TranscriptionResult {
+        text: lines.join("\n"),
+        tokens,
+        hotwords,
+        n_best: Vec::new(),
+        confusion_network: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::TimedToken;
+
+    fn result(text: &str, words: &[(&str, f32, f32)]) -> TranscriptionResult {
+        TranscriptionResult {
+            text: text.to_string(),
+            tokens: words
+                .iter()
+                .map(|&(text, start, end)| TimedToken {
+                    text: text.to_string(),
+                    start,
+                    end,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_merge_channel_results_interleaves_by_time() {
+        let agent = result(
+            "hello how can I help",
+            &[("hello", 0.0, 0.4), ("help", 2.0, 2.3)],
+        );
+        let customer = result("hi there", &[("hi", 0.8, 1.0), ("there", 1.0, 1.3)]);
+
+        let merged = merge_channel_results(vec![agent, customer]);
+
+        let order: Vec<(&str, Option<usize>)> = merged
+            .tokens
+            .iter()
+            .map(|t| (t.text.as_str(), t.channel))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                ("hello", Some(0)),
+                ("hi", Some(1)),
+                ("there", Some(1)),
+                ("help", Some(0)),
+            ]
+        );
+        assert_eq!(
+            merged.text,
+            "Channel 0: hello how can I help\nChannel 1: hi there"
+        );
+    }
+
+    #[test]
+    fn test_merge_skips_silent_channel_text() {
+        let merged =
+            merge_channel_results(vec![result("", &[]), result("yes", &[("yes", 0.1, 0.2)])]);
+        assert_eq!(merged.text, "Channel 1: yes");
+        assert_eq!(merged.tokens[0].channel, Some(1));
+    }
+}
@@ -0,0 +1,542 @@
+//! Voice activity detection
+//!
+//! Finds the speech regions of a recording so silence and music can be skipped
+//! before running the (much more expensive) ASR encoder or the diarization model.
+//!
+//! Two detectors are provided:
+//! - [`EnergyVad`]: frame energy against an adaptive noise floor. No model needed.
+//! - [`SileroVad`]: the Silero VAD v5 ONNX model, loaded with the usual `ExecutionConfig`.
+//!   Download: https://github.com/snakers4/silero-vad/tree/master/src/silero_vad/data
+//!
+//! Both return [`SpeechSegment`]s in seconds, which can be passed to
+//! `Transcriber::transcribe_speech_segments` or `Sortformer::diarize_speech_segments`.
+
+use crate::audio;
+use crate::error::{Error, Result};
+use crate::execution::ModelConfig as ExecutionConfig;
+use ndarray::{arr0, Array2, Array3};
+use ort::session::Session;
+use std::path::Path;
+
+/// A region of detected speech, in seconds from the start of the audio
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpeechSegment {
+    pub start: f32,
+    pub end: f32,
+}
+
+impl SpeechSegment {
+    pub fn duration(&self) -> f32 {
+        self.end - self.start
+    }
+}
+
+/// Common interface of the voice activity detectors
+pub trait VoiceActivityDetector {
+    /// Detect speech in mono audio.
+    ///
+    /// # Arguments
+    ///
+    /// * `audio` - Mono audio samples as f32 values
+    /// * `sample_rate` - Sample rate in Hz
+    ///
+    /// # Returns
+    ///
+    /// Speech segments sorted by start time, non-overlapping.
+    fn detect(&mut self, audio: &[f32], sample_rate: u32) -> Result<Vec<SpeechSegment>>;
+}
+
+/// Segment post-processing shared by all detectors.
+///
+/// # Parameters
+/// - `min_speech_duration`: Drop speech segments shorter than this (seconds)
+/// - `min_silence_duration`: Merge segments separated by a shorter gap (seconds)
+/// - `speech_pad`: Seconds added before and after each segment
+/// - `max_speech_duration`: Split longer segments into equal parts, e.g. to stay under
+///   a model's input length limit (`None` = never split)
+#[derive(Debug, Clone)]
+pub struct VadSegmentConfig {
+    pub min_speech_duration: f32,
+    pub min_silence_duration: f32,
+    pub speech_pad: f32,
+    pub max_speech_duration: Option<f32>,
+}
+
+impl Default for VadSegmentConfig {
+    fn default() -> Self {
+        Self {
+            min_speech_duration: 0.25,
+            min_silence_duration: 0.3,
+            speech_pad: 0.1,
+            max_speech_duration: None,
+        }
+    }
+}
+
+/// Energy detector configuration.
+///
+/// A frame counts as speech when its level is at least `margin_db` above the
+/// estimated noise floor and above the absolute `threshold_db` (dBFS). The floor
+/// estimate is capped at `max_noise_floor_db`, so audio that is nearly all speech
+/// does not raise it to the speech level.
+#[derive(Debug, Clone)]
+pub struct EnergyVadConfig {
+    pub frame_duration: f32,
+    pub threshold_db: f32,
+    pub margin_db: f32,
+    pub max_noise_floor_db: f32,
+    pub segments: VadSegmentConfig,
+}
+
+impl Default for EnergyVadConfig {
+    fn default() -> Self {
+        Self {
+            frame_duration: 0.03,
+            threshold_db: -50.0,
+            margin_db: 12.0,
+            max_noise_floor_db: -40.0,
+            segments: VadSegmentConfig::default(),
+        }
+    }
+}
+
+/// Energy-based voice activity detector
+#[derive(Debug, Clone, Default)]
+pub struct EnergyVad {
+    config: EnergyVadConfig,
+}
+
+impl EnergyVad {
+    pub fn new(config: EnergyVadConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn config(&self) -> &EnergyVadConfig {
+        &self.config
+    }
+}
+
+impl VoiceActivityDetector for EnergyVad {
+    fn detect(&mut self, audio: &[f32], sample_rate: u32) -> Result<Vec<SpeechSegment>> {
+        if sample_rate == 0 {
+            return Err(Error::Audio("Sample rate must be positive".to_string()));
+        }
+
+        let frame_len = ((self.config.frame_duration * sample_rate as f32) as usize).max(1);
+        let levels: Vec<f32> = audio
+            .chunks(frame_len)
+            .map(|frame| {
+                let power = frame.iter().map(|x| x * x).sum::<f32>() / frame.len() as f32;
+                10.0 * power.max(1e-12).log10()
+            })
+            .collect();
+        if levels.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Noise floor: 10th percentile of frame levels, which is speech itself when
+        // there are few pauses, hence the ceiling
+        let mut sorted = levels.clone();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let noise_floor = sorted[sorted.len() / 10].min(self.config.max_noise_floor_db);
+        let threshold = self
+            .config
+            .threshold_db
+            .max(noise_floor + self.config.margin_db);
+
+        let is_speech: Vec<bool> = levels.iter().map(|&db| db >= threshold).collect();
+        let frame_secs = frame_len as f32 / sample_rate as f32;
+        let total = audio.len() as f32 / sample_rate as f32;
+
+        Ok(finalize_segments(
+            frames_to_spans(&is_speech, frame_secs),
+            &self.config.segments,
+            total,
+        ))
+    }
+}
+
+/// Silero VAD configuration.
+///
+/// Speech starts when the speech probability exceeds `threshold` and ends once it
+/// drops below `threshold - 0.15`, mirroring the reference implementation.
+#[derive(Debug, Clone)]
+pub struct SileroVadConfig {
+    pub threshold: f32,
+    pub segments: VadSegmentConfig,
+}
+
+impl Default for SileroVadConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 0.5,
+            segments: VadSegmentConfig {
+                min_speech_duration: 0.25,
+                min_silence_duration: 0.1,
+                speech_pad: 0.03,
+                max_speech_duration: None,
+            },
+        }
+    }
+}
+
+// Silero v5 runs on 512-sample windows at 16kHz, with 64 samples of left context
+const SILERO_SAMPLE_RATE: u32 = 16000;
+const SILERO_WINDOW: usize = 512;
+const SILERO_CONTEXT: usize = 64;
+
+/// Silero VAD v5 (ONNX) voice activity detector
+pub struct SileroVad {
+    session: Session,
+    config: SileroVadConfig,
+}
+
+impl SileroVad {
+    /// Load the Silero VAD ONNX model.
+    ///
+    /// # Arguments
+    /// * `model_path` - Path to `silero_vad.onnx`
+    /// * `exec_config` - Optional execution configuration (defaults to CPU if None)
+    /// * `config` - Detection thresholds and segment post-processing
+    pub fn from_pretrained<P: AsRef<Path>>(
+        model_path: P,
+        exec_config: Option<ExecutionConfig>,
+        config: SileroVadConfig,
+    ) -> Result<Self> {
+        let model_path = model_path.as_ref();
+        if !model_path.is_file() {
+            return Err(Error::Config(format!(
+                "Silero VAD model not found: {}",
+                model_path.display()
+            )));
+        }
+
+        let session = exec_config
+            .unwrap_or_default()
+            .apply_to_session_builder(Session::builder()?)?
+            .commit_from_file(model_path)?;
+
+        Ok(Self { session, config })
+    }
+
+    /// Per-window speech probabilities (one per 32ms window of 16kHz audio)
+    pub fn speech_probabilities(&mut self, audio: &[f32], sample_rate: u32) -> Result<Vec<f32>> {
+        let audio = audio::resample(
+            audio,
+            sample_rate,
+            SILERO_SAMPLE_RATE,
+            audio::ResampleQuality::Fast,
+        )?;
+
+        let mut state = Array3::<f32>::zeros((2, 1, 128));
+        let mut context = vec![0.0f32; SILERO_CONTEXT];
+        let mut probs = Vec::with_capacity(audio.len() / SILERO_WINDOW + 1);
+
+        for window in audio.chunks(SILERO_WINDOW) {
+            let mut input = Vec::with_capacity(SILERO_CONTEXT + SILERO_WINDOW);
+            input.extend_from_slice(&context);
+            input.extend_from_slice(window);
+            input.resize(SILERO_CONTEXT + SILERO_WINDOW, 0.0);
+            context.copy_from_slice(&input[input.len() - SILERO_CONTEXT..]);
+
+            let input = Array2::from_shape_vec((1, input.len()), input)
+                .map_err(|e| Error::Model(format!("Failed to create VAD input: {e}")))?;
+
+            let outputs = self.session.run(ort::inputs!(
+                "input" => ort::value::Value::from_array(input)?,
+                "state" => ort::value::Value::from_array(state.clone())?,
+                "sr" => ort::value::Value::from_array(arr0(SILERO_SAMPLE_RATE as i64))?
+            ))?;
+
+            let (_, prob) = outputs["output"]
+                .try_extract_tensor::<f32>()
+                .map_err(|e| Error::Model(format!("Failed to extract VAD output: {e}")))?;
+            let (_, new_state) = outputs["stateN"]
+                .try_extract_tensor::<f32>()
+                .map_err(|e| Error::Model(format!("Failed to extract VAD state: {e}")))?;
+
+            probs.push(prob.first().copied().unwrap_or(0.0));
+            state = Array3::from_shape_vec((2, 1, 128), new_state.to_vec())
+                .map_err(|e| Error::Model(format!("Failed to reshape VAD state: {e}")))?;
+        }
+
+        Ok(probs)
+    }
+}
+
+impl VoiceActivityDetector for SileroVad {
+    fn detect(&mut self, audio: &[f32], sample_rate: u32) -> Result<Vec<SpeechSegment>> {
+        let probs = self.speech_probabilities(audio, sample_rate)?;
+
+        // Hysteresis between onset and offset thresholds
+        let onset = self.config.threshold;
+        let offset = (onset - 0.15).max(0.01);
+        let mut in_speech = false;
+        let is_speech: Vec<bool> = probs
+            .iter()
+            .map(|&p| {
+                in_speech = if in_speech { p >= offset } else { p >= onset };
+                in_speech
+            })
+            .collect();
+
+        let frame_secs = SILERO_WINDOW as f32 / SILERO_SAMPLE_RATE as f32;
+        let total = audio.len() as f32 / sample_rate.max(1) as f32;
+
+        Ok(finalize_segments(
+            frames_to_spans(&is_speech, frame_secs),
+            &self.config.segments,
+            total,
+        ))
+    }
+}
+
+// Runs of speech frames as (start, end) in seconds
+fn frames_to_spans(is_speech: &[bool], frame_secs: f32) -> Vec<SpeechSegment> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (i, &speech) in is_speech.iter().enumerate() {
+        match (speech, start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                spans.push(SpeechSegment {
+                    start: s as f32 * frame_secs,
+                    end: i as f32 * frame_secs,
+                });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        spans.push(SpeechSegment {
+            start: s as f32 * frame_secs,
+            end: is_speech.len() as f32 * frame_secs,
+        });
+    }
+    spans
+}
+
+/// Merge short gaps, drop short blips, pad, and split overlong segments
+pub(crate) fn finalize_segments(
+    spans: Vec<SpeechSegment>,
+    config: &VadSegmentConfig,
+    total_duration: f32,
+) -> Vec<SpeechSegment> {
+    let mut merged: Vec<SpeechSegment> = Vec::with_capacity(spans.len());
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if span.start - last.end < config.min_silence_duration => {
+                last.end = span.end
+            }
+            _ => merged.push(span),
+        }
+    }
+
+    let mut segments: Vec<SpeechSegment> = Vec::with_capacity(merged.len());
+    for seg in merged
+        .into_iter()
+        .filter(|s| s.duration() >= config.min_speech_duration)
+    {
+        let padded = SpeechSegment {
+            start: (seg.start - config.speech_pad).max(0.0),
+            end: (seg.end + config.speech_pad).min(total_duration),
+        };
+        // Padding may close the gap to the previous segment
+        match segments.last_mut() {
+            Some(last) if padded.start <= last.end => last.end = padded.end,
+            _ => segments.push(padded),
+        }
+    }
+
+    match config.max_speech_duration {
+        Some(max) if max > 0.0 => segments
+            .into_iter()
+            .flat_map(|seg| {
+                let parts = (seg.duration() / max).ceil().max(1.0) as usize;
+                let step = seg.duration() / parts as f32;
+                (0..parts).map(move |i| SpeechSegment {
+                    start: seg.start + i as f32 * step,
+                    end: if i + 1 == parts {
+                        seg.end
+                    } else {
+                        seg.start + (i + 1) as f32 * step
+                    },
+                })
+            })
+            .collect(),
+        _ => segments,
+    }
+}
+
+/// Speech-only audio with the bookkeeping needed to map times back to the original.
+///
+/// Useful for models that need one continuous stream (like diarization) but
+/// shouldn't see the silence between segments.
+#[derive(Debug, Clone)]
+pub struct CondensedAudio {
+    pub samples: Vec<f32>,
+    // (start in condensed audio, segment in original audio), in seconds
+    offsets: Vec<(f32, SpeechSegment)>,
+}
+
+impl CondensedAudio {
+    /// Concatenate the given segments of mono audio
+    pub fn new(audio: &[f32], sample_rate: u32, segments: &[SpeechSegment]) -> Self {
+        let rate = sample_rate as f32;
+        let mut samples = Vec::new();
+        let mut offsets = Vec::with_capacity(segments.len());
+
+        for seg in segments {
+            let from = ((seg.start * rate) as usize).min(audio.len());
+            let to = ((seg.end * rate) as usize).clamp(from, audio.len());
+            if from == to {
+                continue;
+            }
+            offsets.push((
+                samples.len() as f32 / rate,
+                SpeechSegment {
+                    start: from as f32 / rate,
+                    end: to as f32 / rate,
+                },
+            ));
+            samples.extend_from_slice(&audio[from..to]);
+        }
+
+        Self { samples, offsets }
+    }
+
+    /// Map a span of the condensed audio back to original time.
+    ///
+    /// A span that crosses a removed gap comes back as one piece per speech segment.
+    pub fn map_span(&self, start: f32, end: f32) -> Vec<SpeechSegment> {
+        let mut pieces = Vec::new();
+        for &(offset, seg) in &self.offsets {
+            let seg_end = offset + seg.duration();
+            let from = start.max(offset);
+            let to = end.min(seg_end);
+            if to > from {
+                pieces.push(SpeechSegment {
+                    start: seg.start + (from - offset),
+                    end: seg.start + (to - offset),
+                });
+            }
+        }
+        pieces
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    fn tone(secs: f32, rate: u32) -> Vec<f32> {
+        (0..(secs * rate as f32) as usize)
+            .map(|i| 0.3 * (2.0 * PI * 220.0 * i as f32 / rate as f32).sin())
+            .collect()
+    }
+
+    fn seg(start: f32, end: f32) -> SpeechSegment {
+        SpeechSegment { start, end }
+    }
+
+    fn noise(secs: f32, rate: u32) -> Vec<f32> {
+        // Deterministic low-level hiss
+        let mut x = 12345u32;
+        (0..(secs * rate as f32) as usize)
+            .map(|_| {
+                x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                ((x >> 16) as f32 / 32768.0 - 1.0) * 0.001
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_energy_vad_finds_tone_between_silence() {
+        let rate = 16000;
+        let mut audio = noise(1.0, rate);
+        audio.extend(tone(1.5, rate));
+        audio.extend(noise(1.0, rate));
+
+        let mut vad = EnergyVad::new(EnergyVadConfig {
+            segments: VadSegmentConfig {
+                speech_pad: 0.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let segments = vad.detect(&audio, rate).unwrap();
+
+        assert_eq!(segments.len(), 1);
+        assert!((segments[0].start - 1.0).abs() < 0.05, "{segments:?}");
+        assert!((segments[0].end - 2.5).abs() < 0.05, "{segments:?}");
+    }
+
+    #[test]
+    fn test_energy_vad_on_continuous_speech() {
+        // 96% of the audio is speech, so the 10th percentile lands on the tone
+        let rate = 16000;
+        let mut audio = noise(0.2, rate);
+        audio.extend(tone(4.8, rate));
+
+        let mut vad = EnergyVad::new(EnergyVadConfig {
+            segments: VadSegmentConfig {
+                speech_pad: 0.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let segments = vad.detect(&audio, rate).unwrap();
+
+        assert_eq!(segments.len(), 1, "{segments:?}");
+        assert!((segments[0].start - 0.2).abs() < 0.05, "{segments:?}");
+        assert!((segments[0].end - 5.0).abs() < 0.05, "{segments:?}");
+    }
+
+    #[test]
+    fn test_energy_vad_on_silence() {
+        let mut vad = EnergyVad::default();
+        assert!(vad.detect(&noise(2.0, 16000), 16000).unwrap().is_empty());
+        assert!(vad.detect(&[], 16000).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_finalize_merges_drops_pads_and_splits() {
+        let spans = vec![
+            seg(1.0, 2.0),
+            seg(2.1, 3.0),  // gap < min_silence: merged
+            seg(5.0, 5.1),  // too short: dropped
+            seg(7.0, 13.0), // split in two
+        ];
+        let config = VadSegmentConfig {
+            min_speech_duration: 0.25,
+            min_silence_duration: 0.3,
+            speech_pad: 0.5,
+            max_speech_duration: Some(4.0),
+        };
+
+        let segments = finalize_segments(spans, &config, 13.2);
+        assert_eq!(
+            segments,
+            vec![seg(0.5, 3.5), seg(6.5, 9.85), seg(9.85, 13.2),]
+        );
+    }
+
+    #[test]
+    fn test_condensed_audio_maps_back_to_original_time() {
+        let audio = vec![0.0f32; 100];
+        let segments = [seg(1.0, 3.0), seg(6.0, 7.0)];
+        let condensed = CondensedAudio::new(&audio, 10, &segments);
+        assert_eq!(condensed.samples.len(), 30);
+
+        // Fully inside the second segment
+        assert_eq!(condensed.map_span(2.2, 2.8), vec![seg(6.2, 6.8)]);
+
+        // Crosses the removed gap
+        let pieces = condensed.map_span(1.5, 2.5);
+        assert_eq!(pieces.len(), 2);
+        assert!((pieces[0].start - 2.5).abs() < 1e-6 && (pieces[0].end - 3.0).abs() < 1e-6);
+        assert!((pieces[1].start - 6.0).abs() < 1e-6 && (pieces[1].end - 6.5).abs() < 1e-6);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::error::{Error, Result};
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
@@ -8,6 +9,7 @@ use std::path::Path;
 pub struct Vocabulary {
     pub id_to_token: Vec<String>,
     pub _blank_id: usize,
+    token_to_id: HashMap<String, usize>,
 }
 
 impl Vocabulary {
@@ -48,10 +50,20 @@ impl Vocabulary {
             blank_id = id_to_token.len() - 1;
         }
 
-        Ok(Self {
+        Ok(Self::new(id_to_token, blank_id))
+    }
+
+    /// Build a vocabulary from tokens indexed by id
+    pub fn new(id_to_token: Vec<String>, blank_id: usize) -> Self {
+        let mut token_to_id = HashMap::with_capacity(id_to_token.len());
+        for (id, token) in id_to_token.iter().enumerate() {
+            token_to_id.entry(token.clone()).or_insert(id);
+        }
+        Self {
             id_to_token,
             _blank_id: blank_id,
-        })
+            token_to_id,
+        }
     }
 
     /// Get token by ID
@@ -59,6 +71,34 @@ impl Vocabulary {
         self.id_to_token.get(id).map(|s| s.as_str())
     }
 
+    /// Tokenize text by greedy longest match against the vocabulary, word by word
+    /// (`▁` marks a word start as in SentencePiece).
+    ///
+    /// Only an approximation of the SentencePiece model the vocabulary came from, but
+    /// enough for short phrases such as hotwords. Returns `None` if some part of the
+    /// text has no matching token.
+    pub fn encode(&self, text: &str) -> Option<Vec<u32>> {
+        let mut ids = Vec::new();
+        for word in text.split_whitespace() {
+            let word = format!("▁{word}");
+            let mut rest = word.as_str();
+            while !rest.is_empty() {
+                let (id, len) = rest
+                    .char_indices()
+                    .map(|(i, c)| i + c.len_utf8())
+                    .rev()
+                    .find_map(|len| self.token_id(&rest[..len]).map(|id| (id, len)))?;
+                ids.push(id as u32);
+                rest = &rest[len..];
+            }
+        }
+        Some(ids)
+    }
+
+    fn token_id(&self, token: &str) -> Option<usize> {
+        self.token_to_id.get(token).copied()
+    }
+
     /// Get vocabulary size (number of tokens)
     pub fn size(&self) -> usize {
         self.id_to_token.len()
@@ -0,0 +1,396 @@
+//! Minimal RIFF/WAVE reader.
+//!
+//! hound only reads plain PCM/float WAVs, but call-center and telephony audio commonly
+//! arrives as G.711 A-law/µ-law, 8-bit unsigned, 24-bit packed or WAVE_FORMAT_EXTENSIBLE
+//! files. This parser handles all of those and scales every integer width correctly.
+
+use crate::audio::AudioSpec;
+use crate::error::{Error, Result};
+
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+const WAVE_FORMAT_ALAW: u16 = 0x0006;
+const WAVE_FORMAT_MULAW: u16 = 0x0007;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Sample encoding declared in the `fmt ` chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Pcm,
+    Float,
+    ALaw,
+    MuLaw,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Format {
+    encoding: Encoding,
+    channels: u16,
+    sample_rate: u32,
+    // Bytes per sample in the data chunk (container size, may exceed valid bits)
+    container_bytes: usize,
+}
+
+/// Decode a complete WAV file into interleaved f32 samples in [-1.0, 1.0].
+pub(crate) fn decode_wav(bytes: &[u8]) -> Result<(Vec<f32>, AudioSpec)> {
+    if bytes.len() < 12
+        || !(&bytes[0..4] == b"RIFF" || &bytes[0..4] == b"RF64")
+        || &bytes[8..12] != b"WAVE"
+    {
+        return Err(Error::Audio("Not a RIFF/WAVE file".to_string()));
+    }
+
+    let mut format: Option<Format> = None;
+    let mut data: Option<&[u8]> = None;
+    // RF64 stores the real data size in the ds64 chunk
+    let mut rf64_data_size: Option<u64> = None;
+
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let id = &bytes[pos..pos + 4];
+        let declared = read_u32(bytes, pos + 4) as u64;
+        let body = pos + 8;
+
+        let size = match (id, rf64_data_size) {
+            (b"data", Some(size)) if declared == u32::MAX as u64 => size,
+            _ => declared,
+        };
+        // Streamed recordings often leave the size unset or too large; take what is there
+        let end = body.saturating_add(size as usize).min(bytes.len());
+        let chunk = &bytes[body..end];
+
+        match id {
+            b"ds64" if chunk.len() >= 16 => {
+                rf64_data_size = Some(read_u64(chunk, 8));
+            }
+            b"fmt " => format = Some(parse_fmt(chunk)?),
+            b"data" => {
+                data = Some(chunk);
+                break;
+            }
+            _ => {}
+        }
+
+        // Chunks are word aligned
+        pos = end + (size as usize & 1);
+    }
+
+    let format = format.ok_or_else(|| Error::Audio("WAV file has no fmt chunk".to_string()))?;
+    let data = data.ok_or_else(|| Error::Audio("WAV file has no data chunk".to_string()))?;
+
+    let width = format.container_bytes;
+    let frame_bytes = width * format.channels as usize;
+    // Drop a trailing partial frame instead of failing on truncated files
+    let usable = data.len() - data.len() % frame_bytes;
+    let data = &data[..usable];
+
+    let samples: Vec<f32> = match (format.encoding, width) {
+        (Encoding::Pcm, 1) => data.iter().map(|&b| (b as f32 - 128.0) / 128.0).collect(),
+        (Encoding::Pcm, 2) => data
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
+            .collect(),
+        (Encoding::Pcm, 3) => data
+            .chunks_exact(3)
+            .map(|b| i32::from_le_bytes([0, b[0], b[1], b[2]]) as f32 / 2_147_483_648.0)
+            .collect(),
+        (Encoding::Pcm, 4) => data
+            .chunks_exact(4)
+            .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0)
+            .collect(),
+        (Encoding::Float, 4) => data
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+        (Encoding::Float, 8) => data
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()) as f32)
+            .collect(),
+        (Encoding::ALaw, 1) => data.iter().map(|&b| alaw_to_f32(b)).collect(),
+        (Encoding::MuLaw, 1) => data.iter().map(|&b| mulaw_to_f32(b)).collect(),
+        (encoding, width) => {
+            return Err(Error::Audio(format!(
+                "Unsupported WAV encoding: {encoding:?} with {} bits per sample",
+                width * 8
+            )))
+        }
+    };
+
+    Ok((
+        samples,
+        AudioSpec {
+            sample_rate: format.sample_rate,
+            channels: format.channels,
+        },
+    ))
+}
+
+fn parse_fmt(chunk: &[u8]) -> Result<Format> {
+    if chunk.len() < 16 {
+        return Err(Error::Audio("WAV fmt chunk is too short".to_string()));
+    }
+
+    let mut tag = read_u16(chunk, 0);
+    let channels = read_u16(chunk, 2);
+    let sample_rate = read_u32(chunk, 4);
+    let block_align = read_u16(chunk, 12) as usize;
+    let bits_per_sample = read_u16(chunk, 14) as usize;
+
+    if tag == WAVE_FORMAT_EXTENSIBLE {
+        // cbSize(2) validBits(2) channelMask(4) then the SubFormat GUID,
+        // whose first two bytes are the actual format tag
+        if chunk.len() < 40 {
+            return Err(Error::Audio(
+                "WAVE_FORMAT_EXTENSIBLE fmt chunk is too short".to_string(),
+            ));
+        }
+        tag = read_u16(chunk, 24);
+    }
+
+    let encoding = match tag {
+        WAVE_FORMAT_PCM => Encoding::Pcm,
+        WAVE_FORMAT_IEEE_FLOAT => Encoding::Float,
+        WAVE_FORMAT_ALAW => Encoding::ALaw,
+        WAVE_FORMAT_MULAW => Encoding::MuLaw,
+        other => {
+            return Err(Error::Audio(format!(
+                "Unsupported WAV format tag 0x{other:04X}"
+            )))
+        }
+    };
+
+    if channels == 0 || sample_rate == 0 {
+        return Err(Error::Audio(format!(
+            "Invalid WAV format: {channels} channels at {sample_rate} Hz"
+        )));
+    }
+
+    // The block alignment is authoritative for the container size
+    // (e.g. 20- or 24-bit samples stored in 32-bit slots)
+    let container_bytes =
+        if block_align >= channels as usize && block_align.is_multiple_of(channels as usize) {
+            block_align / channels as usize
+        } else {
+            bits_per_sample.div_ceil(8)
+        };
+    if container_bytes == 0 {
+        return Err(Error::Audio(
+            "Invalid WAV format: zero-width samples".to_string(),
+        ));
+    }
+
+    Ok(Format {
+        encoding,
+        channels,
+        sample_rate,
+        container_bytes,
+    })
+}
+
+/// Decode one G.711 A-law byte (ITU-T G.711) to f32.
+pub(crate) fn alaw_to_f32(byte: u8) -> f32 {
+    let a = byte ^ 0x55;
+    let exponent = (a >> 4) & 0x07;
+    let mantissa = (a & 0x0F) as i32;
+    let magnitude = if exponent == 0 {
+        (mantissa << 4) + 8
+    } else {
+        ((mantissa << 4) + 0x108) << (exponent - 1)
+    };
+    // In A-law a set sign bit means positive
+    let sample = if a & 0x80 != 0 { magnitude } else { -magnitude };
+    sample as f32 / 32768.0
+}
+
+/// Decode one G.711 µ-law byte (ITU-T G.711) to f32.
+pub(crate) fn mulaw_to_f32(byte: u8) -> f32 {
+    let u = !byte;
+    let exponent = (u >> 4) & 0x07;
+    let mantissa = (u & 0x0F) as i32;
+    let magnitude = (((mantissa << 3) + 0x84) << exponent) - 0x84;
+    let sample = if u & 0x80 != 0 { -magnitude } else { magnitude };
+    sample as f32 / 32768.0
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Build a WAV file; `extensible` wraps the tag in a WAVE_FORMAT_EXTENSIBLE header
+    fn make_wav(
+        tag: u16,
+        channels: u16,
+        bits: u16,
+        container_bytes: u16,
+        extensible: bool,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let sample_rate = 8000u32;
+        let block_align = channels * container_bytes;
+
+        let mut fmt = Vec::new();
+        fmt.extend(
+            (if extensible {
+                WAVE_FORMAT_EXTENSIBLE
+            } else {
+                tag
+            })
+            .to_le_bytes(),
+        );
+        fmt.extend(channels.to_le_bytes());
+        fmt.extend(sample_rate.to_le_bytes());
+        fmt.extend((sample_rate * block_align as u32).to_le_bytes());
+        fmt.extend(block_align.to_le_bytes());
+        fmt.extend((container_bytes * 8).to_le_bytes());
+        if extensible {
+            fmt.extend(22u16.to_le_bytes());
+            fmt.extend(bits.to_le_bytes());
+            fmt.extend(0u32.to_le_bytes());
+            fmt.extend(tag.to_le_bytes());
+            fmt.extend([
+                0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
+            ]);
+        }
+
+        let mut wav = Vec::new();
+        wav.extend(b"RIFF");
+        wav.extend(((4 + 8 + fmt.len() + 8 + data.len()) as u32).to_le_bytes());
+        wav.extend(b"WAVE");
+        // An unrelated chunk before fmt must be skipped
+        wav.extend(b"LIST");
+        wav.extend(3u32.to_le_bytes());
+        wav.extend([1, 2, 3, 0]);
+        wav.extend(b"fmt ");
+        wav.extend((fmt.len() as u32).to_le_bytes());
+        wav.extend(fmt);
+        wav.extend(b"data");
+        wav.extend((data.len() as u32).to_le_bytes());
+        wav.extend(data);
+        wav
+    }
+
+    fn decode(wav: &[u8]) -> Vec<f32> {
+        decode_wav(wav).unwrap().0
+    }
+
+    #[test]
+    fn test_pcm_u8() {
+        let wav = make_wav(WAVE_FORMAT_PCM, 1, 8, 1, false, &[128, 255, 0, 192]);
+        assert_eq!(decode(&wav), vec![0.0, 127.0 / 128.0, -1.0, 0.5]);
+    }
+
+    #[test]
+    fn test_pcm_s16() {
+        let data: Vec<u8> = [0i16, 16384, -32768, 32767]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let wav = make_wav(WAVE_FORMAT_PCM, 2, 16, 2, false, &data);
+        let (samples, spec) = decode_wav(&wav).unwrap();
+        assert_eq!(samples, vec![0.0, 0.5, -1.0, 32767.0 / 32768.0]);
+        assert_eq!(spec.channels, 2);
+        assert_eq!(spec.sample_rate, 8000);
+    }
+
+    #[test]
+    fn test_pcm_s24_packed() {
+        // 0x400000 = 0.5, 0xC00000 = -0.5, 0x800000 = -1.0
+        let data = [0x00, 0x00, 0x40, 0x00, 0x00, 0xC0, 0x00, 0x00, 0x80];
+        let wav = make_wav(WAVE_FORMAT_PCM, 1, 24, 3, false, &data);
+        assert_eq!(decode(&wav), vec![0.5, -0.5, -1.0]);
+    }
+
+    #[test]
+    fn test_pcm_s32() {
+        let data: Vec<u8> = [0i32, 1 << 30, i32::MIN]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let wav = make_wav(WAVE_FORMAT_PCM, 1, 32, 4, false, &data);
+        assert_eq!(decode(&wav), vec![0.0, 0.5, -1.0]);
+    }
+
+    #[test]
+    fn test_extensible_24_in_32() {
+        // 24 valid bits left-justified in 32-bit containers
+        let data: Vec<u8> = [0x4000_0000i32, -0x4000_0000]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let wav = make_wav(WAVE_FORMAT_PCM, 1, 24, 4, true, &data);
+        assert_eq!(decode(&wav), vec![0.5, -0.5]);
+    }
+
+    #[test]
+    fn test_float32_and_float64() {
+        let data: Vec<u8> = [0.25f32, -0.75]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let wav = make_wav(WAVE_FORMAT_IEEE_FLOAT, 1, 32, 4, false, &data);
+        assert_eq!(decode(&wav), vec![0.25, -0.75]);
+
+        let data: Vec<u8> = [0.25f64, -0.75]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let wav = make_wav(WAVE_FORMAT_IEEE_FLOAT, 1, 64, 8, true, &data);
+        assert_eq!(decode(&wav), vec![0.25, -0.75]);
+    }
+
+    #[test]
+    fn test_alaw() {
+        // Reference values from the ITU-T G.711 tables
+        let wav = make_wav(WAVE_FORMAT_ALAW, 1, 8, 1, false, &[0xD5, 0x55, 0xAA, 0x2A]);
+        let expected: Vec<f32> = [8, -8, 32256, -32256]
+            .iter()
+            .map(|&s| s as f32 / 32768.0)
+            .collect();
+        assert_eq!(decode(&wav), expected);
+    }
+
+    #[test]
+    fn test_mulaw() {
+        let wav = make_wav(WAVE_FORMAT_MULAW, 1, 8, 1, false, &[0xFF, 0x7F, 0x80, 0x00]);
+        let expected: Vec<f32> = [0, 0, 32124, -32124]
+            .iter()
+            .map(|&s| s as f32 / 32768.0)
+            .collect();
+        assert_eq!(decode(&wav), expected);
+    }
+
+    #[test]
+    fn test_truncated_data_chunk() {
+        let mut wav = make_wav(WAVE_FORMAT_PCM, 1, 16, 2, false, &[0, 0x40, 0, 0xC0]);
+        // Declared size larger than the file plus a dangling odd byte
+        let data_size_at = wav.len() - 4 - 4;
+        wav[data_size_at..data_size_at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        wav.push(0x12);
+        assert_eq!(decode(&wav), vec![0.5, -0.5]);
+    }
+
+    #[test]
+    fn test_unsupported_format_tag() {
+        // 0x0002 is MS ADPCM
+        let wav = make_wav(0x0002, 1, 4, 1, false, &[0; 4]);
+        assert!(decode_wav(&wav).is_err());
+    }
+    #[test]
+    fn test_zero_width_samples() {
+        // block_align = 0 and bits_per_sample = 0
+        let wav = make_wav(WAVE_FORMAT_PCM, 1, 0, 0, false, &[0; 4]);
+        assert!(matches!(decode_wav(&wav), Err(Error::Audio(_))));
+    }
+}